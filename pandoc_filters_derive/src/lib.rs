@@ -0,0 +1,219 @@
+//! `#[derive(Walkable)]` for types that hold pandoc nodes.
+//!
+//! The derived impls pass every `T` in the fields on to the field's own
+//! impls, so a type wrapping `Block`s and `Inline`s can be walked, walked
+//! in place, walked with context and queried like the types in
+//! `pandoc_filters::types`:
+//!
+//! ```ignore
+//! #[derive(Walkable)]
+//! struct Chapter {
+//!     meta: Meta,
+//!     body: Vec<Block>,
+//!     #[walkable(skip)]
+//!     number: u32
+//! }
+//! ```
+//!
+//! Fields marked `#[walkable(skip)]` are left as they are; every other
+//! field must implement the trait being derived.
+
+extern crate proc_macro;
+extern crate syn;
+#[macro_use]
+extern crate quote;
+
+use proc_macro::TokenStream;
+use syn::{DeriveInput, Body, VariantData, Field, Ident, MetaItem, NestedMetaItem};
+use quote::Tokens;
+
+/// Derives `Walkable<T>`, `WalkableMut<T>`, `Queryable<T>` and
+/// `ContextWalkable<T>` for every `T` that all the walked fields support.
+#[proc_macro_derive(Walkable, attributes(walkable))]
+pub fn derive_walkable(input: TokenStream) -> TokenStream {
+    let source = input.to_string();
+    let ast = syn::parse_derive_input(&source).unwrap();
+    let expanded = expand(&ast);
+    expanded.to_string().parse().unwrap()
+}
+
+fn expand(ast: &DeriveInput) -> Tokens {
+    let variants: Vec<Variant> = match ast.body {
+        Body::Struct(ref data) => vec![Variant::new(None, &ast.ident, data)],
+        Body::Enum(ref variants) => variants.iter()
+            .map(|v| Variant::new(Some(&ast.ident), &v.ident, &v.data))
+            .collect()
+    };
+    let walk = impl_trait(ast, &variants, quote!(::pandoc_filters::walk::Walkable<__T>), |variants| {
+        let arms = variants.iter().map(|v| {
+            let pattern = v.pattern(quote!(), true);
+            let rebuilt = v.rebuild(|name| quote!(#name.walk_with(v)));
+            quote!(#pattern => #rebuilt)
+        });
+        quote! {
+            fn walk_with<__V>(self, v: &__V) -> Self
+                where __V : ::pandoc_filters::walk::Traversal<__T> {
+                match self {
+                    #(#arms),*
+                }
+            }
+        }
+    });
+    let walk_mut = impl_trait(ast, &variants, quote!(::pandoc_filters::walk::WalkableMut<__T>), |variants| {
+        let arms = variants.iter().map(|v| {
+            let pattern = v.pattern(quote!(ref mut), false);
+            let calls = v.walked().map(|name| quote!(#name.walk_mut_with(v);));
+            quote!(#pattern => { #(#calls)* })
+        });
+        quote! {
+            fn walk_mut_with<__V>(&mut self, v: &mut __V)
+                where __V : ::pandoc_filters::walk::TraversalMut<__T> {
+                match *self {
+                    #(#arms),*
+                }
+            }
+        }
+    });
+    let query = impl_trait(ast, &variants, quote!(::pandoc_filters::query::Queryable<__T>), |variants| {
+        let arms = variants.iter().map(|v| {
+            let pattern = v.pattern(quote!(ref), false);
+            let calls = v.walked().map(|name| quote!(.append(#name.query(f))));
+            quote!(#pattern => <__R as ::pandoc_filters::query::Monoid>::empty() #(#calls)*)
+        });
+        quote! {
+            fn query<__R, __F>(&self, f: &__F) -> __R
+                where __F : Fn(&__T) -> __R, __R : ::pandoc_filters::query::Monoid {
+                match *self {
+                    #(#arms),*
+                }
+            }
+        }
+    });
+    let context = impl_trait(ast, &variants, quote!(::pandoc_filters::context::ContextWalkable<__T>), |variants| {
+        let arms = variants.iter().map(|v| {
+            let pattern = v.pattern(quote!(), true);
+            let rebuilt = v.rebuild(|name| quote!(#name.walk_in(v, at, section)));
+            quote!(#pattern => #rebuilt)
+        });
+        quote! {
+            fn walk_in<__V>(self, v: &__V, at: ::pandoc_filters::context::Position,
+                            section: &mut Option<::std::rc::Rc<::pandoc_filters::context::Section>>) -> Self
+                where __V : ::pandoc_filters::context::ContextTraversal<__T> {
+                match self {
+                    #(#arms),*
+                }
+            }
+        }
+    });
+    quote! {
+        #walk
+        #walk_mut
+        #query
+        #context
+    }
+}
+
+/// `impl<__T, ..> Trait<__T> for Type<..> where Field : Trait<__T>, .. { body }`.
+fn impl_trait<F>(ast: &DeriveInput, variants: &[Variant], trait_: Tokens, body: F) -> Tokens
+    where F : Fn(&[Variant]) -> Tokens {
+    let name = &ast.ident;
+    let mut generics = ast.generics.clone();
+    generics.ty_params.push(syn::TyParam {
+        attrs: vec![],
+        ident: Ident::new("__T"),
+        bounds: vec![],
+        default: None
+    });
+    for variant in variants {
+        for field in variant.fields.iter().filter(|f| !skipped(f)) {
+            let ty = &field.ty;
+            let predicate = quote!(where #ty : #trait_).to_string();
+            let mut clause = syn::parse_where_clause(&predicate).unwrap();
+            generics.where_clause.predicates.append(&mut clause.predicates);
+        }
+    }
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    let (_, ty_generics, _) = ast.generics.split_for_impl();
+    let body = body(variants);
+    quote! {
+        impl #impl_generics #trait_ for #name #ty_generics #where_clause {
+            #body
+        }
+    }
+}
+
+/// A struct, or one variant of an enum, with names bound to its fields.
+struct Variant<'a> {
+    path: Tokens,
+    data: &'a VariantData,
+    fields: &'a [Field],
+    names: Vec<Ident>
+}
+
+impl<'a> Variant<'a> {
+    fn new(enum_name: Option<&Ident>, name: &Ident, data: &'a VariantData) -> Variant<'a> {
+        let path = match enum_name {
+            Some(enum_name) => quote!(#enum_name::#name),
+            None => quote!(#name)
+        };
+        let fields = data.fields();
+        let names = (0..fields.len()).map(|i| Ident::new(format!("__field{}", i))).collect();
+        Variant { path, data, fields, names }
+    }
+
+    /// The names bound to the fields that are walked.
+    fn walked<'b>(&'b self) -> Box<dyn Iterator<Item = &'b Ident> + 'b> {
+        Box::new(self.fields.iter().zip(&self.names).filter(|&(f, _)| !skipped(f)).map(|(_, n)| n))
+    }
+
+    /// A pattern binding the fields with `mode` (`ref`, `ref mut` or
+    /// nothing), and the skipped ones to `_` unless `all`.
+    fn pattern(&self, mode: Tokens, all: bool) -> Tokens {
+        let path = &self.path;
+        let bindings: Vec<Tokens> = self.fields.iter().zip(&self.names)
+            .map(|(f, n)| if all || !skipped(f) { quote!(#mode #n) } else { quote!(_) })
+            .collect();
+        match *self.data {
+            VariantData::Struct(_) => {
+                let idents = self.fields.iter().map(|f| f.ident.as_ref().unwrap());
+                quote!(#path { #(#idents: #bindings),* })
+            },
+            VariantData::Tuple(_) => quote!(#path(#(#bindings),*)),
+            VariantData::Unit => quote!(#path)
+        }
+    }
+
+    /// The variant built again from its fields, the walked ones through `walk`.
+    fn rebuild<F>(&self, walk: F) -> Tokens
+        where F : Fn(&Ident) -> Tokens {
+        let path = &self.path;
+        let values: Vec<Tokens> = self.fields.iter().zip(&self.names)
+            .map(|(f, n)| if skipped(f) { quote!(#n) } else { walk(n) })
+            .collect();
+        match *self.data {
+            VariantData::Struct(_) => {
+                let idents = self.fields.iter().map(|f| f.ident.as_ref().unwrap());
+                quote!(#path { #(#idents: #values),* })
+            },
+            VariantData::Tuple(_) => quote!(#path(#(#values),*)),
+            VariantData::Unit => quote!(#path)
+        }
+    }
+}
+
+/// Whether the field is marked `#[walkable(skip)]`.
+fn skipped(field: &Field) -> bool {
+    let mut skip = false;
+    for attr in field.attrs.iter().filter(|a| a.name() == "walkable") {
+        match attr.value {
+            MetaItem::List(_, ref items) if !items.is_empty() => for item in items {
+                match *item {
+                    NestedMetaItem::MetaItem(MetaItem::Word(ref word)) if word == "skip" => skip = true,
+                    _ => panic!("unknown walkable attribute `{}`", quote!(#item))
+                }
+            },
+            _ => panic!("expected `#[walkable(skip)]`, found `{}`", quote!(#attr))
+        }
+    }
+    skip
+}
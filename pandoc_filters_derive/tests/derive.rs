@@ -0,0 +1,99 @@
+extern crate pandoc_filters;
+#[macro_use]
+extern crate pandoc_filters_derive;
+
+use std::collections::BTreeMap;
+use pandoc_filters::types::*;
+use pandoc_filters::walk::{Walkable, WalkableMut};
+use pandoc_filters::query::Queryable;
+use pandoc_filters::selector::Selector;
+
+#[derive(Walkable, Clone, PartialEq, Debug)]
+struct Chapter {
+    meta: Meta,
+    body: Vec<Block>,
+    #[walkable(skip)]
+    number: u32
+}
+
+#[derive(Walkable, Clone, PartialEq, Debug)]
+enum Part {
+    Chapter(Chapter),
+    Interlude { title: Vec<Inline>, body: Vec<Block> },
+    Break
+}
+
+#[derive(Walkable, PartialEq, Debug)]
+struct Numbered<T>(#[walkable(skip)] u32, T);
+
+fn str(s: &str) -> Inline {
+    Inline::Str(String::from(s))
+}
+
+fn chapter() -> Chapter {
+    let mut un_meta = BTreeMap::new();
+    un_meta.insert(String::from("title"), MetaValue::MetaInlines(vec![str("one")]));
+    Chapter { meta: Meta { un_meta },
+              body: vec![Block::Para(vec![str("two"), Inline::Emph(vec![str("three")])])],
+              number: 1 }
+}
+
+fn strings<T: Queryable<Inline>>(x: &T) -> Vec<String> {
+    x.query(&|i: &Inline| match *i {
+        Inline::Str(ref s) => vec![s.clone()],
+        _ => vec![]
+    })
+}
+
+fn upper(i: Inline) -> Inline {
+    match i {
+        Inline::Str(s) => Inline::Str(s.to_uppercase()),
+        i => i
+    }
+}
+
+#[test]
+fn struct_fields() {
+    let walked = chapter().walk(&upper);
+    assert_eq!(strings(&walked), vec!["ONE", "TWO", "THREE"]);
+    assert_eq!(walked.number, 1);
+
+    let mut blocks = 0;
+    chapter().walk_mut(&mut |_: &mut Block| blocks += 1);
+    assert_eq!(blocks, 1);
+}
+
+#[test]
+fn enum_variants() {
+    let parts = vec![Part::Chapter(chapter()),
+                     Part::Interlude { title: vec![str("four")], body: vec![Block::HorizontalRule] },
+                     Part::Break];
+    assert_eq!(strings(&parts), vec!["one", "two", "three", "four"]);
+
+    let mut walked = parts.walk(&upper);
+    walked.walk_mut(&mut |b: &mut Block| if *b == Block::HorizontalRule { *b = Block::Null });
+    match walked[1] {
+        Part::Interlude { ref title, ref body } => {
+            assert_eq!(*title, vec![str("FOUR")]);
+            assert_eq!(*body, vec![Block::Null]);
+        },
+        _ => panic!()
+    }
+}
+
+#[test]
+fn generic_fields() {
+    let numbered = Numbered(2, vec![str("five")]).walk(&upper);
+    assert_eq!(numbered, Numbered(2, vec![str("FIVE")]));
+}
+
+#[test]
+fn selector_on_derived() {
+    let parts = vec![Part::Chapter(chapter()),
+                     Part::Interlude { title: vec![str("four")], body: vec![] }];
+    let emph = Selector::parse("Emph Str").unwrap();
+    let selected: Vec<Inline> = emph.select(&parts);
+    assert_eq!(selected, vec![str("three")]);
+    let walked = emph.walk(parts, upper);
+    assert_eq!(strings(&walked), vec!["one", "two", "THREE", "four"]);
+}
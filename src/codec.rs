@@ -0,0 +1,385 @@
+//! Conversion between the JSON layouts of the pandoc API versions we support.
+//!
+//! Documents are always read into the latest `types` definitions. The only
+//! node whose layout changed in an incompatible way is `Table`, and its
+//! deserializer accepts the old layout through `table_from_legacy`, so reading
+//! needs no extra pass. `downgrade` rewrites a serialized document into the
+//! shape expected by an older pandoc; it works on pandoc's `{"t": ..., "c": ...}`
+//! JSON and is only needed when writing for a version before `LATEST`.
+//!
+//! Downgrading is lossy where the older API cannot express a node:
+//!
+//! * before 1.23, `Figure` becomes a `Div` with the class `figure`, holding
+//!   the figure content followed by the caption blocks;
+//! * before 1.22, nothing changes, but from 1.22 on `Null` blocks are dropped
+//!   since pandoc no longer accepts them;
+//! * before 1.21, `Underline` becomes a `Span` with the class `underline`, and
+//!   tables lose their attributes, short caption, foot and cell spans: the
+//!   caption blocks are flattened into inlines, every head, body and foot row
+//!   becomes a plain row, columns covered by a row or column span get empty
+//!   cells, and cells past the last column are dropped;
+//! * in the legacy format (before 1.17), `LineBlock` becomes a `Para` with a
+//!   `LineBreak` between the lines.
+
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use types::{ApiVersion, Attr, Block, Inline, Alignment, ColWidth, Caption, TableHead, TableBody,
+            TableFoot, Row, Cell, RowHeadColumns, RowSpan, ColSpan};
+
+/// The API version `types` is modelled after.
+pub const LATEST: &'static [u64] = &[1, 23, 1];
+
+/// Whether documents at `version` can be read and written. `None` is the
+/// legacy format used before the API version was part of the JSON.
+pub fn is_supported(version: &Option<ApiVersion>) -> bool {
+    match *version {
+        None => true,
+        Some(ref v) => &v[..] >= &[1, 17][..] && &v[..] < &[1, 24][..]
+    }
+}
+
+/// Whether documents written for `version` need to go through `downgrade`.
+/// Documents at a later version only do if they contain `Null` blocks, see
+/// `drops_null`.
+pub fn needs_downgrade(version: &Option<ApiVersion>) -> bool {
+    before(version, 23)
+}
+
+/// Whether `downgrade` drops `Null` blocks when writing for `version`.
+pub fn drops_null(version: &Option<ApiVersion>) -> bool {
+    !before(version, 22)
+}
+
+/// Rewrites a document in the layout of `LATEST` into the layout of `to`.
+pub fn downgrade(value: Value, to: &Option<ApiVersion>) -> Value {
+    let figures = before(to, 23);
+    let before_1_21 = before(to, 21);
+    let line_blocks = to.is_none();
+    let drop_null = drops_null(to);
+    map_nodes(value, drop_null, &|tag, content| {
+        match &tag[..] {
+            "Figure" if figures => downgrade_figure(content),
+            "Underline" if before_1_21 => {
+                let attr = attr_with_class(null_attr(), "underline");
+                node("Span", Value::Array(vec![attr, content]))
+            },
+            "Table" if before_1_21 => downgrade_table(content),
+            "LineBlock" if line_blocks => downgrade_line_block(content),
+            _ => node(&tag, content)
+        }
+    })
+}
+
+fn before(version: &Option<ApiVersion>, minor: u64) -> bool {
+    match *version {
+        None => true,
+        Some(ref v) => &v[..] < &[1, minor][..]
+    }
+}
+
+/// Rebuilds `value` bottom-up, passing the tag and content of every pandoc
+/// node to `f`. With `drop_null`, `Null` nodes are removed from their arrays.
+fn map_nodes<F>(value: Value, drop_null: bool, f: &F) -> Value
+    where F: Fn(String, Value) -> Value {
+    match value {
+        Value::Object(mut obj) => {
+            let tag = obj.get("t").and_then(|t| t.as_str()).map(String::from);
+            match tag {
+                Some(tag) => {
+                    let content = obj.remove("c").unwrap_or(Value::Array(Vec::new()));
+                    f(tag, map_nodes(content, drop_null, f))
+                },
+                None => Value::Object(obj.into_iter()
+                                      .map(|(k, v)| (k, map_nodes(v, drop_null, f)))
+                                      .collect())
+            }
+        },
+        Value::Array(arr) => Value::Array(arr.into_iter()
+                                          .filter(|v| !(drop_null && tag_of(v) == Some("Null")))
+                                          .map(|v| map_nodes(v, drop_null, f))
+                                          .collect()),
+        e => e
+    }
+}
+
+fn node(tag: &str, content: Value) -> Value {
+    let mut obj = BTreeMap::new();
+    obj.insert(String::from("t"), Value::String(String::from(tag)));
+    obj.insert(String::from("c"), content);
+    Value::Object(obj)
+}
+
+fn tag_of(value: &Value) -> Option<&str> {
+    value.as_object().and_then(|obj| obj.get("t")).and_then(|t| t.as_str())
+}
+
+fn null_attr() -> Value {
+    Value::Array(vec![Value::String(String::new()),
+                      Value::Array(Vec::new()),
+                      Value::Array(Vec::new())])
+}
+
+fn attr_with_class(attr: Value, class: &str) -> Value {
+    match attr {
+        Value::Array(mut parts) => {
+            if let Some(&mut Value::Array(ref mut classes)) = parts.get_mut(1) {
+                classes.push(Value::String(String::from(class)));
+            }
+            Value::Array(parts)
+        },
+        e => e
+    }
+}
+
+fn into_array(value: Value) -> Vec<Value> {
+    match value {
+        Value::Array(arr) => arr,
+        _ => Vec::new()
+    }
+}
+
+fn empty_attr() -> Attr {
+    (String::new(), Vec::new(), Vec::new())
+}
+
+fn legacy_row(cells: Vec<Vec<Block>>) -> Row {
+    Row(empty_attr(), cells.into_iter().map(|blocks| {
+        Cell(empty_attr(), Alignment::AlignDefault, RowSpan(1), ColSpan(1), blocks)
+    }).collect())
+}
+
+/// Builds a table from the fields of the `Table` constructor used before
+/// pandoc-types 1.21. A width of 0 means the column has no explicit width,
+/// and a header made only of empty cells means the table has no head.
+pub fn table_from_legacy(caption: Vec<Inline>, aligns: Vec<Alignment>, widths: Vec<f64>,
+                         headers: Vec<Vec<Block>>, rows: Vec<Vec<Vec<Block>>>) -> Block {
+    let caption_blocks = if caption.is_empty() {
+        Vec::new()
+    } else {
+        vec![Block::Plain(caption)]
+    };
+    let col_specs = aligns.into_iter().zip(widths.into_iter()).map(|(align, width)| {
+        if width > 0.0 {
+            (align, ColWidth::ColWidth(width))
+        } else {
+            (align, ColWidth::ColWidthDefault)
+        }
+    }).collect();
+    let head_rows = if headers.iter().all(|cell| cell.is_empty()) {
+        Vec::new()
+    } else {
+        vec![legacy_row(headers)]
+    };
+    let body = TableBody(empty_attr(), RowHeadColumns(0), Vec::new(),
+                         rows.into_iter().map(legacy_row).collect());
+    Block::Table(empty_attr(),
+                 Caption(None, caption_blocks),
+                 col_specs,
+                 TableHead(empty_attr(), head_rows),
+                 vec![body],
+                 TableFoot(empty_attr(), Vec::new()))
+}
+
+// The blocks of every cell in the rows of a table section, `columns` to a
+// row: columns covered by a column span, or by a row span from an earlier
+// row of the section, get empty cells, and cells past the last column are
+// dropped.
+fn section_cells(rows: Vec<Value>, columns: usize) -> Vec<Value> {
+    // How many more rows each column is covered for by a row span.
+    let mut covered = vec![0; columns];
+    rows.into_iter().map(|row| {
+        let mut cells = Vec::new();
+        for cell in into_array(into_array(row).into_iter().nth(1).unwrap_or(Value::Null)) {
+            skip_covered(&mut cells, &mut covered);
+            let parts = into_array(cell);
+            let row_span = parts.get(2).and_then(|s| s.as_u64()).unwrap_or(1);
+            let col_span = parts.get(3).and_then(|s| s.as_u64()).unwrap_or(1);
+            let start = cells.len();
+            cells.push(parts.into_iter().nth(4).unwrap_or(Value::Array(Vec::new())));
+            for _ in 1..col_span {
+                cells.push(Value::Array(Vec::new()));
+            }
+            for rows_left in covered.iter_mut().take(cells.len()).skip(start) {
+                *rows_left = row_span.saturating_sub(1);
+            }
+        }
+        skip_covered(&mut cells, &mut covered);
+        while cells.len() < columns {
+            cells.push(Value::Array(Vec::new()));
+            skip_covered(&mut cells, &mut covered);
+        }
+        cells.truncate(columns);
+        Value::Array(cells)
+    }).collect()
+}
+
+// Adds an empty cell for each column from the next one on that is covered by
+// a row span.
+fn skip_covered(cells: &mut Vec<Value>, covered: &mut [u64]) {
+    while cells.len() < covered.len() && covered[cells.len()] > 0 {
+        covered[cells.len()] -= 1;
+        cells.push(Value::Array(Vec::new()));
+    }
+}
+
+fn caption_inlines(blocks: Vec<Value>) -> Vec<Value> {
+    let mut inlines = Vec::new();
+    for block in blocks {
+        if tag_of(&block) == Some("Plain") || tag_of(&block) == Some("Para") {
+            if !inlines.is_empty() {
+                inlines.push(node("Space", Value::Array(Vec::new())));
+            }
+            let content = block.as_object().and_then(|obj| obj.get("c")).cloned();
+            inlines.extend(into_array(content.unwrap_or(Value::Null)));
+        }
+    }
+    inlines
+}
+
+fn downgrade_table(content: Value) -> Value {
+    let mut parts = into_array(content).into_iter().skip(1);
+    let caption = into_array(parts.next().unwrap_or(Value::Null));
+    let col_specs = into_array(parts.next().unwrap_or(Value::Null));
+    let head = into_array(parts.next().unwrap_or(Value::Null));
+    let bodies = into_array(parts.next().unwrap_or(Value::Null));
+    let foot = into_array(parts.next().unwrap_or(Value::Null));
+
+    let columns = col_specs.len();
+    let caption = caption_inlines(into_array(caption.into_iter().nth(1).unwrap_or(Value::Null)));
+    let mut aligns = Vec::new();
+    let mut widths = Vec::new();
+    for spec in col_specs {
+        let mut spec = into_array(spec).into_iter();
+        aligns.push(spec.next().unwrap_or(Value::Null));
+        let width = spec.next();
+        widths.push(match width.as_ref().map(|w| (tag_of(w), w)) {
+            Some((Some("ColWidth"), w)) =>
+                w.as_object().and_then(|obj| obj.get("c")).cloned().unwrap_or(Value::F64(0.0)),
+            _ => Value::F64(0.0)
+        });
+    }
+
+    let head_rows = into_array(head.into_iter().nth(1).unwrap_or(Value::Null));
+    let mut head_rows = section_cells(head_rows, columns).into_iter();
+    let headers = match head_rows.next() {
+        Some(first) => first,
+        None => Value::Array((0..columns).map(|_| Value::Array(Vec::new())).collect())
+    };
+    let mut rows: Vec<Value> = head_rows.collect();
+    for body in bodies {
+        for part in into_array(body).into_iter().skip(2) {
+            rows.extend(section_cells(into_array(part), columns));
+        }
+    }
+    rows.extend(section_cells(into_array(foot.into_iter().nth(1).unwrap_or(Value::Null)), columns));
+
+    node("Table", Value::Array(vec![Value::Array(caption), Value::Array(aligns),
+                                    Value::Array(widths), headers, Value::Array(rows)]))
+}
+
+fn downgrade_figure(content: Value) -> Value {
+    let mut parts = into_array(content).into_iter();
+    let attr = parts.next().unwrap_or_else(null_attr);
+    let caption = into_array(parts.next().unwrap_or(Value::Null));
+    let mut blocks = into_array(parts.next().unwrap_or(Value::Null));
+    blocks.extend(into_array(caption.into_iter().nth(1).unwrap_or(Value::Null)));
+    node("Div", Value::Array(vec![attr_with_class(attr, "figure"), Value::Array(blocks)]))
+}
+
+fn downgrade_line_block(content: Value) -> Value {
+    let mut inlines = Vec::new();
+    for line in into_array(content) {
+        if !inlines.is_empty() {
+            inlines.push(node("LineBreak", Value::Array(Vec::new())));
+        }
+        inlines.extend(into_array(line));
+    }
+    node("Para", Value::Array(inlines))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json;
+    use serde_json::Value;
+    use types::Block;
+
+    fn parse(json: &str) -> Value {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn supported_versions() {
+        assert!(is_supported(&None));
+        assert!(is_supported(&Some(vec![1, 17, 0, 4])));
+        assert!(is_supported(&Some(vec![1, 23, 1])));
+        assert!(!is_supported(&Some(vec![1, 16])));
+        assert!(!is_supported(&Some(vec![1, 24])));
+    }
+
+    #[test]
+    fn upgrade_old_table() {
+        let old = r#"{"t":"Table","c":[[{"t":"Str","c":"Cap"}],[{"t":"AlignLeft","c":[]}],[0.5],[[{"t":"Plain","c":[{"t":"Str","c":"H"}]}]],[[[{"t":"Plain","c":[{"t":"Str","c":"a"}]}]]]]}"#;
+        let new = r#"{"t":"Table","c":[["",[],[]],[null,[{"t":"Plain","c":[{"t":"Str","c":"Cap"}]}]],[[{"t":"AlignLeft"},{"t":"ColWidth","c":0.5}]],[["",[],[]],[[["",[],[]],[[["",[],[]],{"t":"AlignDefault"},1,1,[{"t":"Plain","c":[{"t":"Str","c":"H"}]}]]]]]],[[["",[],[]],0,[],[[["",[],[]],[[["",[],[]],{"t":"AlignDefault"},1,1,[{"t":"Plain","c":[{"t":"Str","c":"a"}]}]]]]]]],[["",[],[]],[]]]}"#;
+        let table: Block = serde_json::from_str(old).unwrap();
+        assert_eq!(serde_json::to_value(&table), parse(new));
+    }
+
+    #[test]
+    fn downgrade_new_table() {
+        let new = parse(r#"{"t":"Table","c":[["id",[],[]],[null,[{"t":"Para","c":[{"t":"Str","c":"Cap"}]}]],[[{"t":"AlignLeft"},{"t":"ColWidth","c":0.5}],[{"t":"AlignRight"},{"t":"ColWidthDefault"}]],[["",[],[]],[]],[[["",[],[]],0,[],[[["",[],[]],[[["",[],[]],{"t":"AlignDefault"},1,2,[{"t":"Plain","c":[{"t":"Str","c":"a"}]}]]]]]]],[["",[],[]],[]]]}"#);
+        let old = parse(r#"{"t":"Table","c":[[{"t":"Str","c":"Cap"}],[{"t":"AlignLeft","c":[]},{"t":"AlignRight","c":[]}],[0.5,0.0],[[],[]],[[[{"t":"Plain","c":[{"t":"Str","c":"a"}]}],[]]]]}"#);
+        assert_eq!(downgrade(new, &Some(vec![1, 20])), old);
+    }
+
+    #[test]
+    fn downgrade_row_spans() {
+        let cell = |text: &str, row_span: u64, col_span: u64| {
+            format!(r#"[["",[],[]],{{"t":"AlignDefault"}},{},{},[{{"t":"Plain","c":[{{"t":"Str","c":"{}"}}]}}]]"#,
+                    row_span, col_span, text)
+        };
+        let row = |cells: Vec<String>| format!(r#"[["",[],[]],[{}]]"#, cells.join(","));
+        let rows = [row(vec![cell("a", 2, 1), cell("b", 1, 1), cell("c", 1, 1), cell("extra", 1, 1)]),
+                    row(vec![cell("d", 1, 1), cell("e", 1, 1)]),
+                    row(vec![cell("f", 1, 1), cell("g", 2, 2)]),
+                    row(vec![])];
+        let new = parse(&format!(r#"{{"t":"Table","c":[["",[],[]],[null,[]],[{spec},{spec},{spec}],[["",[],[]],[]],[[["",[],[]],0,[],[{rows}]]],[["",[],[]],[]]]}}"#,
+                                 spec = r#"[{"t":"AlignDefault"},{"t":"ColWidthDefault"}]"#,
+                                 rows = rows.join(",")));
+        let plain = |text: &str| format!(r#"[{{"t":"Plain","c":[{{"t":"Str","c":"{}"}}]}}]"#, text);
+        let expected = parse(&format!(r#"[[{a},{b},{c}],[[],{d},{e}],[{f},{g},[]],[[],[],[]]]"#,
+                                      a = plain("a"), b = plain("b"), c = plain("c"), d = plain("d"),
+                                      e = plain("e"), f = plain("f"), g = plain("g")));
+        let old = downgrade(new, &Some(vec![1, 20]));
+        assert_eq!(old.as_object().unwrap()["c"].as_array().unwrap()[4], expected);
+    }
+
+    #[test]
+    fn downgrade_figure_and_underline() {
+        let new = parse(r#"[{"t":"Figure","c":[["fig",[],[]],[null,[{"t":"Plain","c":[{"t":"Underline","c":[{"t":"Str","c":"Cap"}]}]}]],[{"t":"Para","c":[]}]]}]"#);
+        let at_22 = parse(r#"[{"t":"Div","c":[["fig",["figure"],[]],[{"t":"Para","c":[]},{"t":"Plain","c":[{"t":"Underline","c":[{"t":"Str","c":"Cap"}]}]}]]}]"#);
+        let at_20 = parse(r#"[{"t":"Div","c":[["fig",["figure"],[]],[{"t":"Para","c":[]},{"t":"Plain","c":[{"t":"Span","c":[["",["underline"],[]],[{"t":"Str","c":"Cap"}]]}]}]]}]"#);
+        assert_eq!(downgrade(new.clone(), &Some(vec![1, 22])), at_22);
+        assert_eq!(downgrade(new, &Some(vec![1, 20])), at_20);
+    }
+
+    #[test]
+    fn downgrade_line_block() {
+        let new = parse(r#"[{"t":"LineBlock","c":[[{"t":"Str","c":"a"}],[{"t":"Str","c":"b"}]]}]"#);
+        let legacy = parse(r#"[{"t":"Para","c":[{"t":"Str","c":"a"},{"t":"LineBreak","c":[]},{"t":"Str","c":"b"}]}]"#);
+        assert_eq!(downgrade(new.clone(), &None), legacy);
+        assert_eq!(downgrade(new.clone(), &Some(vec![1, 22])), new);
+    }
+
+    #[test]
+    fn downgrade_line_block_and_null() {
+        let new = parse(r#"[{"t":"Null"},{"t":"LineBlock","c":[[{"t":"Str","c":"a"}],[{"t":"Str","c":"b"}]]}]"#);
+        let legacy = parse(r#"[{"t":"Null","c":[]},{"t":"Para","c":[{"t":"Str","c":"a"},{"t":"LineBreak","c":[]},{"t":"Str","c":"b"}]}]"#);
+        let at_21 = parse(r#"[{"t":"Null","c":[]},{"t":"LineBlock","c":[[{"t":"Str","c":"a"}],[{"t":"Str","c":"b"}]]}]"#);
+        let at_23 = parse(r#"[{"t":"LineBlock","c":[[{"t":"Str","c":"a"}],[{"t":"Str","c":"b"}]]}]"#);
+        assert_eq!(downgrade(new.clone(), &None), legacy);
+        assert_eq!(downgrade(new.clone(), &Some(vec![1, 21])), at_21);
+        assert_eq!(downgrade(new, &Some(vec![1, 23])), at_23);
+    }
+}
@@ -0,0 +1,576 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use types::{Inline, Block, Citation, Pandoc, Meta, MetaValue, Attr};
+use types::{Caption, TableHead, TableBody, TableFoot, Row, Cell};
+use env::FilterContext;
+use walk::Descent;
+
+/// A `Block` or `Inline` that encloses the node being visited.
+pub struct Ancestor<'a> {
+    /// The constructor of the ancestor, as its `"t"`, e.g. `"Link"`.
+    pub tag: &'static str,
+    /// The attributes of the ancestor, if its constructor has them.
+    pub attr: Option<&'a Attr>,
+    /// The level of the ancestor if it is a `Header`.
+    pub level: Option<u64>,
+    at: Position<'a>
+}
+
+impl<'a> Ancestor<'a> {
+    /// The position of the ancestor itself among its siblings.
+    pub fn index(&self) -> usize {
+        self.at.index
+    }
+}
+
+/// Where a node is: its closest ancestor, and its index in the list it is in.
+#[derive(Clone, Copy)]
+pub struct Position<'a> {
+    parent: Option<&'a Ancestor<'a>>,
+    index: usize,
+    filter_context: Option<&'a FilterContext>
+}
+
+impl<'a> Position<'a> {
+    /// The position of the value `walk_with_context` is called on.
+    pub fn root() -> Position<'static> {
+        Position { parent: None, index: 0, filter_context: None }
+    }
+
+    /// The position of the value `walk_with_filter_context` is called on.
+    pub fn root_in(context: &'a FilterContext) -> Position<'a> {
+        Position { parent: None, index: 0, filter_context: Some(context) }
+    }
+
+    fn at(self, index: usize) -> Position<'a> {
+        Position { parent: self.parent, index: index, filter_context: self.filter_context }
+    }
+
+    fn inside<'b>(self, parent: &'b Ancestor<'b>) -> Position<'b>
+        where 'a: 'b {
+        Position { parent: Some(parent), index: 0, filter_context: self.filter_context }
+    }
+}
+
+/// The header whose section a node is in.
+#[derive(PartialEq, Debug)]
+pub struct Section {
+    pub level: u64,
+    pub attr: Attr
+}
+
+/// What `ContextWalkable` passes to the filter along with each node.
+pub struct Context<'a> {
+    at: Position<'a>,
+    section: Option<&'a Section>
+}
+
+impl<'a> Context<'a> {
+    /// The enclosing blocks and inlines, closest first.
+    pub fn ancestors(&self) -> Ancestors<'a> {
+        Ancestors(self.at.parent)
+    }
+
+    /// The closest enclosing block or inline.
+    pub fn parent(&self) -> Option<&'a Ancestor<'a>> {
+        self.at.parent
+    }
+
+    /// Whether any enclosing node has the constructor `tag`.
+    pub fn is_inside(&self, tag: &str) -> bool {
+        self.ancestors().any(|a| a.tag == tag)
+    }
+
+    /// The number of enclosing blocks and inlines.
+    pub fn depth(&self) -> usize {
+        self.ancestors().count()
+    }
+
+    /// The index of the node in the list it is in.
+    pub fn index(&self) -> usize {
+        self.at.index
+    }
+
+    /// The last header met before the node in document order, or the header
+    /// the node is part of. A header inside a container, such as a `Div`, a
+    /// list item or a note, only counts up to the end of that container.
+    /// `None` before the first header.
+    pub fn section(&self) -> Option<&'a Section> {
+        self.section
+    }
+
+    /// What pandoc told the filter about the conversion, if the walk was
+    /// started with `walk_with_filter_context`.
+    pub fn filter_context(&self) -> Option<&'a FilterContext> {
+        self.at.filter_context
+    }
+}
+
+/// Iterator over the ancestors of a node, closest first.
+pub struct Ancestors<'a>(Option<&'a Ancestor<'a>>);
+
+impl<'a> Iterator for Ancestors<'a> {
+    type Item = &'a Ancestor<'a>;
+
+    fn next(&mut self) -> Option<&'a Ancestor<'a>> {
+        let next = self.0;
+        self.0 = next.and_then(|a| a.at.parent);
+        next
+    }
+}
+
+/// The order in which `ContextWalkable` applies its filter to a node and
+/// walks the node's children.
+pub trait ContextTraversal<T> {
+    /// Filters `node`, found in `cx`, using `children` to walk its children.
+    fn visit<W>(&self, node: T, cx: &Context, children: W) -> T
+        where W : FnOnce(T) -> T;
+}
+
+/// `BottomUp` for `ContextWalkable`.
+pub struct ContextBottomUp<'a, F: 'a>(pub &'a F);
+
+impl<'a, T, F> ContextTraversal<T> for ContextBottomUp<'a, F>
+    where F : Fn(T, &Context) -> T {
+    fn visit<W>(&self, node: T, cx: &Context, children: W) -> T
+        where W : FnOnce(T) -> T {
+        (self.0)(children(node), cx)
+    }
+}
+
+/// `TopDown` for `ContextWalkable`.
+pub struct ContextTopDown<'a, F: 'a>(pub &'a F);
+
+impl<'a, T, F> ContextTraversal<T> for ContextTopDown<'a, F>
+    where F : Fn(T, &Context) -> (T, Descent) {
+    fn visit<W>(&self, node: T, cx: &Context, children: W) -> T
+        where W : FnOnce(T) -> T {
+        match (self.0)(node, cx) {
+            (node, Descent::Continue) => children(node),
+            (node, Descent::Stop) => node
+        }
+    }
+}
+
+/// Like `Walkable`, with a filter that also gets the `Context` of each node.
+/// The same nodes are visited in the same order.
+pub trait ContextWalkable<T> : Sized {
+    /// Walks the value found at `at` with `v`, keeping the current header in
+    /// `section`.
+    fn walk_in<V>(self, v: &V, at: Position, section: &mut Option<Rc<Section>>) -> Self
+        where V : ContextTraversal<T>;
+
+    fn walk_with_context<F>(self, f: &F) -> Self
+        where F : Fn(T, &Context) -> T {
+        self.walk_in(&ContextBottomUp(f), Position::root(), &mut None)
+    }
+
+    /// Like `walk_with_context`, with `context` available to the filter
+    /// through `Context::filter_context`.
+    fn walk_with_filter_context<F>(self, f: &F, context: &FilterContext) -> Self
+        where F : Fn(T, &Context) -> T {
+        self.walk_in(&ContextBottomUp(f), Position::root_in(context), &mut None)
+    }
+
+    /// Like `walk_top_down`, with the `Context` of each node.
+    fn walk_top_down_with_context<F>(self, f: &F) -> Self
+        where F : Fn(T, &Context) -> (T, Descent) {
+        self.walk_in(&ContextTopDown(f), Position::root(), &mut None)
+    }
+}
+
+impl<U, T> ContextWalkable<U> for Vec<T>
+    where T : ContextWalkable<U> {
+    fn walk_in<V>(self, v: &V, at: Position, section: &mut Option<Rc<Section>>) -> Self
+        where V : ContextTraversal<U> {
+        self.into_iter().enumerate().map(|(i, x)| x.walk_in(v, at.at(i), section)).collect()
+    }
+}
+
+impl<U, T> ContextWalkable<U> for Option<T>
+    where T : ContextWalkable<U> {
+    fn walk_in<V>(self, v: &V, at: Position, section: &mut Option<Rc<Section>>) -> Self
+        where V : ContextTraversal<U> {
+        self.map(|x| x.walk_in(v, at, section))
+    }
+}
+
+impl<U, K> ContextWalkable<U> for BTreeMap<String, K>
+    where K : ContextWalkable<U> {
+    fn walk_in<V>(self, v: &V, at: Position, section: &mut Option<Rc<Section>>) -> Self
+        where V : ContextTraversal<U> {
+        self.into_iter().map(|(k, x)| (k, x.walk_in(v, at, section))).collect()
+    }
+}
+
+impl<T> ContextWalkable<T> for Pandoc
+    where Meta : ContextWalkable<T>, Block : ContextWalkable<T> {
+    fn walk_in<V>(self, v: &V, at: Position, section: &mut Option<Rc<Section>>) -> Self
+        where V : ContextTraversal<T> {
+        let meta = self.0.walk_in(v, at, section);
+        // Headers in the metadata don't start sections of the body.
+        *section = None;
+        Pandoc(meta, self.1.walk_in(v, at, section), self.2)
+    }
+}
+
+impl<T> ContextWalkable<T> for Meta
+    where MetaValue : ContextWalkable<T> {
+    fn walk_in<V>(self, v: &V, at: Position, section: &mut Option<Rc<Section>>) -> Self
+        where V : ContextTraversal<T> {
+        Meta { un_meta: self.un_meta.walk_in(v, at, section) }
+    }
+}
+
+impl ContextWalkable<Block> for MetaValue {
+    fn walk_in<V>(self, v: &V, at: Position, section: &mut Option<Rc<Section>>) -> Self
+        where V : ContextTraversal<Block> {
+        walk_meta_value_children(self, v, at, section)
+    }
+}
+
+impl ContextWalkable<Inline> for MetaValue {
+    fn walk_in<V>(self, v: &V, at: Position, section: &mut Option<Rc<Section>>) -> Self
+        where V : ContextTraversal<Inline> {
+        walk_meta_value_children(self, v, at, section)
+    }
+}
+
+fn walk_meta_value_children<T, V>(value: MetaValue, v: &V, at: Position,
+                                  section: &mut Option<Rc<Section>>) -> MetaValue
+    where V : ContextTraversal<T>,
+          MetaValue : ContextWalkable<T>, Inline : ContextWalkable<T>, Block : ContextWalkable<T> {
+    match value {
+        MetaValue::MetaMap(map) => MetaValue::MetaMap(map.walk_in(v, at, section)),
+        MetaValue::MetaList(values) => MetaValue::MetaList(values.walk_in(v, at, section)),
+        MetaValue::MetaInlines(inlines) => MetaValue::MetaInlines(inlines.walk_in(v, at, section)),
+        MetaValue::MetaBlocks(blocks) => MetaValue::MetaBlocks(blocks.walk_in(v, at, section)),
+        e => e
+    }
+}
+
+impl ContextWalkable<Block> for Block {
+    fn walk_in<V>(self, v: &V, at: Position, section: &mut Option<Rc<Section>>) -> Self
+        where V : ContextTraversal<Block> {
+        start_section(&self, section);
+        let current = section.clone();
+        let cx = Context { at: at, section: current.as_ref().map(|s| &**s) };
+        v.visit(self, &cx, |block| walk_block_children(block, v, at, section))
+    }
+}
+
+impl ContextWalkable<Inline> for Block {
+    fn walk_in<V>(self, v: &V, at: Position, section: &mut Option<Rc<Section>>) -> Self
+        where V : ContextTraversal<Inline> {
+        start_section(&self, section);
+        walk_block_children(self, v, at, section)
+    }
+}
+
+/// Starts the section of `block` if it is a header, so that the header itself
+/// is part of it.
+fn start_section(block: &Block, section: &mut Option<Rc<Section>>) {
+    if let Block::Header(level, ref attr, _) = *block {
+        *section = Some(Rc::new(Section { level: level, attr: attr.clone() }));
+    }
+}
+
+/// Walks the children of an ancestor with the given tag and attributes.
+macro_rules! inside {
+    ($v:ident, $at:ident, $section:ident, $tag:expr, $attr:expr, $($children:ident),*) => {{
+        let me = Ancestor { tag: $tag, attr: $attr, level: None, at: $at };
+        let inside = $at.inside(&me);
+        // A header among the children only starts a section up to the end
+        // of this node.
+        let outer = $section.clone();
+        let walked = ($( $children.walk_in($v, inside, $section) ),*);
+        *$section = outer;
+        walked
+    }}
+}
+
+fn walk_block_children<T, V>(block: Block, v: &V, at: Position,
+                             section: &mut Option<Rc<Section>>) -> Block
+    where V : ContextTraversal<T>, Inline : ContextWalkable<T>, Block : ContextWalkable<T> {
+    let tag = block.tag();
+    match block {
+        Block::Plain(inlines) => Block::Plain(inside!(v, at, section, tag, None, inlines)),
+        Block::Para(inlines) => Block::Para(inside!(v, at, section, tag, None, inlines)),
+        Block::LineBlock(lines) => Block::LineBlock(inside!(v, at, section, tag, None, lines)),
+        Block::BlockQuote(blocks) => Block::BlockQuote(inside!(v, at, section, tag, None, blocks)),
+        Block::OrderedList(list, vecs_blocks) =>
+            Block::OrderedList(list, inside!(v, at, section, tag, None, vecs_blocks)),
+        Block::BulletList(vecs_blocks) =>
+            Block::BulletList(inside!(v, at, section, tag, None, vecs_blocks)),
+        Block::DefinitionList(inlines_and_blocks) => {
+            let me = Ancestor { tag: tag, attr: None, level: None, at: at };
+            let inside = at.inside(&me);
+            let outer = section.clone();
+            let walked = inlines_and_blocks
+                .into_iter()
+                .enumerate()
+                .map(|(i, (is, vbs))| (is.walk_in(v, inside.at(i), section),
+                                       vbs.walk_in(v, inside.at(i), section)))
+                .collect();
+            *section = outer;
+            Block::DefinitionList(walked)
+        },
+        Block::Header(level, attr, inlines) => {
+            let inlines = {
+                let me = Ancestor { tag: tag, attr: Some(&attr), level: Some(level), at: at };
+                inlines.walk_in(v, at.inside(&me), section)
+            };
+            Block::Header(level, attr, inlines)
+        },
+        Block::Table(attr, caption, col_specs, head, bodies, foot) => {
+            let (caption, head, bodies, foot) =
+                inside!(v, at, section, tag, Some(&attr), caption, head, bodies, foot);
+            Block::Table(attr, caption, col_specs, head, bodies, foot)
+        },
+        Block::Figure(attr, caption, blocks) => {
+            let (caption, blocks) = inside!(v, at, section, tag, Some(&attr), caption, blocks);
+            Block::Figure(attr, caption, blocks)
+        },
+        Block::Div(attr, blocks) => {
+            let blocks = inside!(v, at, section, tag, Some(&attr), blocks);
+            Block::Div(attr, blocks)
+        },
+        b => b
+    }
+}
+
+impl ContextWalkable<Inline> for Inline {
+    fn walk_in<V>(self, v: &V, at: Position, section: &mut Option<Rc<Section>>) -> Self
+        where V : ContextTraversal<Inline> {
+        let current = section.clone();
+        let cx = Context { at: at, section: current.as_ref().map(|s| &**s) };
+        v.visit(self, &cx, |inline| walk_inline_children(inline, v, at, section))
+    }
+}
+
+impl ContextWalkable<Block> for Inline {
+    fn walk_in<V>(self, v: &V, at: Position, section: &mut Option<Rc<Section>>) -> Self
+        where V : ContextTraversal<Block> {
+        walk_inline_children(self, v, at, section)
+    }
+}
+
+fn walk_inline_children<T, V>(inline: Inline, v: &V, at: Position,
+                              section: &mut Option<Rc<Section>>) -> Inline
+    where V : ContextTraversal<T>, Inline : ContextWalkable<T>, Block : ContextWalkable<T> {
+    let tag = inline.tag();
+    match inline {
+        Inline::Emph(inlines) => Inline::Emph(inside!(v, at, section, tag, None, inlines)),
+        Inline::Underline(inlines) => Inline::Underline(inside!(v, at, section, tag, None, inlines)),
+        Inline::Strong(inlines) => Inline::Strong(inside!(v, at, section, tag, None, inlines)),
+        Inline::Strikeout(inlines) => Inline::Strikeout(inside!(v, at, section, tag, None, inlines)),
+        Inline::Superscript(inlines) =>
+            Inline::Superscript(inside!(v, at, section, tag, None, inlines)),
+        Inline::Subscript(inlines) => Inline::Subscript(inside!(v, at, section, tag, None, inlines)),
+        Inline::SmallCaps(inlines) => Inline::SmallCaps(inside!(v, at, section, tag, None, inlines)),
+        Inline::Quoted(quote_type, inlines) =>
+            Inline::Quoted(quote_type, inside!(v, at, section, tag, None, inlines)),
+        Inline::Cite(citations, inlines) => {
+            let (citations, inlines) = inside!(v, at, section, tag, None, citations, inlines);
+            Inline::Cite(citations, inlines)
+        },
+        Inline::Link(attr, inlines, target) => {
+            let inlines = inside!(v, at, section, tag, Some(&attr), inlines);
+            Inline::Link(attr, inlines, target)
+        },
+        Inline::Image(attr, inlines, target) => {
+            let inlines = inside!(v, at, section, tag, Some(&attr), inlines);
+            Inline::Image(attr, inlines, target)
+        },
+        Inline::Span(attr, inlines) => {
+            let inlines = inside!(v, at, section, tag, Some(&attr), inlines);
+            Inline::Span(attr, inlines)
+        },
+        Inline::Note(blocks) => Inline::Note(inside!(v, at, section, tag, None, blocks)),
+        i => i
+    }
+}
+
+impl<T> ContextWalkable<T> for Caption
+    where Inline : ContextWalkable<T>, Block : ContextWalkable<T> {
+    fn walk_in<V>(self, v: &V, at: Position, section: &mut Option<Rc<Section>>) -> Self
+        where V : ContextTraversal<T> {
+        Caption(self.0.walk_in(v, at, section), self.1.walk_in(v, at, section))
+    }
+}
+
+impl<T> ContextWalkable<T> for TableHead
+    where Row : ContextWalkable<T> {
+    fn walk_in<V>(self, v: &V, at: Position, section: &mut Option<Rc<Section>>) -> Self
+        where V : ContextTraversal<T> {
+        TableHead(self.0, self.1.walk_in(v, at, section))
+    }
+}
+
+impl<T> ContextWalkable<T> for TableBody
+    where Row : ContextWalkable<T> {
+    fn walk_in<V>(self, v: &V, at: Position, section: &mut Option<Rc<Section>>) -> Self
+        where V : ContextTraversal<T> {
+        let head = self.2.walk_in(v, at, section);
+        TableBody(self.0, self.1, head, self.3.walk_in(v, at, section))
+    }
+}
+
+impl<T> ContextWalkable<T> for TableFoot
+    where Row : ContextWalkable<T> {
+    fn walk_in<V>(self, v: &V, at: Position, section: &mut Option<Rc<Section>>) -> Self
+        where V : ContextTraversal<T> {
+        TableFoot(self.0, self.1.walk_in(v, at, section))
+    }
+}
+
+impl<T> ContextWalkable<T> for Row
+    where Cell : ContextWalkable<T> {
+    fn walk_in<V>(self, v: &V, at: Position, section: &mut Option<Rc<Section>>) -> Self
+        where V : ContextTraversal<T> {
+        Row(self.0, self.1.walk_in(v, at, section))
+    }
+}
+
+impl<T> ContextWalkable<T> for Cell
+    where Block : ContextWalkable<T> {
+    fn walk_in<V>(self, v: &V, at: Position, section: &mut Option<Rc<Section>>) -> Self
+        where V : ContextTraversal<T> {
+        Cell(self.0, self.1, self.2, self.3, self.4.walk_in(v, at, section))
+    }
+}
+
+impl<T> ContextWalkable<T> for Citation
+    where Inline : ContextWalkable<T> {
+    fn walk_in<V>(self, v: &V, at: Position, section: &mut Option<Rc<Section>>) -> Self
+        where V : ContextTraversal<T> {
+        let prefix = self.citation_prefix.walk_in(v, at, section);
+        Citation { citation_id: self.citation_id,
+                   citation_prefix: prefix,
+                   citation_suffix: self.citation_suffix.walk_in(v, at, section),
+                   citation_mode: self.citation_mode,
+                   citation_note_num: self.citation_note_num,
+                   citation_hash: self.citation_hash
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use context::ContextWalkable;
+    use walk::Descent;
+    use env::FilterContext;
+    use types::*;
+    use test_util::{text, attr, doc};
+
+    #[test]
+    fn str_inside_link() {
+        let para = Block::Para(vec![text("a"),
+                                    Inline::Link(attr("l"), vec![Inline::Emph(vec![text("b")])],
+                                                 (String::from("#"), String::new()))]);
+        let walked = para.walk_with_context(&(|i, cx| match i {
+            Inline::Str(s) => {
+                let kinds: Vec<&str> = cx.ancestors().map(|a| a.tag).collect();
+                Inline::Str(format!("{}:{}:{}:{}", s, kinds.join("<"), cx.depth(), cx.is_inside("Link")))
+            },
+            e => e
+        }));
+        let expected = Block::Para(vec![text("a:Para:1:false"),
+                                        Inline::Link(attr("l"), vec![Inline::Emph(vec![text("b:Emph<Link<Para:3:true")])],
+                                                     (String::from("#"), String::new()))]);
+        assert_eq!(walked, expected);
+    }
+
+    #[test]
+    fn section_ends_with_container() {
+        let note = Inline::Note(vec![Block::Header(2, attr("note"), vec![]), Block::Para(vec![text("")])]);
+        let doc = doc(vec![Block::Header(1, attr("top"), vec![]),
+                           Block::Para(vec![text(""), note, text("")]),
+                           Block::Div(attr(""), vec![Block::Header(2, attr("inner"), vec![])]),
+                           Block::Para(vec![text("")])]);
+        let section = |cx: &::context::Context| String::from(cx.section().map_or("-", |s| &s.attr.0[..]));
+        let Pandoc(_, blocks, _) = doc.walk_with_context(&(|i, cx| match i {
+            Inline::Str(_) => text(&section(cx)),
+            e => e
+        })).walk_with_context(&(|b, cx| match b {
+            Block::Div(_, blocks) => Block::Div(attr(&section(cx)), blocks),
+            e => e
+        }));
+        let note = Inline::Note(vec![Block::Header(2, attr("note"), vec![]), Block::Para(vec![text("note")])]);
+        assert_eq!(blocks, vec![Block::Header(1, attr("top"), vec![]),
+                                Block::Para(vec![text("top"), note, text("top")]),
+                                Block::Div(attr("top"), vec![Block::Header(2, attr("inner"), vec![])]),
+                                Block::Para(vec![text("top")])]);
+    }
+
+    #[test]
+    fn top_down_header_in_own_section() {
+        let blocks = vec![Block::Para(vec![text("")]),
+                          Block::Header(1, attr("h"), vec![text("")]),
+                          Block::BlockQuote(vec![Block::Para(vec![text("")])])];
+        let walked = blocks.walk_top_down_with_context(&(|b, cx| {
+            let id = String::from(cx.section().map_or("-", |s| &s.attr.0[..]));
+            match b {
+                Block::Para(_) => (Block::Para(vec![text(&id)]), Descent::Continue),
+                Block::Header(level, attr, _) => (Block::Header(level, attr, vec![text(&id)]), Descent::Continue),
+                e @ Block::BlockQuote(_) => (e, Descent::Stop),
+                e => (e, Descent::Continue)
+            }
+        }));
+        assert_eq!(walked, vec![Block::Para(vec![text("-")]),
+                                Block::Header(1, attr("h"), vec![text("h")]),
+                                Block::BlockQuote(vec![Block::Para(vec![text("")])])]);
+    }
+
+    #[test]
+    fn filter_context_inside_note() {
+        let para = Block::Para(vec![Inline::Note(vec![Block::Para(vec![text("a")])])]);
+        let format = |i, cx: &::context::Context| match i {
+            Inline::Str(_) => text(cx.filter_context().map_or("-", |c| &c.format.as_ref().unwrap().base[..])),
+            e => e
+        };
+        let context = FilterContext::with_format("latex");
+        let expected = |s| Block::Para(vec![Inline::Note(vec![Block::Para(vec![text(s)])])]);
+        let walked = para.walk_with_filter_context(&format, &context);
+        assert_eq!(walked, expected("latex"));
+        assert_eq!(walked.walk_with_context(&format), expected("-"));
+    }
+
+    #[test]
+    fn first_para_in_block_quote() {
+        let quote = Block::BlockQuote(vec![Block::Para(vec![text("a")]), Block::Para(vec![text("b")])]);
+        let walked = quote.walk_with_context(&(|b, cx| match b {
+            Block::Para(inlines) =>
+                if cx.index() == 0 && cx.parent().map(|p| p.tag) == Some("BlockQuote") {
+                    Block::Plain(inlines)
+                } else {
+                    Block::Para(inlines)
+                },
+            e => e
+        }));
+        let expected = Block::BlockQuote(vec![Block::Plain(vec![text("a")]), Block::Para(vec![text("b")])]);
+        assert_eq!(walked, expected);
+    }
+
+    #[test]
+    fn section_and_ancestor_attr() {
+        let doc = doc(vec![Block::Para(vec![text("")]),
+                           Block::Header(1, attr("intro"), vec![text("")]),
+                           Block::Div(attr("box"), vec![Block::Para(vec![text("")])]),
+                           Block::Header(2, attr("details"), vec![]),
+                           Block::Plain(vec![text("")])]);
+        let Pandoc(_, blocks, _) = doc.walk_with_context(&(|i, cx| match i {
+            Inline::Str(_) => {
+                let section = cx.section().map_or("-", |s| &s.attr.0[..]);
+                let div = cx.ancestors().filter_map(|a| a.attr).map(|a| &a.0[..]).next().unwrap_or("-");
+                Inline::Str(format!("{}/{}", section, div))
+            },
+            e => e
+        }));
+        assert_eq!(blocks, vec![Block::Para(vec![text("-/-")]),
+                                Block::Header(1, attr("intro"), vec![text("intro/intro")]),
+                                Block::Div(attr("box"), vec![Block::Para(vec![text("intro/box")])]),
+                                Block::Header(2, attr("details"), vec![]),
+                                Block::Plain(vec![text("details/-")])]);
+    }
+}
@@ -0,0 +1,619 @@
+use std::mem;
+use types::{Pandoc, Block, Inline, TableBody};
+use iter::Node;
+
+/// A block or an inline, owned, as taken out of or put into a document by a
+/// `Cursor`.
+#[derive(PartialEq, Debug)]
+pub enum Item {
+    Block(Block),
+    Inline(Inline)
+}
+
+impl From<Block> for Item {
+    fn from(block: Block) -> Item {
+        Item::Block(block)
+    }
+}
+
+impl From<Inline> for Item {
+    fn from(inline: Inline) -> Item {
+        Item::Inline(inline)
+    }
+}
+
+enum List<'a> {
+    Blocks(&'a mut Vec<Block>),
+    Inlines(&'a mut Vec<Inline>)
+}
+
+enum ListRef<'a> {
+    Blocks(&'a Vec<Block>),
+    Inlines(&'a Vec<Inline>)
+}
+
+/// Defines the functions listing the children of a block and of an inline as
+/// separate lists, in document order: one per list item, definition, table
+/// cell and so on. Used once for shared and once for mutable borrows.
+macro_rules! child_lists {
+    ($block_lists:ident, $inline_lists:ident, $List:ident, $($m:tt)*) => {
+        fn $block_lists<'a>(block: &'a $($m)* Block) -> Vec<$List<'a>> {
+            let mut out = vec![];
+            match *block {
+                Block::Plain(ref $($m)* inlines) | Block::Para(ref $($m)* inlines) |
+                Block::Header(_, _, ref $($m)* inlines) => out.push($List::Inlines(inlines)),
+                Block::LineBlock(ref $($m)* lines) => for line in lines {
+                    out.push($List::Inlines(line))
+                },
+                Block::BlockQuote(ref $($m)* blocks) | Block::Div(_, ref $($m)* blocks) =>
+                    out.push($List::Blocks(blocks)),
+                Block::OrderedList(_, ref $($m)* items) | Block::BulletList(ref $($m)* items) =>
+                    for item in items {
+                        out.push($List::Blocks(item))
+                    },
+                Block::DefinitionList(ref $($m)* items) => for &$($m)* (ref $($m)* term, ref $($m)* defs) in items {
+                    out.push($List::Inlines(term));
+                    for def in defs {
+                        out.push($List::Blocks(def))
+                    }
+                },
+                Block::Table(_, ref $($m)* caption, _, ref $($m)* head, ref $($m)* bodies, ref $($m)* foot) => {
+                    if let Some(ref $($m)* short) = caption.0 {
+                        out.push($List::Inlines(short))
+                    }
+                    out.push($List::Blocks(& $($m)* caption.1));
+                    let mut sections = vec![& $($m)* head.1];
+                    for & $($m)* TableBody(_, _, ref $($m)* head_rows, ref $($m)* rows) in bodies {
+                        sections.push(head_rows);
+                        sections.push(rows);
+                    }
+                    sections.push(& $($m)* foot.1);
+                    for rows in sections {
+                        for row in rows {
+                            for cell in & $($m)* row.1 {
+                                out.push($List::Blocks(& $($m)* cell.4))
+                            }
+                        }
+                    }
+                },
+                Block::Figure(_, ref $($m)* caption, ref $($m)* blocks) => {
+                    if let Some(ref $($m)* short) = caption.0 {
+                        out.push($List::Inlines(short))
+                    }
+                    out.push($List::Blocks(& $($m)* caption.1));
+                    out.push($List::Blocks(blocks))
+                },
+                _ => ()
+            }
+            out
+        }
+
+        fn $inline_lists<'a>(inline: &'a $($m)* Inline) -> Vec<$List<'a>> {
+            let mut out = vec![];
+            match *inline {
+                Inline::Emph(ref $($m)* inlines) | Inline::Underline(ref $($m)* inlines) |
+                Inline::Strong(ref $($m)* inlines) | Inline::Strikeout(ref $($m)* inlines) |
+                Inline::Superscript(ref $($m)* inlines) | Inline::Subscript(ref $($m)* inlines) |
+                Inline::SmallCaps(ref $($m)* inlines) | Inline::Quoted(_, ref $($m)* inlines) |
+                Inline::Link(_, ref $($m)* inlines, _) | Inline::Image(_, ref $($m)* inlines, _) |
+                Inline::Span(_, ref $($m)* inlines) => out.push($List::Inlines(inlines)),
+                Inline::Cite(ref $($m)* citations, ref $($m)* inlines) => {
+                    for citation in citations {
+                        out.push($List::Inlines(& $($m)* citation.citation_prefix));
+                        out.push($List::Inlines(& $($m)* citation.citation_suffix));
+                    }
+                    out.push($List::Inlines(inlines))
+                },
+                Inline::Note(ref $($m)* blocks) => out.push($List::Blocks(blocks)),
+                _ => ()
+            }
+            out
+        }
+    }
+}
+
+child_lists!(block_lists, inline_lists, ListRef,);
+child_lists!(block_lists_mut, inline_lists_mut, List, mut);
+
+impl<'a> List<'a> {
+    fn len(&self) -> usize {
+        match *self {
+            List::Blocks(ref v) => v.len(),
+            List::Inlines(ref v) => v.len()
+        }
+    }
+
+    /// The `which`th child list of the node at `index`.
+    fn into_child(self, index: usize, which: usize) -> List<'a> {
+        let lists = match self {
+            List::Blocks(v) => block_lists_mut(&mut v[index]),
+            List::Inlines(v) => inline_lists_mut(&mut v[index])
+        };
+        lists.into_iter().nth(which).expect("cursor path out of date")
+    }
+
+    fn child_lists(self, index: usize) -> Vec<List<'a>> {
+        match self {
+            List::Blocks(v) => block_lists_mut(&mut v[index]),
+            List::Inlines(v) => inline_lists_mut(&mut v[index])
+        }
+    }
+
+    fn insert(self, index: usize, item: Item) -> Result<(), Item> {
+        match (self, item) {
+            (List::Blocks(v), Item::Block(b)) => v.insert(index, b),
+            (List::Inlines(v), Item::Inline(i)) => v.insert(index, i),
+            (_, item) => return Err(item)
+        }
+        Ok(())
+    }
+
+    fn replace(self, index: usize, item: Item) -> Result<Item, Item> {
+        match (self, item) {
+            (List::Blocks(v), Item::Block(b)) => Ok(Item::Block(mem::replace(&mut v[index], b))),
+            (List::Inlines(v), Item::Inline(i)) => Ok(Item::Inline(mem::replace(&mut v[index], i))),
+            (_, item) => Err(item)
+        }
+    }
+
+    fn drain(self, index: usize, count: usize) -> Vec<Item> {
+        match self {
+            List::Blocks(v) => v.drain(index..index + count).map(Item::Block).collect(),
+            List::Inlines(v) => v.drain(index..index + count).map(Item::Inline).collect()
+        }
+    }
+
+    /// Appends `items` if they are all of this list's kind.
+    fn append(self, items: Vec<Item>) -> Result<(), Vec<Item>> {
+        match self {
+            List::Blocks(v) if items.iter().all(|i| if let Item::Block(_) = *i { true } else { false }) =>
+                v.extend(items.into_iter().filter_map(|i| if let Item::Block(b) = i { Some(b) } else { None })),
+            List::Inlines(v) if items.iter().all(|i| if let Item::Inline(_) = *i { true } else { false }) =>
+                v.extend(items.into_iter().filter_map(|i| if let Item::Inline(i) = i { Some(i) } else { None })),
+            _ => return Err(items)
+        }
+        Ok(())
+    }
+}
+
+impl<'a> ListRef<'a> {
+    fn len(&self) -> usize {
+        match *self {
+            ListRef::Blocks(v) => v.len(),
+            ListRef::Inlines(v) => v.len()
+        }
+    }
+
+    fn child_lists(self, index: usize) -> Vec<ListRef<'a>> {
+        match self {
+            ListRef::Blocks(v) => block_lists(&v[index]),
+            ListRef::Inlines(v) => inline_lists(&v[index])
+        }
+    }
+
+    fn into_child(self, index: usize, which: usize) -> ListRef<'a> {
+        let lists = match self {
+            ListRef::Blocks(v) => block_lists(&v[index]),
+            ListRef::Inlines(v) => inline_lists(&v[index])
+        };
+        lists.into_iter().nth(which).expect("cursor path out of date")
+    }
+
+    fn get(self, index: usize) -> Option<Node<'a>> {
+        match self {
+            ListRef::Blocks(v) => v.get(index).map(Node::Block),
+            ListRef::Inlines(v) => v.get(index).map(Node::Inline)
+        }
+    }
+}
+
+/// A position in a document's blocks and inlines, from which the nodes
+/// around it can be reached and changed.
+///
+/// The children of a node are kept in one or more lists: a `Para` has one,
+/// a `BulletList` one per item, a `Table` one per cell. Siblings are the nodes
+/// of the same list, and `next_list` and `prev_list` move between the lists
+/// of the same parent. The cursor is always on a node, except after the last
+/// block of the document was removed.
+pub struct Cursor<'a> {
+    doc: &'a mut Pandoc,
+    // For each level, the child list of the parent (always 0 at the top) and
+    // the index in it.
+    path: Vec<(usize, usize)>
+}
+
+impl<'a> Cursor<'a> {
+    /// A cursor on the first block of `doc`.
+    pub fn new(doc: &'a mut Pandoc) -> Cursor<'a> {
+        Cursor { doc: doc, path: vec![(0, 0)] }
+    }
+
+    fn list<'b>(&'b mut self) -> List<'b> {
+        let mut list = List::Blocks(&mut self.doc.1);
+        for pair in self.path.windows(2) {
+            list = list.into_child(pair[0].1, pair[1].0);
+        }
+        list
+    }
+
+    fn list_ref<'b>(&'b self) -> ListRef<'b> {
+        let mut list = ListRef::Blocks(&self.doc.1);
+        for pair in self.path.windows(2) {
+            list = list.into_child(pair[0].1, pair[1].0);
+        }
+        list
+    }
+
+    /// The length of each child list of the parent, or of the document's
+    /// blocks at the top level.
+    fn list_lens(&self) -> Vec<usize> {
+        let depth = self.path.len();
+        if depth < 2 {
+            return vec![self.doc.1.len()];
+        }
+        let mut list = ListRef::Blocks(&self.doc.1);
+        for pair in self.path[..depth - 1].windows(2) {
+            list = list.into_child(pair[0].1, pair[1].0);
+        }
+        list.child_lists(self.path[depth - 2].1).iter().map(|l| l.len()).collect()
+    }
+
+    fn index(&self) -> usize {
+        self.path[self.path.len() - 1].1
+    }
+
+    fn set_index(&mut self, index: usize) {
+        let last = self.path.len() - 1;
+        self.path[last].1 = index;
+    }
+
+    /// The node under the cursor.
+    pub fn node<'b>(&'b self) -> Option<Node<'b>> {
+        self.list_ref().get(self.index())
+    }
+
+    pub fn block(&self) -> Option<&Block> {
+        match self.node() {
+            Some(Node::Block(b)) => Some(b),
+            _ => None
+        }
+    }
+
+    pub fn inline(&self) -> Option<&Inline> {
+        match self.node() {
+            Some(Node::Inline(i)) => Some(i),
+            _ => None
+        }
+    }
+
+    pub fn block_mut(&mut self) -> Option<&mut Block> {
+        let index = self.index();
+        match self.list() {
+            List::Blocks(v) => v.get_mut(index),
+            _ => None
+        }
+    }
+
+    pub fn inline_mut(&mut self) -> Option<&mut Inline> {
+        let index = self.index();
+        match self.list() {
+            List::Inlines(v) => v.get_mut(index),
+            _ => None
+        }
+    }
+
+    /// Moves to the node containing this one. Returns false at the top level.
+    pub fn parent(&mut self) -> bool {
+        if self.path.len() > 1 {
+            self.path.pop();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Moves to the next node of the same list. Returns false at its end.
+    pub fn next_sibling(&mut self) -> bool {
+        let index = self.index() + 1;
+        if index < self.list().len() {
+            self.set_index(index);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Moves to the previous node of the same list. Returns false at its start.
+    pub fn prev_sibling(&mut self) -> bool {
+        let index = self.index();
+        if index > 0 && index <= self.list().len() {
+            self.set_index(index - 1);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Moves to the first node of the first non-empty child list. Returns
+    /// false if the node has no children. The other lists are reached with
+    /// `next_list`.
+    pub fn first_child(&mut self) -> bool {
+        let index = self.index();
+        if index >= self.list().len() {
+            return false;
+        }
+        let which = self.list().child_lists(index).iter().position(|l| l.len() > 0);
+        match which {
+            Some(which) => {
+                self.path.push((which, 0));
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Moves to the first node of the next non-empty child list of the
+    /// parent: the next item of a list, the next cell of a table, the body of
+    /// a `Figure` after its caption. Returns false if there is none.
+    pub fn next_list(&mut self) -> bool {
+        let lens = self.list_lens();
+        let last = self.path.len() - 1;
+        match (self.path[last].0 + 1..lens.len()).find(|&i| lens[i] > 0) {
+            Some(which) => {
+                self.path[last] = (which, 0);
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Moves to the first node of the previous non-empty child list of the
+    /// parent. Returns false if there is none.
+    pub fn prev_list(&mut self) -> bool {
+        let lens = self.list_lens();
+        let last = self.path.len() - 1;
+        match (0..self.path[last].0).rev().find(|&i| lens[i] > 0) {
+            Some(which) => {
+                self.path[last] = (which, 0);
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Puts `item` in place of the node under the cursor and returns that
+    /// node. `item` is given back if it isn't of the same kind.
+    pub fn replace<I: Into<Item>>(&mut self, item: I) -> Result<Item, Item> {
+        let index = self.index();
+        if index >= self.list().len() {
+            return Err(item.into());
+        }
+        self.list().replace(index, item.into())
+    }
+
+    /// Inserts `item` before the node under the cursor, which stays on it.
+    pub fn insert_before<I: Into<Item>>(&mut self, item: I) -> Result<(), Item> {
+        let index = self.index();
+        try!(self.list().insert(index, item.into()));
+        if self.index() < self.list().len() - 1 {
+            self.set_index(index + 1);
+        }
+        Ok(())
+    }
+
+    /// Inserts `item` after the node under the cursor, which stays on it.
+    pub fn insert_after<I: Into<Item>>(&mut self, item: I) -> Result<(), Item> {
+        let index = self.index();
+        let at = if index < self.list().len() { index + 1 } else { index };
+        self.list().insert(at, item.into())
+    }
+
+    /// Takes out the node under the cursor, and moves to the next sibling,
+    /// else to the previous one, else to the parent.
+    pub fn remove(&mut self) -> Option<Item> {
+        let index = self.index();
+        let len = self.list().len();
+        if index >= len {
+            return None;
+        }
+        let removed = self.list().drain(index, 1).pop();
+        if index == len - 1 {
+            if index > 0 {
+                self.set_index(index - 1);
+            } else {
+                self.parent();
+            }
+        }
+        removed
+    }
+
+    /// Moves `count` nodes, starting with the one under the cursor, to the
+    /// end of the first child list of `wrapper` and puts `wrapper` in their
+    /// place, under the cursor. `wrapper` is given back if that isn't
+    /// possible: not enough siblings, or children of another kind.
+    pub fn wrap_in<I: Into<Item>>(&mut self, count: usize, wrapper: I) -> Result<(), Item> {
+        let mut wrapper = wrapper.into();
+        let index = self.index();
+        if count == 0 || index + count > self.list().len() {
+            return Err(wrapper);
+        }
+        let fits = {
+            let kind_ok = match (self.list(), &wrapper) {
+                (List::Blocks(_), &Item::Block(_)) | (List::Inlines(_), &Item::Inline(_)) => true,
+                _ => false
+            };
+            let lists = match wrapper {
+                Item::Block(ref mut b) => block_lists_mut(b),
+                Item::Inline(ref mut i) => inline_lists_mut(i)
+            };
+            let child_ok = match (self.list(), lists.first()) {
+                (List::Blocks(_), Some(&List::Blocks(_))) |
+                (List::Inlines(_), Some(&List::Inlines(_))) => true,
+                _ => false
+            };
+            kind_ok && child_ok
+        };
+        if !fits {
+            return Err(wrapper);
+        }
+        let items = self.list().drain(index, count);
+        {
+            let first = match wrapper {
+                Item::Block(ref mut b) => block_lists_mut(b).into_iter().next(),
+                Item::Inline(ref mut i) => inline_lists_mut(i).into_iter().next()
+            };
+            first.expect("checked above").append(items).expect("checked above");
+        }
+        self.list().insert(index, wrapper).expect("checked above");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use types::*;
+    use test_util::{text, attr, doc};
+    use iter::Node;
+    use cursor::{Cursor, Item};
+
+    fn code(cursor: &Cursor) -> Option<String> {
+        match cursor.block() {
+            Some(&Block::CodeBlock(_, ref text)) => Some(text.clone()),
+            _ => None
+        }
+    }
+
+    #[test]
+    fn merge_code_blocks() {
+        let mut d = doc(vec![Block::CodeBlock(attr(""), String::from("a")),
+                             Block::CodeBlock(attr(""), String::from("b")),
+                             Block::HorizontalRule,
+                             Block::CodeBlock(attr(""), String::from("c")),
+                             Block::CodeBlock(attr(""), String::from("d"))]);
+        {
+            let mut cursor = Cursor::new(&mut d);
+            loop {
+                if let Some(second) = code(&cursor) {
+                    if cursor.prev_sibling() {
+                        if let Some(first) = code(&cursor) {
+                            cursor.replace(Block::CodeBlock(attr(""), first + "\n" + &second)).unwrap();
+                            cursor.next_sibling();
+                            cursor.remove();
+                            continue;
+                        }
+                        cursor.next_sibling();
+                    }
+                }
+                if !cursor.next_sibling() {
+                    break;
+                }
+            }
+        }
+        assert_eq!(d.1, vec![Block::CodeBlock(attr(""), String::from("a\nb")),
+                             Block::HorizontalRule,
+                             Block::CodeBlock(attr(""), String::from("c\nd"))]);
+    }
+
+    #[test]
+    fn wrap_between_rules() {
+        let para = |s| Block::Para(vec![text(s)]);
+        let mut d = doc(vec![para("x"), Block::HorizontalRule, para("a"), para("b"),
+                             Block::HorizontalRule, para("y")]);
+        {
+            let mut cursor = Cursor::new(&mut d);
+            while cursor.block() != Some(&Block::HorizontalRule) {
+                cursor.next_sibling();
+            }
+            cursor.remove();
+            let mut count = 0;
+            while cursor.block() != Some(&Block::HorizontalRule) {
+                count += 1;
+                cursor.next_sibling();
+            }
+            cursor.remove();
+            for _ in 0..count {
+                cursor.prev_sibling();
+            }
+            cursor.wrap_in(count, Block::Div(attr(""), vec![])).unwrap();
+        }
+        assert_eq!(d.1, vec![para("x"), Block::Div(attr(""), vec![para("a"), para("b")]), para("y")]);
+    }
+
+    #[test]
+    fn caption_into_image() {
+        let target = (String::from("a.png"), String::new());
+        let mut d = doc(vec![Block::Para(vec![Inline::Image(attr(""), vec![], target.clone())]),
+                             Block::Para(vec![text("caption")])]);
+        {
+            let mut cursor = Cursor::new(&mut d);
+            cursor.next_sibling();
+            let caption = match cursor.remove() {
+                Some(Item::Block(Block::Para(inlines))) => inlines,
+                _ => panic!()
+            };
+            assert!(cursor.first_child());
+            if let Some(&mut Inline::Image(_, ref mut alt, _)) = cursor.inline_mut() {
+                *alt = caption;
+            }
+        }
+        assert_eq!(d.1, vec![Block::Para(vec![Inline::Image(attr(""), vec![text("caption")], target)])]);
+    }
+
+    #[test]
+    fn navigate_lists_and_cells() {
+        let cell = |s| Cell(attr(""), Alignment::AlignDefault, RowSpan(1), ColSpan(1), vec![Block::Plain(vec![text(s)])]);
+        let table = Block::Table(attr(""), Caption(None, vec![]), vec![], TableHead(attr(""), vec![]),
+                                 vec![TableBody(attr(""), RowHeadColumns(0), vec![],
+                                                vec![Row(attr(""), vec![cell("one"), cell("two")])])],
+                                 TableFoot(attr(""), vec![]));
+        let mut d = doc(vec![Block::BulletList(vec![vec![Block::Plain(vec![text("a")])],
+                                                    vec![Block::Plain(vec![text("b")])]]),
+                             table]);
+        let mut cursor = Cursor::new(&mut d);
+        assert!(!cursor.parent());
+        assert!(cursor.first_child());
+        assert_eq!(cursor.block(), Some(&Block::Plain(vec![text("a")])));
+        assert!(!cursor.next_sibling() && !cursor.prev_list());
+        assert!(cursor.next_list());
+        assert_eq!(cursor.block(), Some(&Block::Plain(vec![text("b")])));
+        assert!(!cursor.next_list());
+        assert!(cursor.prev_list());
+        assert!(cursor.first_child());
+        assert_eq!(cursor.node(), Some(Node::Inline(&text("a"))));
+        assert!(!cursor.next_list());
+        assert_eq!(cursor.replace(Block::HorizontalRule), Err(Item::Block(Block::HorizontalRule)));
+        assert!(cursor.parent() && cursor.parent() && cursor.next_sibling());
+        assert!(cursor.first_child());
+        assert!(cursor.next_list());
+        assert_eq!(cursor.block(), Some(&Block::Plain(vec![text("two")])));
+        assert!(cursor.prev_list());
+        assert_eq!(cursor.block(), Some(&Block::Plain(vec![text("one")])));
+        cursor.insert_after(Block::HorizontalRule).unwrap();
+        cursor.insert_before(Block::Null).unwrap();
+        assert_eq!(cursor.block(), Some(&Block::Plain(vec![text("one")])));
+        assert!(cursor.prev_sibling());
+        assert_eq!(cursor.remove(), Some(Item::Block(Block::Null)));
+        assert_eq!(cursor.block(), Some(&Block::Plain(vec![text("one")])));
+    }
+
+    #[test]
+    fn navigate_definitions_and_figures() {
+        let plain = |s| Block::Plain(vec![text(s)]);
+        let mut d = doc(vec![Block::DefinitionList(vec![(vec![text("term")], vec![vec![plain("one")], vec![plain("two")]])]),
+                             Block::Figure(attr(""), Caption(None, vec![plain("caption")]), vec![plain("body")])]);
+        let mut cursor = Cursor::new(&mut d);
+        assert!(cursor.first_child());
+        assert_eq!(cursor.inline(), Some(&text("term")));
+        assert!(cursor.next_list());
+        assert_eq!(cursor.block(), Some(&plain("one")));
+        assert!(cursor.next_list());
+        assert_eq!(cursor.block(), Some(&plain("two")));
+        assert!(cursor.parent() && cursor.next_sibling() && cursor.first_child());
+        assert_eq!(cursor.block(), Some(&plain("caption")));
+        assert!(cursor.next_list());
+        assert_eq!(cursor.block(), Some(&plain("body")));
+        assert!(cursor.remove().is_some());
+        assert!(cursor.block() == Some(&Block::Figure(attr(""), Caption(None, vec![plain("caption")]), vec![])));
+        assert!(!cursor.next_list());
+    }
+}
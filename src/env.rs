@@ -0,0 +1,141 @@
+use std::env;
+use serde_json::{self, Value};
+
+/// The format pandoc is writing, as given to filters: a base format and the
+/// extensions turned on (`+smart`) or off (`-raw_html`) after it.
+#[derive(Clone, PartialEq, Debug)]
+pub struct OutputFormat {
+    pub base: String,
+    pub enabled: Vec<String>,
+    pub disabled: Vec<String>
+}
+
+impl OutputFormat {
+    /// Splits a format such as `html5+smart-raw_html`.
+    pub fn parse(format: &str) -> OutputFormat {
+        let end = format.find(|c| c == '+' || c == '-').unwrap_or(format.len());
+        let mut output = OutputFormat { base: String::from(&format[..end]), enabled: vec![], disabled: vec![] };
+        let mut rest = &format[end..];
+        while !rest.is_empty() {
+            let next = rest[1..].find(|c| c == '+' || c == '-').map_or(rest.len(), |i| i + 1);
+            let name = String::from(&rest[1..next]);
+            if rest.starts_with('+') {
+                output.enabled.push(name);
+            } else {
+                output.disabled.push(name);
+            }
+            rest = &rest[next..];
+        }
+        output
+    }
+
+    /// Whether the base format starts with `name`, so that `"html"` matches
+    /// `html5` and `"latex"` matches `latex`.
+    pub fn is(&self, name: &str) -> bool {
+        self.base.starts_with(name)
+    }
+
+    /// `Some(true)` if the extension was turned on, `Some(false)` if it was
+    /// turned off, `None` if the format leaves it at pandoc's default.
+    pub fn extension(&self, name: &str) -> Option<bool> {
+        if self.enabled.iter().any(|e| e == name) {
+            Some(true)
+        } else if self.disabled.iter().any(|e| e == name) {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
+
+/// What pandoc tells a filter about the conversion it is part of: the
+/// output format as the first argument, and the version and reader options
+/// in `PANDOC_VERSION` and `PANDOC_READER_OPTIONS`. Each is `None` when not
+/// given, e.g. when the filter isn't run by pandoc.
+///
+/// Pandoc doesn't pass the names of the input files to JSON filters, so
+/// `input_files` is only filled in by whoever builds the context.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct FilterContext {
+    pub format: Option<OutputFormat>,
+    pub pandoc_version: Option<Vec<u64>>,
+    pub reader_options: Option<Value>,
+    pub input_files: Vec<String>
+}
+
+impl FilterContext {
+    /// The context of this process.
+    pub fn from_env() -> FilterContext {
+        let args: Vec<String> = env::args().collect();
+        FilterContext::new(&args, |name| env::var(name).ok())
+    }
+
+    /// The context given by the command line `args`, program name first,
+    /// and the environment variables read with `var`.
+    pub fn new<F>(args: &[String], var: F) -> FilterContext
+        where F: Fn(&str) -> Option<String> {
+        FilterContext {
+            format: args.get(1).map(|format| OutputFormat::parse(format)),
+            pandoc_version: var("PANDOC_VERSION").and_then(|v| {
+                v.split('.').map(|n| n.parse().ok()).collect()
+            }),
+            reader_options: var("PANDOC_READER_OPTIONS").and_then(|o| serde_json::from_str(&o).ok()),
+            input_files: vec![]
+        }
+    }
+
+    /// A context with only an output format, e.g. to test a filter.
+    pub fn with_format(format: &str) -> FilterContext {
+        FilterContext { format: Some(OutputFormat::parse(format)), ..FilterContext::default() }
+    }
+
+    /// Whether pandoc is writing `name`, as by `OutputFormat::is`.
+    pub fn is_format(&self, name: &str) -> bool {
+        self.format.as_ref().map_or(false, |format| format.is(name))
+    }
+
+    /// One of the reader options, e.g. `"standalone"` or `"abbreviations"`.
+    pub fn reader_option(&self, name: &str) -> Option<&Value> {
+        self.reader_options.as_ref().and_then(|o| o.as_object()).and_then(|o| o.get(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use env::{FilterContext, OutputFormat};
+
+    #[test]
+    fn parse_format() {
+        let format = OutputFormat::parse("html5+smart-raw_html+footnotes");
+        assert_eq!(format.base, "html5");
+        assert_eq!(format.enabled, vec!["smart", "footnotes"]);
+        assert_eq!(format.disabled, vec!["raw_html"]);
+        assert!(format.is("html") && !format.is("latex"));
+        assert_eq!(format.extension("smart"), Some(true));
+        assert_eq!(format.extension("raw_html"), Some(false));
+        assert_eq!(format.extension("emoji"), None);
+        assert_eq!(OutputFormat::parse("latex"),
+                   OutputFormat { base: String::from("latex"), enabled: vec![], disabled: vec![] });
+    }
+
+    #[test]
+    fn from_args_and_vars() {
+        let args = vec![String::from("filter"), String::from("latex-smart")];
+        let context = FilterContext::new(&args, |name| match name {
+            "PANDOC_VERSION" => Some(String::from("3.1.11")),
+            "PANDOC_READER_OPTIONS" => Some(String::from(r#"{"standalone":true,"columns":72}"#)),
+            _ => None
+        });
+        assert!(context.is_format("latex"));
+        assert_eq!(context.format.as_ref().and_then(|f| f.extension("smart")), Some(false));
+        assert_eq!(context.pandoc_version, Some(vec![3, 1, 11]));
+        assert_eq!(context.reader_option("standalone").and_then(|v| v.as_bool()), Some(true));
+        assert_eq!(context.input_files, Vec::<String>::new());
+
+        let empty = FilterContext::new(&args[..1], |name| match name {
+            "PANDOC_VERSION" => Some(String::from("dev")),
+            _ => Some(String::from("{"))
+        });
+        assert_eq!(empty, FilterContext::default());
+    }
+}
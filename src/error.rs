@@ -0,0 +1,122 @@
+use std::error;
+use std::fmt;
+use std::io;
+use serde_json;
+
+use types::ApiVersion;
+
+/// Everything that can go wrong between reading a document and writing the
+/// filtered one. Errors about a part of the document carry the JSON path of
+/// that part, such as `$.blocks[2].c[1][0]`.
+#[derive(Debug)]
+pub enum FilterError {
+    /// The input is not JSON, or the node at `path` doesn't have the shape of
+    /// the pandoc type it should be.
+    MalformedJson { path: String, message: String },
+    /// The `"t"` of the node at `path` is not the name of any pandoc constructor.
+    UnknownTag { path: String, tag: String },
+    /// The document's `pandoc-api-version` is outside the range `codec` supports.
+    UnsupportedVersion(ApiVersion),
+    /// The filter failed on the node at `path`.
+    Filter { path: String, message: String },
+    /// Reading the input or writing the output failed.
+    Io(io::Error)
+}
+
+impl FilterError {
+    /// The JSON path of the part of the document the error is about.
+    pub fn path(&self) -> &str {
+        match *self {
+            FilterError::MalformedJson { ref path, .. } => path,
+            FilterError::UnknownTag { ref path, .. } => path,
+            FilterError::UnsupportedVersion(_) => "$[\"pandoc-api-version\"]",
+            FilterError::Filter { ref path, .. } => path,
+            FilterError::Io(_) => "$"
+        }
+    }
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FilterError::MalformedJson { ref path, ref message } =>
+                write!(f, "malformed JSON at {}: {}", path, message),
+            FilterError::UnknownTag { ref path, ref tag } =>
+                write!(f, "unknown node tag {:?} at {}", tag, path),
+            FilterError::UnsupportedVersion(ref version) => {
+                let version: Vec<String> = version.iter().map(|v| v.to_string()).collect();
+                write!(f, "unsupported pandoc-api-version {}", version.join("."))
+            },
+            FilterError::Filter { ref path, ref message } =>
+                write!(f, "filter failed at {}: {}", path, message),
+            FilterError::Io(ref e) => write!(f, "{}", e)
+        }
+    }
+}
+
+impl error::Error for FilterError {
+    fn description(&self) -> &str {
+        match *self {
+            FilterError::MalformedJson { .. } => "malformed JSON",
+            FilterError::UnknownTag { .. } => "unknown node tag",
+            FilterError::UnsupportedVersion(_) => "unsupported pandoc-api-version",
+            FilterError::Filter { .. } => "filter failed",
+            FilterError::Io(ref e) => error::Error::description(e)
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            FilterError::Io(ref e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for FilterError {
+    fn from(e: io::Error) -> FilterError {
+        FilterError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for FilterError {
+    fn from(e: serde_json::Error) -> FilterError {
+        match e {
+            serde_json::Error::Io(e) => FilterError::Io(e),
+            e => FilterError::MalformedJson { path: String::from("$"), message: e.to_string() }
+        }
+    }
+}
+
+/// Appends an object key to a JSON path.
+pub fn key_path(path: &str, key: &str) -> String {
+    if !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        format!("{}.{}", path, key)
+    } else {
+        format!("{}[{:?}]", path, key)
+    }
+}
+
+/// Appends an array index to a JSON path.
+pub fn index_path(path: &str, index: usize) -> String {
+    format!("{}[{}]", path, index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths() {
+        assert_eq!(index_path(&key_path("$", "blocks"), 2), "$.blocks[2]");
+        assert_eq!(key_path("$.meta", "link-citations"), r#"$.meta["link-citations"]"#);
+    }
+
+    #[test]
+    fn display() {
+        let e = FilterError::UnknownTag { path: String::from("$.blocks[0]"), tag: String::from("Foo") };
+        assert_eq!(e.to_string(), r#"unknown node tag "Foo" at $.blocks[0]"#);
+        assert_eq!(FilterError::UnsupportedVersion(vec![1, 24]).to_string(),
+                   "unsupported pandoc-api-version 1.24");
+    }
+}
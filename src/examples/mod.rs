@@ -1,4 +1,5 @@
-use types::{Inline, Block};
+use types::{Inline, Block, Format};
+use env::FilterContext;
 
 pub fn to_upper(inline: Inline) -> Inline {
     match inline {
@@ -19,3 +20,23 @@ pub fn behead(header: Block) -> Block {
         e => e
     }
 }
+
+pub fn page_break(div: Block, context: &FilterContext) -> Block {
+    match div {
+        Block::Div(attrs, blocks) => {
+            if blocks.is_empty() && attrs.1.iter().any(|c| c == "pagebreak") {
+                if context.is_format("html") {
+                    Block::RawBlock(Format(String::from("html")),
+                                    String::from("<div style=\"page-break-after: always\"></div>"))
+                } else if context.is_format("latex") {
+                    Block::RawBlock(Format(String::from("latex")), String::from("\\newpage"))
+                } else {
+                    Block::Div(attrs, blocks)
+                }
+            } else {
+                Block::Div(attrs, blocks)
+            }
+        },
+        e => e
+    }
+}
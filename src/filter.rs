@@ -0,0 +1,352 @@
+use types::{Pandoc, Meta, MetaValue, Block, Inline, Citation, Attr, Target, Format};
+use types::{QuoteType, MathType, ListAttributes, ColSpec, Caption};
+use types::{TableHead, TableBody, TableFoot, Row, Cell};
+use env::FilterContext;
+
+/// Generates `Filter` and the functions dispatching a node to its hook. Each
+/// constructor `X` of `Inline` or `Block` gets a hook called with the fields
+/// of `X`, which by default rebuilds the `X`.
+macro_rules! filter_hooks {
+    (inline_units = { $( $iunit:ident => $iunit_hook:ident ),* },
+     inlines = { $( $inline:ident => $inline_hook:ident($( $ifield:ident : $ity:ty ),*) ),* },
+     block_units = { $( $bunit:ident => $bunit_hook:ident ),* },
+     blocks = { $( $block:ident => $block_hook:ident($( $bfield:ident : $bty:ty ),*) ),* }) => {
+        /// A filter made of hooks, like a pandoc Lua filter. Override the
+        /// hooks for the nodes to change; the others leave nodes as they are.
+        ///
+        /// `apply` runs the hooks in phases, as pandoc runs a Lua filter: every
+        /// inline of the document, metadata included, goes through `on_inline`
+        /// first, then every block through `on_block`, then the metadata
+        /// through `on_meta`, and finally the whole document through
+        /// `on_pandoc`. Within a phase nodes are filtered bottom-up, so a block
+        /// hook sees the already filtered inlines and blocks inside it. The
+        /// defaults of `on_inline` and `on_block` call the hook of the node's
+        /// constructor, e.g. `on_str` or `on_header`.
+        pub trait Filter {
+            fn on_inline(&mut self, inline: Inline) -> Inline {
+                match inline {
+                    $( Inline::$iunit => self.$iunit_hook(), )*
+                    $( Inline::$inline($( $ifield ),*) => self.$inline_hook($( $ifield ),*), )*
+                }
+            }
+
+            fn on_block(&mut self, block: Block) -> Block {
+                match block {
+                    $( Block::$bunit => self.$bunit_hook(), )*
+                    $( Block::$block($( $bfield ),*) => self.$block_hook($( $bfield ),*), )*
+                }
+            }
+
+            $(
+                fn $iunit_hook(&mut self) -> Inline {
+                    Inline::$iunit
+                }
+            )*
+
+            $(
+                fn $inline_hook(&mut self, $( $ifield: $ity ),*) -> Inline {
+                    Inline::$inline($( $ifield ),*)
+                }
+            )*
+
+            $(
+                fn $bunit_hook(&mut self) -> Block {
+                    Block::$bunit
+                }
+            )*
+
+            $(
+                fn $block_hook(&mut self, $( $bfield: $bty ),*) -> Block {
+                    Block::$block($( $bfield ),*)
+                }
+            )*
+
+            fn on_meta(&mut self, meta: Meta) -> Meta {
+                meta
+            }
+
+            fn on_pandoc(&mut self, pandoc: Pandoc) -> Pandoc {
+                pandoc
+            }
+
+            /// Called by `apply_with_context` before any other hook, for
+            /// filters that depend on the output format or pandoc's options.
+            fn on_context(&mut self, _context: &FilterContext) {}
+
+            /// Like `apply`, first passing `context` to `on_context`.
+            fn apply_with_context(&mut self, pandoc: Pandoc, context: &FilterContext) -> Pandoc
+                where Self: Sized {
+                self.on_context(context);
+                self.apply(pandoc)
+            }
+
+            /// Runs every hook over `pandoc`, one phase after the other.
+            fn apply(&mut self, pandoc: Pandoc) -> Pandoc
+                where Self: Sized {
+                let Pandoc(meta, blocks, version) = pandoc;
+                let (meta, blocks) = filter_document(self, Phase::Inlines, meta, blocks);
+                let (meta, blocks) = filter_document(self, Phase::Blocks, meta, blocks);
+                let meta = self.on_meta(meta);
+                self.on_pandoc(Pandoc(meta, blocks, version))
+            }
+        }
+    }
+}
+
+filter_hooks!(
+    inline_units = {
+        Space => on_space,
+        SoftBreak => on_soft_break,
+        LineBreak => on_line_break
+    },
+    inlines = {
+        Str => on_str(text: String),
+        Emph => on_emph(content: Vec<Inline>),
+        Underline => on_underline(content: Vec<Inline>),
+        Strong => on_strong(content: Vec<Inline>),
+        Strikeout => on_strikeout(content: Vec<Inline>),
+        Superscript => on_superscript(content: Vec<Inline>),
+        Subscript => on_subscript(content: Vec<Inline>),
+        SmallCaps => on_small_caps(content: Vec<Inline>),
+        Quoted => on_quoted(quote_type: QuoteType, content: Vec<Inline>),
+        Cite => on_cite(citations: Vec<Citation>, content: Vec<Inline>),
+        Code => on_code(attr: Attr, code: String),
+        Math => on_math(math_type: MathType, math: String),
+        RawInline => on_raw_inline(format: Format, raw: String),
+        Link => on_link(attr: Attr, content: Vec<Inline>, target: Target),
+        Image => on_image(attr: Attr, content: Vec<Inline>, target: Target),
+        Note => on_note(blocks: Vec<Block>),
+        Span => on_span(attr: Attr, content: Vec<Inline>)
+    },
+    block_units = {
+        HorizontalRule => on_horizontal_rule,
+        Null => on_null
+    },
+    blocks = {
+        Plain => on_plain(content: Vec<Inline>),
+        Para => on_para(content: Vec<Inline>),
+        LineBlock => on_line_block(lines: Vec<Vec<Inline>>),
+        CodeBlock => on_code_block(attr: Attr, code: String),
+        RawBlock => on_raw_block(format: Format, raw: String),
+        BlockQuote => on_block_quote(blocks: Vec<Block>),
+        OrderedList => on_ordered_list(list: ListAttributes, items: Vec<Vec<Block>>),
+        BulletList => on_bullet_list(items: Vec<Vec<Block>>),
+        DefinitionList => on_definition_list(items: Vec<(Vec<Inline>, Vec<Vec<Block>>)>),
+        Header => on_header(level: u64, attr: Attr, content: Vec<Inline>),
+        Table => on_table(attr: Attr, caption: Caption, col_specs: Vec<ColSpec>,
+                          head: TableHead, bodies: Vec<TableBody>, foot: TableFoot),
+        Figure => on_figure(attr: Attr, caption: Caption, blocks: Vec<Block>),
+        Div => on_div(attr: Attr, blocks: Vec<Block>)
+    }
+);
+
+/// The kind of node whose hooks run during a walk of `apply`.
+#[derive(Clone, Copy, PartialEq)]
+enum Phase {
+    Inlines,
+    Blocks
+}
+
+fn filter_document<F: Filter>(f: &mut F, phase: Phase, meta: Meta, blocks: Vec<Block>)
+                              -> (Meta, Vec<Block>) {
+    let blocks = filter_blocks(f, phase, blocks);
+    let meta = Meta {
+        un_meta: meta.un_meta.into_iter().map(|(k, v)| (k, filter_meta_value(f, phase, v))).collect()
+    };
+    (meta, blocks)
+}
+
+fn filter_meta_value<F: Filter>(f: &mut F, phase: Phase, value: MetaValue) -> MetaValue {
+    match value {
+        MetaValue::MetaMap(map) =>
+            MetaValue::MetaMap(map.into_iter().map(|(k, v)| (k, filter_meta_value(f, phase, v))).collect()),
+        MetaValue::MetaList(values) =>
+            MetaValue::MetaList(values.into_iter().map(|v| filter_meta_value(f, phase, v)).collect()),
+        MetaValue::MetaInlines(inlines) => MetaValue::MetaInlines(filter_inlines(f, phase, inlines)),
+        MetaValue::MetaBlocks(blocks) => MetaValue::MetaBlocks(filter_blocks(f, phase, blocks)),
+        e => e
+    }
+}
+
+fn filter_blocks<F: Filter>(f: &mut F, phase: Phase, blocks: Vec<Block>) -> Vec<Block> {
+    blocks.into_iter().map(|b| filter_block(f, phase, b)).collect()
+}
+
+fn filter_items<F: Filter>(f: &mut F, phase: Phase, items: Vec<Vec<Block>>) -> Vec<Vec<Block>> {
+    items.into_iter().map(|blocks| filter_blocks(f, phase, blocks)).collect()
+}
+
+fn filter_block<F: Filter>(f: &mut F, phase: Phase, block: Block) -> Block {
+    let block = match block {
+        Block::Plain(inlines) => Block::Plain(filter_inlines(f, phase, inlines)),
+        Block::Para(inlines) => Block::Para(filter_inlines(f, phase, inlines)),
+        Block::LineBlock(lines) =>
+            Block::LineBlock(lines.into_iter().map(|line| filter_inlines(f, phase, line)).collect()),
+        Block::BlockQuote(blocks) => Block::BlockQuote(filter_blocks(f, phase, blocks)),
+        Block::OrderedList(list, items) => Block::OrderedList(list, filter_items(f, phase, items)),
+        Block::BulletList(items) => Block::BulletList(filter_items(f, phase, items)),
+        Block::DefinitionList(items) => {
+            Block::DefinitionList(items.into_iter()
+                                  .map(|(term, defs)| (filter_inlines(f, phase, term), filter_items(f, phase, defs)))
+                                  .collect())
+        },
+        Block::Header(level, attr, inlines) => Block::Header(level, attr, filter_inlines(f, phase, inlines)),
+        Block::Table(attr, caption, col_specs, head, bodies, foot) => {
+            let caption = filter_caption(f, phase, caption);
+            let head = TableHead(head.0, filter_rows(f, phase, head.1));
+            let bodies = bodies.into_iter()
+                .map(|TableBody(attr, row_head_columns, head, body)| {
+                    let head = filter_rows(f, phase, head);
+                    TableBody(attr, row_head_columns, head, filter_rows(f, phase, body))
+                })
+                .collect();
+            let foot = TableFoot(foot.0, filter_rows(f, phase, foot.1));
+            Block::Table(attr, caption, col_specs, head, bodies, foot)
+        },
+        Block::Figure(attr, caption, blocks) => {
+            let caption = filter_caption(f, phase, caption);
+            Block::Figure(attr, caption, filter_blocks(f, phase, blocks))
+        },
+        Block::Div(attr, blocks) => Block::Div(attr, filter_blocks(f, phase, blocks)),
+        b => b
+    };
+    if phase == Phase::Blocks { f.on_block(block) } else { block }
+}
+
+fn filter_caption<F: Filter>(f: &mut F, phase: Phase, caption: Caption) -> Caption {
+    let Caption(short, blocks) = caption;
+    let short = short.map(|short| filter_inlines(f, phase, short));
+    Caption(short, filter_blocks(f, phase, blocks))
+}
+
+fn filter_rows<F: Filter>(f: &mut F, phase: Phase, rows: Vec<Row>) -> Vec<Row> {
+    rows.into_iter()
+        .map(|Row(attr, cells)| {
+            Row(attr, cells.into_iter()
+                .map(|Cell(attr, alignment, row_span, col_span, blocks)|
+                     Cell(attr, alignment, row_span, col_span, filter_blocks(f, phase, blocks)))
+                .collect())
+        })
+        .collect()
+}
+
+fn filter_inlines<F: Filter>(f: &mut F, phase: Phase, inlines: Vec<Inline>) -> Vec<Inline> {
+    inlines.into_iter().map(|i| filter_inline(f, phase, i)).collect()
+}
+
+fn filter_inline<F: Filter>(f: &mut F, phase: Phase, inline: Inline) -> Inline {
+    let inline = match inline {
+        Inline::Emph(inlines) => Inline::Emph(filter_inlines(f, phase, inlines)),
+        Inline::Underline(inlines) => Inline::Underline(filter_inlines(f, phase, inlines)),
+        Inline::Strong(inlines) => Inline::Strong(filter_inlines(f, phase, inlines)),
+        Inline::Strikeout(inlines) => Inline::Strikeout(filter_inlines(f, phase, inlines)),
+        Inline::Superscript(inlines) => Inline::Superscript(filter_inlines(f, phase, inlines)),
+        Inline::Subscript(inlines) => Inline::Subscript(filter_inlines(f, phase, inlines)),
+        Inline::SmallCaps(inlines) => Inline::SmallCaps(filter_inlines(f, phase, inlines)),
+        Inline::Quoted(quote_type, inlines) => Inline::Quoted(quote_type, filter_inlines(f, phase, inlines)),
+        Inline::Cite(citations, inlines) => {
+            let citations = citations.into_iter()
+                .map(|c| {
+                    let prefix = filter_inlines(f, phase, c.citation_prefix);
+                    Citation { citation_prefix: prefix,
+                               citation_suffix: filter_inlines(f, phase, c.citation_suffix),
+                               ..c }
+                })
+                .collect();
+            Inline::Cite(citations, filter_inlines(f, phase, inlines))
+        },
+        Inline::Link(attr, inlines, target) => Inline::Link(attr, filter_inlines(f, phase, inlines), target),
+        Inline::Image(attr, inlines, target) => Inline::Image(attr, filter_inlines(f, phase, inlines), target),
+        Inline::Span(attr, inlines) => Inline::Span(attr, filter_inlines(f, phase, inlines)),
+        Inline::Note(blocks) => Inline::Note(filter_blocks(f, phase, blocks)),
+        i => i
+    };
+    if phase == Phase::Inlines { f.on_inline(inline) } else { inline }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use filter::Filter;
+    use types::*;
+    use test_util::{text, attr, attr_with, doc};
+
+    /// Records the order the hooks are called in.
+    struct Trace(Vec<String>);
+
+    impl Filter for Trace {
+        fn on_str(&mut self, text: String) -> Inline {
+            self.0.push(format!("str {}", text));
+            Inline::Str(text.to_uppercase())
+        }
+
+        fn on_header(&mut self, level: u64, attr: Attr, content: Vec<Inline>) -> Block {
+            self.0.push(format!("header {}", level));
+            Block::Header(level + 1, attr, content)
+        }
+
+        fn on_code_block(&mut self, attr: Attr, code: String) -> Block {
+            self.0.push(String::from("code block"));
+            Block::CodeBlock(attr, code.trim().to_string())
+        }
+
+        fn on_meta(&mut self, mut meta: Meta) -> Meta {
+            self.0.push(String::from("meta"));
+            meta.un_meta.insert(String::from("filtered"), MetaValue::MetaBool(true));
+            meta
+        }
+
+        fn on_pandoc(&mut self, pandoc: Pandoc) -> Pandoc {
+            self.0.push(String::from("pandoc"));
+            pandoc
+        }
+    }
+
+    #[test]
+    fn hooks_in_phases() {
+        let mut un_meta = BTreeMap::new();
+        un_meta.insert(String::from("title"), MetaValue::MetaInlines(vec![text("t")]));
+        let doc = Pandoc(Meta { un_meta: un_meta },
+                         vec![Block::Header(1, attr(""), vec![text("h")]),
+                              Block::CodeBlock(attr(""), String::from(" x ")),
+                              Block::Para(vec![Inline::Emph(vec![text("p")])])],
+                         Some(vec![1, 23]));
+        let mut trace = Trace(vec![]);
+        let Pandoc(meta, blocks, _) = trace.apply(doc);
+        assert_eq!(trace.0, vec!["str h", "str p", "str t", "header 1", "code block", "meta", "pandoc"]);
+        assert_eq!(blocks, vec![Block::Header(2, attr(""), vec![text("H")]),
+                                Block::CodeBlock(attr(""), String::from("x")),
+                                Block::Para(vec![Inline::Emph(vec![text("P")])])]);
+        assert_eq!(meta.un_meta["title"], MetaValue::MetaInlines(vec![text("T")]));
+        assert_eq!(meta.un_meta["filtered"], MetaValue::MetaBool(true));
+    }
+
+    /// Unwraps every `Span` and drops every `Div` class.
+    struct Unwrap;
+
+    impl Filter for Unwrap {
+        fn on_inline(&mut self, inline: Inline) -> Inline {
+            match inline {
+                Inline::Span(_, mut content) => if content.len() == 1 {
+                    content.remove(0)
+                } else {
+                    Inline::Span(attr(""), content)
+                },
+                e => e
+            }
+        }
+
+        fn on_div(&mut self, (id, _, kvs): Attr, blocks: Vec<Block>) -> Block {
+            Block::Div((id, vec![], kvs), blocks)
+        }
+    }
+
+    #[test]
+    fn generic_hook_and_containers() {
+        let doc = doc(vec![Block::Div(attr_with("", &["c"], &[]),
+                                      vec![Block::Plain(vec![Inline::Span(attr(""),
+                                                                          vec![text("a")])])])]);
+        let Pandoc(_, blocks, _) = Unwrap.apply(doc);
+        assert_eq!(blocks, vec![Block::Div(attr(""), vec![Block::Plain(vec![text("a")])])]);
+    }
+}
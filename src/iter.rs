@@ -0,0 +1,257 @@
+use types::{Pandoc, MetaValue, Block, Inline, Citation, Caption, Row};
+
+/// Anything in a document that holds or is a node, borrowed from it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Node<'a> {
+    Block(&'a Block),
+    Inline(&'a Inline),
+    Citation(&'a Citation),
+    /// An entry of the metadata or of a `MetaMap`.
+    MetaEntry(&'a String, &'a MetaValue),
+    /// An element of a `MetaList`.
+    MetaValue(&'a MetaValue)
+}
+
+impl<'a> Node<'a> {
+    /// The nodes directly inside this one, in document order.
+    pub fn children(self) -> Vec<Node<'a>> {
+        let mut out = vec![];
+        match self {
+            Node::Block(block) => push_block_children(block, &mut out),
+            Node::Inline(inline) => push_inline_children(inline, &mut out),
+            Node::Citation(citation) => {
+                push_inlines(&citation.citation_prefix, &mut out);
+                push_inlines(&citation.citation_suffix, &mut out)
+            },
+            Node::MetaEntry(_, value) | Node::MetaValue(value) => push_meta_value_children(value, &mut out)
+        }
+        out
+    }
+}
+
+fn push_block_children<'a>(block: &'a Block, out: &mut Vec<Node<'a>>) {
+    match *block {
+        Block::Plain(ref inlines) | Block::Para(ref inlines) |
+        Block::Header(_, _, ref inlines) => push_inlines(inlines, out),
+        Block::LineBlock(ref lines) => for line in lines {
+            push_inlines(line, out)
+        },
+        Block::BlockQuote(ref blocks) | Block::Div(_, ref blocks) => push_blocks(blocks, out),
+        Block::OrderedList(_, ref items) | Block::BulletList(ref items) => for item in items {
+            push_blocks(item, out)
+        },
+        Block::DefinitionList(ref items) => for &(ref term, ref defs) in items {
+            push_inlines(term, out);
+            for def in defs {
+                push_blocks(def, out)
+            }
+        },
+        Block::Table(_, ref caption, _, ref head, ref bodies, ref foot) => {
+            push_caption(caption, out);
+            push_rows(&head.1, out);
+            for body in bodies {
+                push_rows(&body.2, out);
+                push_rows(&body.3, out);
+            }
+            push_rows(&foot.1, out);
+        },
+        Block::Figure(_, ref caption, ref blocks) => {
+            push_caption(caption, out);
+            push_blocks(blocks, out)
+        },
+        _ => ()
+    }
+}
+
+fn push_inline_children<'a>(inline: &'a Inline, out: &mut Vec<Node<'a>>) {
+    match *inline {
+        Inline::Emph(ref inlines) | Inline::Underline(ref inlines) |
+        Inline::Strong(ref inlines) | Inline::Strikeout(ref inlines) |
+        Inline::Superscript(ref inlines) | Inline::Subscript(ref inlines) |
+        Inline::SmallCaps(ref inlines) | Inline::Quoted(_, ref inlines) |
+        Inline::Link(_, ref inlines, _) | Inline::Image(_, ref inlines, _) |
+        Inline::Span(_, ref inlines) => push_inlines(inlines, out),
+        Inline::Cite(ref citations, ref inlines) => {
+            out.extend(citations.iter().map(Node::Citation));
+            push_inlines(inlines, out)
+        },
+        Inline::Note(ref blocks) => push_blocks(blocks, out),
+        _ => ()
+    }
+}
+
+fn push_meta_value_children<'a>(value: &'a MetaValue, out: &mut Vec<Node<'a>>) {
+    match *value {
+        MetaValue::MetaMap(ref map) => out.extend(map.iter().map(|(k, v)| Node::MetaEntry(k, v))),
+        MetaValue::MetaList(ref values) => out.extend(values.iter().map(Node::MetaValue)),
+        MetaValue::MetaInlines(ref inlines) => push_inlines(inlines, out),
+        MetaValue::MetaBlocks(ref blocks) => push_blocks(blocks, out),
+        _ => ()
+    }
+}
+
+fn push_inlines<'a>(inlines: &'a [Inline], out: &mut Vec<Node<'a>>) {
+    out.extend(inlines.iter().map(Node::Inline))
+}
+
+fn push_blocks<'a>(blocks: &'a [Block], out: &mut Vec<Node<'a>>) {
+    out.extend(blocks.iter().map(Node::Block))
+}
+
+fn push_caption<'a>(caption: &'a Caption, out: &mut Vec<Node<'a>>) {
+    if let Some(ref short) = caption.0 {
+        push_inlines(short, out)
+    }
+    push_blocks(&caption.1, out)
+}
+
+fn push_rows<'a>(rows: &'a [Row], out: &mut Vec<Node<'a>>) {
+    for row in rows {
+        for cell in &row.1 {
+            push_blocks(&cell.4, out)
+        }
+    }
+}
+
+/// Depth-first iterator over every node of a document, each before its
+/// children, in document order: the metadata first, then the blocks.
+pub struct Nodes<'a> {
+    stack: Vec<Node<'a>>
+}
+
+impl<'a> Nodes<'a> {
+    /// Iterates over `roots` and everything inside them.
+    pub fn new(mut roots: Vec<Node<'a>>) -> Nodes<'a> {
+        roots.reverse();
+        Nodes { stack: roots }
+    }
+}
+
+impl<'a> Iterator for Nodes<'a> {
+    type Item = Node<'a>;
+
+    fn next(&mut self) -> Option<Node<'a>> {
+        let node = self.stack.pop();
+        if let Some(node) = node {
+            self.stack.extend(node.children().into_iter().rev());
+        }
+        node
+    }
+}
+
+/// Defines an iterator over the nodes of one kind, out of `Nodes`.
+macro_rules! node_iterator {
+    ($(#[$doc:meta])* $name:ident, $item:ty, $pattern:pat => $value:expr) => {
+        $(#[$doc])*
+        pub struct $name<'a>(Nodes<'a>);
+
+        impl<'a> Iterator for $name<'a> {
+            type Item = $item;
+
+            fn next(&mut self) -> Option<$item> {
+                for node in &mut self.0 {
+                    if let $pattern = node {
+                        return Some($value);
+                    }
+                }
+                None
+            }
+        }
+    }
+}
+
+node_iterator!(
+    /// Every `Block` of a document, in document order.
+    Blocks, &'a Block, Node::Block(b) => b);
+node_iterator!(
+    /// Every `Inline` of a document, in document order.
+    Inlines, &'a Inline, Node::Inline(i) => i);
+node_iterator!(
+    /// Every `Citation` of a document, in document order.
+    Citations, &'a Citation, Node::Citation(c) => c);
+node_iterator!(
+    /// Every entry of the metadata, including those of nested `MetaMap`s.
+    MetaEntries, (&'a String, &'a MetaValue), Node::MetaEntry(k, v) => (k, v));
+
+/// Iterators that borrow the document. There are no mutable counterparts:
+/// a block and the blocks inside it can't both be lent out mutably at once.
+/// `WalkableMut::walk_mut` edits every node in place instead.
+impl Pandoc {
+    pub fn nodes<'a>(&'a self) -> Nodes<'a> {
+        let mut roots: Vec<Node<'a>> = self.0.un_meta.iter().map(|(k, v)| Node::MetaEntry(k, v)).collect();
+        push_blocks(&self.1, &mut roots);
+        Nodes::new(roots)
+    }
+
+    pub fn blocks_iter<'a>(&'a self) -> Blocks<'a> {
+        Blocks(self.nodes())
+    }
+
+    pub fn inlines_iter<'a>(&'a self) -> Inlines<'a> {
+        Inlines(self.nodes())
+    }
+
+    pub fn citations_iter<'a>(&'a self) -> Citations<'a> {
+        Citations(self.nodes())
+    }
+
+    pub fn meta_iter<'a>(&'a self) -> MetaEntries<'a> {
+        MetaEntries(self.nodes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use types::*;
+    use test_util::{text, attr};
+
+    fn doc() -> Pandoc {
+        let mut author = BTreeMap::new();
+        author.insert(String::from("name"), MetaValue::MetaInlines(vec![text("Ann")]));
+        let mut un_meta = BTreeMap::new();
+        un_meta.insert(String::from("authors"), MetaValue::MetaList(vec![MetaValue::MetaMap(author)]));
+        let citation = Citation { citation_id: String::from("doe"), citation_prefix: vec![text("see")],
+                                  citation_suffix: vec![], citation_mode: CitationMode::NormalCitation,
+                                  citation_note_num: 1, citation_hash: 0 };
+        let cell = Cell(attr(""), Alignment::AlignDefault, RowSpan(1), ColSpan(1),
+                        vec![Block::Plain(vec![text("cell")])]);
+        Pandoc(Meta { un_meta: un_meta },
+               vec![Block::Header(1, attr(""), vec![text("Intro")]),
+                    Block::BulletList(vec![vec![Block::Para(vec![
+                        Inline::Note(vec![Block::Para(vec![Inline::Cite(vec![citation], vec![text("c")])])])
+                    ])]]),
+                    Block::DefinitionList(vec![(vec![text("term")], vec![vec![Block::Plain(vec![text("def")])]])]),
+                    Block::Table(attr(""), Caption(None, vec![]), vec![],
+                                 TableHead(attr(""), vec![Row(attr(""), vec![cell])]), vec![],
+                                 TableFoot(attr(""), vec![]))],
+               None)
+    }
+
+    #[test]
+    fn blocks_in_document_order() {
+        let doc = doc();
+        let tags: Vec<&str> = doc.blocks_iter().map(|b| b.tag()).collect();
+        assert_eq!(tags, vec!["Header", "BulletList", "Para", "Para", "DefinitionList", "Plain",
+                              "Table", "Plain"]);
+        let headers: Vec<u64> = doc.blocks_iter().filter_map(|b| match *b {
+            Block::Header(level, _, _) => Some(level),
+            _ => None
+        }).collect();
+        assert_eq!(headers, vec![1]);
+    }
+
+    #[test]
+    fn inlines_citations_and_meta() {
+        let doc = doc();
+        let text: Vec<&str> = doc.inlines_iter().filter_map(|i| match *i {
+            Inline::Str(ref s) => Some(&s[..]),
+            _ => None
+        }).collect();
+        assert_eq!(text, vec!["Ann", "Intro", "see", "c", "term", "def", "cell"]);
+        let ids: Vec<&str> = doc.citations_iter().map(|c| &c.citation_id[..]).collect();
+        assert_eq!(ids, vec!["doe"]);
+        let keys: Vec<&str> = doc.meta_iter().map(|(k, _)| &k[..]).collect();
+        assert_eq!(keys, vec!["authors", "name"]);
+    }
+}
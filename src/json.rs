@@ -1,65 +1,201 @@
+use serde::{Serialize, Deserialize};
 use serde_json;
 use serde_json::Value;
 use std::process::{Command, Stdio};
-use std::io::{Write, Read, Error};
-use std::collections::BTreeMap;
+use std::io::{Write, Read, Error, BufReader, BufWriter};
+use std::fmt::Display;
 
-use types::Pandoc;
+use types::{Pandoc, ApiVersion, AtVersion};
+use types::{Block, Inline, MetaValue, ListNumberStyle, ListNumberDelim, Alignment, ColWidth,
+            QuoteType, MathType, CitationMode};
+use codec;
+use value_ref::from_value_ref;
+use error::{FilterError, key_path, index_path};
 use walk::Walkable;
+use try_walk::TryWalkable;
+use filter::Filter;
+use two_pass::TwoPass;
+use meta::MetaView;
+use pipeline::Pipeline;
+use env::FilterContext;
 
 impl Pandoc {
-    pub fn new_from_json(meta: Value, blocks: Value) -> Self {
-        let converted_meta = serde_json::from_value(meta).unwrap();
-        let converted_blocks = serde_json::from_value(from_pandoc(blocks)).unwrap();
-        Pandoc(converted_meta, converted_blocks)
-    }
-}
-
-pub fn from_pandoc(entry: Value) -> Value {
-    match entry {
-        Value::Object(obj) => {
-            let mut new_entry = BTreeMap::new();
-            let t = String::from(obj.get("t").unwrap().as_str().unwrap());
-            let c = obj.get("c").unwrap().clone();
-            let new_c = from_pandoc(c);
-            new_entry.insert(t, new_c);
-            Value::Object(new_entry)
-        },
-        Value::Array(arr) => {
-            let mut array = Vec::new();
-            for item in arr {
-                array.push(from_pandoc(item));
-            }
-            Value::Array(array)
-        },
-        _ => entry
-    }
-}
-
-pub fn to_pandoc(entry: Value) -> Value {
-    match entry {
-        Value::Object(obj) => {
-            let mut new_entry = BTreeMap::new();
-            if !obj.is_empty() {
-                let (key, value) = obj.into_iter().next().unwrap();
-                if key == "unMeta" {
-                    new_entry.insert(String::from("unMeta"), value);
-                } else {
-                    let new_c = to_pandoc(value);
-                    new_entry.insert(String::from("c"), new_c);
-                    new_entry.insert(String::from("t"), Value::String(key));
-                }
-            }
-            Value::Object(new_entry)
-        },
-        Value::Array(arr) => {
-            let mut array = Vec::new();
-            for item in arr {
-                array.push(to_pandoc(item));
+    pub fn new_from_json(meta: Value, blocks: Value) -> Result<Self, FilterError> {
+        let converted_meta = try!(decode(&meta, "$[0]"));
+        let converted_blocks = try!(decode(&blocks, "$[1]"));
+        Ok(Pandoc(converted_meta, converted_blocks, None))
+    }
+
+    /// Reads either the legacy `[meta, blocks]` array or the
+    /// `{"pandoc-api-version", "meta", "blocks"}` object emitted since pandoc 1.18.
+    pub fn from_json(json: Value) -> Result<Self, FilterError> {
+        let pandoc = try!(decode(&json, "$"));
+        check_version(pandoc)
+    }
+
+    /// Like `from_json`, but deserializes the document straight from its text.
+    pub fn from_json_str(json: &str) -> Result<Self, FilterError> {
+        let pandoc = match serde_json::from_str(json) {
+            Ok(pandoc) => pandoc,
+            Err(e) => return Err(match serde_json::from_str(json) {
+                Ok(value) => decode_error(&value, "$", e),
+                Err(e) => FilterError::from(e)
+            })
+        };
+        check_version(pandoc)
+    }
+
+    /// Writes the document back in the layout it was read with, echoing its
+    /// `pandoc-api-version` if it had one.
+    pub fn to_json(&self) -> Value {
+        self.to_json_at(&self.2)
+    }
+
+    /// Writes the document for the given API version, `None` being the legacy
+    /// array layout. See `codec` for what is lost when downgrading.
+    pub fn to_json_at(&self, version: &Option<ApiVersion>) -> Value {
+        let json = serde_json::to_value(&AtVersion(self, version));
+        if self.needs_downgrade(version) {
+            codec::downgrade(json, version)
+        } else {
+            json
+        }
+    }
+
+    /// Reads a document from `reader` without holding its whole text. The
+    /// reader is buffered, so it can be given unbuffered input such as a file.
+    ///
+    /// The JSON is read into a `Value` and decoded by `from_json`, so errors
+    /// are reported as by `from_json_str`, with the JSON path of the node.
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, FilterError> {
+        let json: Value = try!(serde_json::from_reader(BufReader::new(reader)));
+        Pandoc::from_json(json)
+    }
+
+    /// Writes the document to `writer` for the version it was read with,
+    /// through a buffer that is flushed before returning.
+    pub fn to_writer<W: Write>(&self, writer: &mut W, output: Output) -> Result<(), FilterError> {
+        let mut writer = BufWriter::new(writer);
+        if self.needs_downgrade(&self.2) {
+            try!(output.write(&mut writer, &self.to_json()));
+        } else {
+            try!(output.write(&mut writer, self));
+        }
+        try!(writer.flush());
+        Ok(())
+    }
+
+    /// Pretty-prints the document for the version it was read with. Documents
+    /// at the latest API version are serialized without an intermediate `Value`.
+    pub fn to_json_string(&self) -> Result<String, FilterError> {
+        let json = if self.needs_downgrade(&self.2) {
+            try!(serde_json::ser::to_string_pretty(&self.to_json()))
+        } else {
+            try!(serde_json::ser::to_string_pretty(self))
+        };
+        Ok(json)
+    }
+
+    /// Whether writing for `version` has to go through `codec::downgrade`,
+    /// which is also where `Null` blocks are dropped from 1.22 on.
+    fn needs_downgrade(&self, version: &Option<ApiVersion>) -> bool {
+        codec::needs_downgrade(version) ||
+            codec::drops_null(version) && self.blocks_iter().any(|b| *b == Block::Null)
+    }
+}
+
+/// How `to_writer` and `filter_reader` lay out the JSON they write.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Output {
+    /// Everything on one line, as pandoc itself writes it.
+    Compact,
+    /// Indented, one value per line.
+    Pretty
+}
+
+impl Output {
+    fn write<W: Write, T: Serialize>(self, writer: &mut W, value: &T) -> Result<(), serde_json::Error> {
+        match self {
+            Output::Compact => serde_json::ser::to_writer(writer, value),
+            Output::Pretty => serde_json::ser::to_writer_pretty(writer, value)
+        }
+    }
+}
+
+fn check_version(pandoc: Pandoc) -> Result<Pandoc, FilterError> {
+    if codec::is_supported(&pandoc.2) {
+        Ok(pandoc)
+    } else {
+        Err(FilterError::UnsupportedVersion(pandoc.2.unwrap_or_default()))
+    }
+}
+
+fn decode<T: Deserialize>(json: &Value, path: &str) -> Result<T, FilterError> {
+    from_value_ref(json).map_err(|e| decode_error(json, path, e))
+}
+
+/// Explains why `json`, found at `path`, failed to deserialize with `error`,
+/// by finding the innermost node that is to blame.
+fn decode_error(json: &Value, path: &str, error: serde_json::Error) -> FilterError {
+    let version = json.as_object()
+        .and_then(|obj| obj.get("pandoc-api-version"))
+        .and_then(|v| from_value_ref::<ApiVersion>(v).ok());
+    if let Some(version) = version {
+        if !codec::is_supported(&Some(version.clone())) {
+            return FilterError::UnsupportedVersion(version);
+        }
+    }
+    match find_culprit(json, path) {
+        Some((path, Some(tag))) => FilterError::UnknownTag { path: path, tag: tag },
+        Some((path, None)) => FilterError::MalformedJson { path: path, message: error.to_string() },
+        None => FilterError::MalformedJson { path: String::from(path), message: error.to_string() }
+    }
+}
+
+/// Whether a node tagged `tag` deserializes as the type that uses that tag,
+/// or `None` if no type uses it.
+fn decodes(tag: &str, node: &Value) -> Option<bool> {
+    fn ok<T: Deserialize>(node: &Value) -> bool {
+        from_value_ref::<T>(node).is_ok()
+    }
+    let checks: &[(&[&str], fn(&Value) -> bool)] = &[
+        (Block::TAGS, ok::<Block>),
+        (Inline::TAGS, ok::<Inline>),
+        (MetaValue::TAGS, ok::<MetaValue>),
+        (ListNumberStyle::TAGS, ok::<ListNumberStyle>),
+        (ListNumberDelim::TAGS, ok::<ListNumberDelim>),
+        (Alignment::TAGS, ok::<Alignment>),
+        (ColWidth::TAGS, ok::<ColWidth>),
+        (QuoteType::TAGS, ok::<QuoteType>),
+        (MathType::TAGS, ok::<MathType>),
+        (CitationMode::TAGS, ok::<CitationMode>)
+    ];
+    checks.iter()
+        .find(|&&(tags, _)| tags.contains(&tag))
+        .map(|&(_, check)| check(node))
+}
+
+/// The path of the innermost tagged node below `json` that fails to
+/// deserialize, with its tag if that tag is unknown. The children of a node
+/// are only searched if the node itself fails, so each node is decoded once
+/// for each of its failing ancestors rather than once for each ancestor.
+fn find_culprit(json: &Value, path: &str) -> Option<(String, Option<String>)> {
+    match *json {
+        Value::Object(ref obj) => {
+            if let Some(tag) = obj.get("t").and_then(|t| t.as_str()) {
+                let known = match decodes(tag, json) {
+                    Some(true) => return None,
+                    known => known.is_some()
+                };
+                let inner = obj.get("c").and_then(|c| find_culprit(c, &key_path(path, "c")));
+                return inner.or_else(|| Some((String::from(path),
+                                              if known { None } else { Some(String::from(tag)) })));
             }
-            Value::Array(array)
+            obj.iter().filter_map(|(k, v)| find_culprit(v, &key_path(path, k))).next()
         },
-        _ => entry
+        Value::Array(ref arr) =>
+            arr.iter().enumerate().filter_map(|(i, v)| find_culprit(v, &index_path(path, i))).next(),
+        _ => None
     }
 }
 
@@ -78,19 +214,107 @@ pub fn markdown_to_json(markdown: String) -> Result<String, Error> {
     Ok(s)
 }
 
-pub fn filter<F, U: Walkable<U>>(json: String, f: &F) -> Result<String, String>
+pub fn filter<F, U: Walkable<U>>(json: String, f: &F) -> Result<String, FilterError>
     where F: Fn(U) -> U, Pandoc: Walkable<U> {
-    let value: Value = try!(serde_json::from_str(&json).map_err(|e| e.to_string()));
-    let arr: &Vec<Value> = try!(value.as_array().ok_or("Not an array"));
+    let pandoc = try!(Pandoc::from_json_str(&json));
+    pandoc.walk(f).to_json_string()
+}
 
-    if arr.len() != 2 {
-        return Err(String::from("Not valid Pandoc"))
-    }
-    let pandoc = Pandoc::new_from_json(arr[0].clone(), arr[1].clone());
-    let walked = to_pandoc(serde_json::to_value(&pandoc.walk(f)));
+/// Like `filter`, with `f` also given `context`, usually
+/// `FilterContext::from_env()`.
+pub fn filter_with_context<F, U: Walkable<U>>(json: String, f: &F, context: &FilterContext)
+                                              -> Result<String, FilterError>
+    where F: Fn(U, &FilterContext) -> U, Pandoc: Walkable<U> {
+    filter(json, &|x| f(x, context))
+}
 
-    let new_json = try!(serde_json::ser::to_string_pretty(&walked).map_err(|e| e.to_string()));
-    Ok(new_json)
+/// Like `filter`, with `f` also given a view of the metadata, through which
+/// it can record changes to it. Only the blocks are walked.
+pub fn filter_with_meta<F, U>(json: String, f: &F) -> Result<String, FilterError>
+    where F: Fn(U, &MetaView) -> U, Vec<Block>: Walkable<U> {
+    let pandoc = try!(Pandoc::from_json_str(&json));
+    pandoc.walk_with_meta(f).to_json_string()
+}
+
+/// Like `filter_with_meta`, with `f` also given `context`.
+pub fn filter_with_meta_and_context<F, U>(json: String, f: &F, context: &FilterContext)
+                                          -> Result<String, FilterError>
+    where F: Fn(U, &MetaView, &FilterContext) -> U, Vec<Block>: Walkable<U> {
+    filter_with_meta(json, &|x, meta: &MetaView| f(x, meta, context))
+}
+
+/// Like `filter`, running the hooks of a `Filter` as `Filter::apply` does.
+pub fn run_filter<F: Filter>(json: String, filter: &mut F) -> Result<String, FilterError> {
+    let pandoc = try!(Pandoc::from_json_str(&json));
+    filter.apply(pandoc).to_json_string()
+}
+
+/// Like `run_filter`, passing `context` to the filter's `on_context` first.
+pub fn run_filter_with_context<F: Filter>(json: String, filter: &mut F, context: &FilterContext)
+                                          -> Result<String, FilterError> {
+    let pandoc = try!(Pandoc::from_json_str(&json));
+    filter.apply_with_context(pandoc, context).to_json_string()
+}
+
+/// Like `filter`, reading the whole document with a `TwoPass` before
+/// rewriting it.
+pub fn run_two_pass<F: TwoPass>(json: String, filter: &mut F) -> Result<String, FilterError> {
+    let pandoc = try!(Pandoc::from_json_str(&json));
+    filter.apply(pandoc).to_json_string()
+}
+
+/// Like `run_two_pass`, passing `context` to the filter's `on_context` first.
+pub fn run_two_pass_with_context<F: TwoPass>(json: String, filter: &mut F, context: &FilterContext)
+                                             -> Result<String, FilterError> {
+    let pandoc = try!(Pandoc::from_json_str(&json));
+    filter.apply_with_context(pandoc, context).to_json_string()
+}
+
+/// Like `filter`, running a whole `Pipeline` over the document. `context` is
+/// what pandoc tells filters about the conversion, usually
+/// `FilterContext::from_env()`.
+pub fn run_pipeline(json: String, pipeline: &mut Pipeline, context: &FilterContext)
+                    -> Result<String, FilterError> {
+    let pandoc = try!(Pandoc::from_json_str(&json));
+    pipeline.run(pandoc, context).to_json_string()
+}
+
+/// Like `filter`, for filters that can fail. The first failure is returned as
+/// `FilterError::Filter`, with the path of the node the filter failed on.
+pub fn try_filter<F, U, E: Display>(json: String, f: &F) -> Result<String, FilterError>
+    where F: Fn(U) -> Result<U, E>, Pandoc: TryWalkable<U> {
+    let pandoc = try!(Pandoc::from_json_str(&json));
+    try!(pandoc.try_walk(f)).to_json_string()
+}
+
+/// Like `try_filter`, with `f` also given `context`.
+pub fn try_filter_with_context<F, U, E: Display>(json: String, f: &F, context: &FilterContext)
+                                                 -> Result<String, FilterError>
+    where F: Fn(U, &FilterContext) -> Result<U, E>, Pandoc: TryWalkable<U> {
+    try_filter(json, &|x| f(x, context))
+}
+
+/// Like `filter`, but reads the document from `reader` and writes the result
+/// to `writer`, so neither the input nor the output is held as a string. Both
+/// are buffered, as by `Pandoc::from_reader` and `Pandoc::to_writer`.
+pub fn filter_reader<R: Read, W: Write, F, U: Walkable<U>>(reader: R, mut writer: W, f: &F,
+                                                           output: Output)
+                                                           -> Result<(), FilterError>
+    where F: Fn(U) -> U, Pandoc: Walkable<U> {
+    let pandoc = try!(Pandoc::from_reader(reader));
+    try!(pandoc.walk(f).to_writer(&mut writer, output));
+    try!(writer.flush());
+    Ok(())
+}
+
+/// Like `filter_reader`, with `f` also given `context`. This is what a filter
+/// binary run by pandoc usually calls, with `FilterContext::from_env()`.
+pub fn filter_reader_with_context<R: Read, W: Write, F, U: Walkable<U>>(reader: R, writer: W, f: &F,
+                                                                        context: &FilterContext,
+                                                                        output: Output)
+                                                                        -> Result<(), FilterError>
+    where F: Fn(U, &FilterContext) -> U, Pandoc: Walkable<U> {
+    filter_reader(reader, writer, &|x| f(x, context), output)
 }
 
 #[cfg(test)]
@@ -98,45 +322,286 @@ mod tests {
     use super::*;
     use serde_json;
     use std::collections::BTreeMap;
+    use types::{Pandoc, Meta, MetaValue, Block, Inline, Format};
+    use error::FilterError;
+    use meta::MetaView;
+    use filter::Filter;
+    use two_pass::{two_pass, headers, Heading};
+    use env::FilterContext;
+    use examples::page_break;
+
+    #[test]
+    fn to_json() {
+        let json = markdown_to_json(String::from("# Test")).unwrap();
+        let pandoc = r#"[{"unMeta":{}},[{"t":"Header","c":[1,["test",[],[]],[{"t":"Str","c":"Test"}]]}]]"#;
+        let expected: serde_json::Value = serde_json::from_str(pandoc).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, expected);
+    }
 
     #[test]
-    fn convert_entry_object() {
-        let mut map = BTreeMap::new();
-        map.insert(String::from("Str"), serde_json::Value::String(String::from("Test")));
-        let expected = serde_json::Value::Object(map);
+    fn read_tagged_nodes() {
+        let json = r#"[{"unMeta":{}},[{"c":[{"t":"Space","c":[]},{"c":"a","t":"Str"}],"t":"Para"},{"t":"HorizontalRule"}]]"#;
+        let pandoc = Pandoc::from_json_str(json).unwrap();
+        let expected = Pandoc(Meta { un_meta: BTreeMap::new() },
+                              vec![Block::Para(vec![Inline::Space, Inline::Str(String::from("a"))]),
+                                   Block::HorizontalRule],
+                              None);
+        assert_eq!(pandoc, expected);
+    }
 
-        let mut object_builder = serde_json::builder::ObjectBuilder::new();
-        object_builder = object_builder.insert(String::from("t"), String::from("Str"));
-        object_builder = object_builder.insert(String::from("c"), String::from("Test"));
-        let object = object_builder.build();
-        let converted = from_pandoc(object);
+    #[test]
+    fn write_null_for_version() {
+        let at = |version: Vec<u64>| {
+            let blocks = vec![Block::Div((String::new(), vec![], vec![]), vec![Block::Null]), Block::Null];
+            let pandoc = Pandoc(Meta { un_meta: BTreeMap::new() }, blocks, Some(version));
+            pandoc.to_json_string().unwrap()
+        };
+        assert!(at(vec![1, 21]).contains("Null"));
+        assert!(!at(vec![1, 22]).contains("Null"));
+        assert!(!at(vec![1, 23, 1]).contains("Null"));
+    }
 
-        assert_eq!(converted, expected);
+    #[test]
+    fn read_unknown_tag() {
+        let json = r#"{"pandoc-api-version":[1,23],"meta":{},"blocks":[{"t":"Div","c":[["",[],[]],[{"t":"Paragraph","c":[]}]]}]}"#;
+        match Pandoc::from_json_str(json) {
+            Err(FilterError::UnknownTag { path, tag }) => {
+                assert_eq!(path, "$.blocks[0].c[1][0]");
+                assert_eq!(tag, "Paragraph");
+            },
+            r => panic!("unexpected result {:?}", r)
+        }
     }
 
     #[test]
-    fn convert_entry_array() {
-        let arr = vec![serde_json::Value::String(String::from("Test")),
-                           serde_json::Value::String(String::from("string"))];
-        let expected = serde_json::Value::Array(arr);
+    fn read_malformed_node() {
+        let json = r#"[{"unMeta":{"title":{"t":"MetaInlines","c":[{"t":"Str","c":1}]}}},[]]"#;
+        match Pandoc::from_json_str(json) {
+            Err(FilterError::MalformedJson { path, .. }) =>
+                assert_eq!(path, "$[0].unMeta.title.c[0]"),
+            r => panic!("unexpected result {:?}", r)
+        }
+    }
 
-        let mut array_builder = serde_json::builder::ArrayBuilder::new();
-        array_builder = array_builder.push(String::from("Test"));
-        array_builder = array_builder.push(String::from("string"));
-        let array = array_builder.build();
-        let converted = from_pandoc(array);
+    #[test]
+    fn read_citation_without_tag() {
+        let json = r#"{"pandoc-api-version":[1,23],"meta":{},"blocks":[{"t":"Para","c":[{"t":"Cite","c":[[{"citationId":"doe"}],[]]}]}]}"#;
+        match Pandoc::from_json_str(json) {
+            Err(FilterError::MalformedJson { path, .. }) =>
+                assert_eq!(path, "$.blocks[0].c[0]"),
+            r => panic!("unexpected result {:?}", r)
+        }
+    }
 
-        assert_eq!(converted, expected);
+    #[test]
+    fn read_invalid_json() {
+        match Pandoc::from_json_str("[{") {
+            Err(FilterError::MalformedJson { path, .. }) => assert_eq!(path, "$"),
+            r => panic!("unexpected result {:?}", r)
+        }
     }
 
     #[test]
-    fn to_json() {
-        let json = markdown_to_json(String::from("# Test")).unwrap();
-        let pandoc = r#"[{"unMeta":{}},[{"t":"Header","c":[1,["test",[],[]],[{"t":"Str","c":"Test"}]]}]]"#;
-        let expected: serde_json::Value = serde_json::from_str(pandoc).unwrap();
-        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+    fn filter_api_version_envelope() {
+        let json = r#"{"pandoc-api-version":[1,23,1],"meta":{"title":{"t":"MetaInlines","c":[{"t":"Str","c":"Doc"}]}},"blocks":[{"t":"Header","c":[2,["test",[],[]],[{"t":"Str","c":"Test"}]]}]}"#;
+        let filtered = filter(String::from(json), &::examples::behead).unwrap();
+        let expected = r#"{"pandoc-api-version":[1,23,1],"meta":{"title":{"t":"MetaInlines","c":[{"t":"Str","c":"Doc"}]}},"blocks":[{"t":"Para","c":[{"t":"Emph","c":[{"t":"Str","c":"Test"}]}]}]}"#;
+        let parsed: serde_json::Value = serde_json::from_str(&filtered).unwrap();
+        let expected: serde_json::Value = serde_json::from_str(expected).unwrap();
+        assert_eq!(parsed, expected);
+    }
+
+    #[test]
+    fn filter_keeps_old_api_version() {
+        let json = r#"{"pandoc-api-version":[1,20],"meta":{},"blocks":[{"t":"Table","c":[[],[{"t":"AlignDefault","c":[]}],[0.0],[[]],[[[{"t":"Plain","c":[{"t":"Str","c":"a"}]}]]]]}]}"#;
+        let filtered = filter(String::from(json), &::examples::to_upper).unwrap();
+        let expected = r#"{"pandoc-api-version":[1,20],"meta":{},"blocks":[{"t":"Table","c":[[],[{"t":"AlignDefault","c":[]}],[0.0],[[]],[[[{"t":"Plain","c":[{"t":"Str","c":"A"}]}]]]]}]}"#;
+        let parsed: serde_json::Value = serde_json::from_str(&filtered).unwrap();
+        let expected: serde_json::Value = serde_json::from_str(expected).unwrap();
+        assert_eq!(parsed, expected);
+    }
+
+    #[test]
+    fn unsupported_api_version() {
+        let json = r#"{"pandoc-api-version":[2,0],"meta":{},"blocks":[{"t":"Future"}]}"#;
+        match filter(String::from(json), &::examples::to_upper) {
+            Err(FilterError::UnsupportedVersion(version)) => assert_eq!(version, vec![2, 0]),
+            r => panic!("unexpected result {:?}", r)
+        }
+    }
+
+    #[test]
+    fn filter_legacy_array() {
+        let json = r#"[{"unMeta":{}},[{"t":"Header","c":[2,["test",[],[]],[{"t":"Str","c":"Test"}]]}]]"#;
+        let filtered = filter(String::from(json), &::examples::behead).unwrap();
+        let expected = r#"[{"unMeta":{}},[{"t":"Para","c":[{"t":"Emph","c":[{"t":"Str","c":"Test"}]}]}]]"#;
+        let parsed: serde_json::Value = serde_json::from_str(&filtered).unwrap();
+        let expected: serde_json::Value = serde_json::from_str(expected).unwrap();
+        assert_eq!(parsed, expected);
+    }
+
+    #[test]
+    fn filter_reader_compact() {
+        let json = r#"{"pandoc-api-version":[1,23,1],"meta":{},"blocks":[{"t":"Header","c":[2,["test",[],[]],[{"t":"Str","c":"Test"}]]}]}"#;
+        let mut out = Vec::new();
+        filter_reader(json.as_bytes(), &mut out, &::examples::behead, Output::Compact).unwrap();
+        let expected = r#"{"pandoc-api-version":[1,23,1],"meta":{},"blocks":[{"t":"Para","c":[{"t":"Emph","c":[{"t":"Str","c":"Test"}]}]}]}"#;
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn filter_reader_pretty_downgrade() {
+        let json = r#"[{"unMeta":{}},[{"t":"Plain","c":[{"t":"Str","c":"a"}]}]]"#;
+        let mut out = Vec::new();
+        filter_reader(json.as_bytes(), &mut out, &::examples::to_upper, Output::Pretty).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains('\n'));
+        let parsed: serde_json::Value = serde_json::from_str(&out).unwrap();
+        let expected: serde_json::Value =
+            serde_json::from_str(r#"[{"unMeta":{}},[{"t":"Plain","c":[{"t":"Str","c":"A"}]}]]"#).unwrap();
         assert_eq!(parsed, expected);
     }
 
+    #[test]
+    fn filter_reader_invalid_json() {
+        let mut out = Vec::new();
+        match filter_reader("[{".as_bytes(), &mut out, &::examples::behead, Output::Compact) {
+            Err(FilterError::MalformedJson { path, .. }) => assert_eq!(path, "$"),
+            r => panic!("unexpected result {:?}", r)
+        }
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn filter_reader_unknown_node() {
+        let json = r#"[{"unMeta":{}},[{"t":"Paragraph","c":[]}]]"#;
+        let mut out = Vec::new();
+        match filter_reader(json.as_bytes(), &mut out, &::examples::behead, Output::Compact) {
+            Err(FilterError::UnknownTag { path, tag }) => {
+                assert_eq!(path, "$[1][0]");
+                assert_eq!(tag, "Paragraph");
+            },
+            r => panic!("unexpected result {:?}", r)
+        }
+    }
+
+    #[test]
+    fn filter_reader_unsupported_version() {
+        let json = r#"{"pandoc-api-version":[2,0],"meta":{},"blocks":[{"t":"Paragraph","c":[]}]}"#;
+        let mut out = Vec::new();
+        match filter_reader(json.as_bytes(), &mut out, &::examples::behead, Output::Compact) {
+            Err(FilterError::UnsupportedVersion(version)) => assert_eq!(version, vec![2, 0]),
+            r => panic!("unexpected result {:?}", r)
+        }
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn try_filter_reports_node() {
+        let json = r#"{"pandoc-api-version":[1,23,1],"meta":{},"blocks":[{"t":"Para","c":[{"t":"Str","c":"ok"},{"t":"Str","c":"bad"}]}]}"#;
+        let result = try_filter(String::from(json), &(|i| match i {
+            Inline::Str(ref s) if s == "bad" => Err("no bad words"),
+            e => Ok(e)
+        }));
+        match result {
+            Err(FilterError::Filter { path, message }) => {
+                assert_eq!(path, "$.blocks[0].c[1]");
+                assert_eq!(message, "no bad words");
+            },
+            r => panic!("unexpected result {:?}", r)
+        }
+    }
+
+    struct Shout;
+
+    impl Filter for Shout {
+        fn on_str(&mut self, text: String) -> Inline {
+            Inline::Str(text.to_uppercase())
+        }
+    }
+
+    #[test]
+    fn run_filter_hooks() {
+        let json = r#"[{"unMeta":{}},[{"t":"Para","c":[{"t":"Str","c":"a"}]}]]"#;
+        let filtered = run_filter(String::from(json), &mut Shout).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&filtered).unwrap();
+        let expected: serde_json::Value =
+            serde_json::from_str(r#"[{"unMeta":{}},[{"t":"Para","c":[{"t":"Str","c":"A"}]}]]"#).unwrap();
+        assert_eq!(parsed, expected);
+    }
 
+    #[test]
+    fn filter_with_context_page_break() {
+        let json = r#"[{"unMeta":{}},[{"t":"Div","c":[["",["pagebreak"],[]],[]]}]]"#;
+        let to = |format: &str| {
+            let context = FilterContext::with_format(format);
+            let filtered = filter_with_context(String::from(json), &page_break, &context).unwrap();
+            Pandoc::from_json_str(&filtered).unwrap().1
+        };
+        assert_eq!(to("latex"), vec![Block::RawBlock(Format(String::from("latex")), String::from("\\newpage"))]);
+        assert_eq!(to("docx"), vec![Block::Div((String::new(), vec![String::from("pagebreak")], vec![]), vec![])]);
+    }
+
+    /// Writes strong text as small caps, except in formats without them.
+    #[derive(Default)]
+    struct SmallCapsStrong {
+        plain: bool
+    }
+
+    impl Filter for SmallCapsStrong {
+        fn on_context(&mut self, context: &FilterContext) {
+            self.plain = context.is_format("plain");
+        }
+
+        fn on_strong(&mut self, content: Vec<Inline>) -> Inline {
+            if self.plain { Inline::Strong(content) } else { Inline::SmallCaps(content) }
+        }
+    }
+
+    #[test]
+    fn run_filter_with_context_hook() {
+        let json = r#"[{"unMeta":{}},[{"t":"Para","c":[{"t":"Strong","c":[]}]}]]"#;
+        let to = |format: &str| {
+            let context = FilterContext::with_format(format);
+            let filtered = run_filter_with_context(String::from(json), &mut SmallCapsStrong::default(),
+                                                   &context).unwrap();
+            Pandoc::from_json_str(&filtered).unwrap().1
+        };
+        assert_eq!(to("html5"), vec![Block::Para(vec![Inline::SmallCaps(vec![])])]);
+        assert_eq!(to("plain"), vec![Block::Para(vec![Inline::Strong(vec![])])]);
+    }
+
+    #[test]
+    fn filter_with_meta_reads_lang() {
+        let json = r#"{"pandoc-api-version":[1,23,1],"meta":{"lang":{"t":"MetaString","c":"fr"}},"blocks":[{"t":"Para","c":[{"t":"Str","c":"hello"}]}]}"#;
+        let filtered = filter_with_meta(String::from(json), &|i, meta: &MetaView| match i {
+            Inline::Str(ref s) if s == "hello" && meta.get_string("lang") == Some(String::from("fr")) => {
+                meta.set("translated", MetaValue::MetaBool(true));
+                Inline::Str(String::from("bonjour"))
+            },
+            i => i
+        }).unwrap();
+        let parsed = Pandoc::from_json_str(&filtered).unwrap();
+        assert_eq!(parsed.1, vec![Block::Para(vec![Inline::Str(String::from("bonjour"))])]);
+        assert_eq!(parsed.0.un_meta.get("translated"), Some(&MetaValue::MetaBool(true)));
+    }
+
+    #[test]
+    fn run_two_pass_numbers_headers() {
+        let json = r#"[{"unMeta":{}},[{"t":"Header","c":[1,["a",[],[]],[]]},{"t":"Header","c":[1,["b",[],[]],[]]}]]"#;
+        let mut number = two_pass(headers, |headings: Vec<Heading>, mut doc: Pandoc| {
+            let mut numbers = headings.into_iter().map(|h| h.number[0].to_string());
+            doc.1 = doc.1.into_iter().map(|b| match b {
+                Block::Header(level, attr, _) => Block::Header(level, attr, vec![Inline::Str(numbers.next().unwrap())]),
+                b => b
+            }).collect();
+            doc
+        });
+        let filtered = run_two_pass(String::from(json), &mut number).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&filtered).unwrap();
+        let expected: serde_json::Value = serde_json::from_str(
+            r#"[{"unMeta":{}},[{"t":"Header","c":[1,["a",[],[]],[{"t":"Str","c":"1"}]]},{"t":"Header","c":[1,["b",[],[]],[{"t":"Str","c":"2"}]]}]]"#).unwrap();
+        assert_eq!(parsed, expected);
+    }
 }
@@ -1,10 +1,32 @@
 #![feature(custom_derive, plugin)]
 #![plugin(serde_macros)]
 
+#[macro_use]
 extern crate serde;
 extern crate serde_json;
+#[cfg(feature = "rayon")]
+extern crate rayon;
 
 pub mod types;
-mod walk;
+pub mod walk;
+pub mod query;
+pub mod splice;
+pub mod try_walk;
+pub mod context;
+pub mod filter;
+pub mod pipeline;
+pub mod two_pass;
+pub mod meta;
+pub mod env;
+pub mod selector;
+pub mod iter;
+pub mod cursor;
+#[cfg(feature = "rayon")]
+pub mod parallel;
 pub mod json;
+pub mod codec;
+pub mod error;
+mod value_ref;
+#[cfg(test)]
+mod test_util;
 pub mod examples;
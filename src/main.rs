@@ -1,15 +1,17 @@
 extern crate serde;
 extern crate serde_json;
 extern crate pandoc_filters;
-use pandoc_filters::json::filter;
+use pandoc_filters::json::{filter_reader, Output};
 use pandoc_filters::examples::behead;
 
-use std::io::{self, Read};
+use std::io::{self, Write};
+use std::process;
 
 fn main() {
-    let mut json = String::new();
-    io::stdin().read_to_string(&mut json).unwrap();
-    let new_json = filter(json, &behead).unwrap();
-    println!("{}", new_json);
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    if let Err(e) = filter_reader(stdin.lock(), stdout.lock(), &behead, Output::Compact) {
+        writeln!(io::stderr(), "{}", e).unwrap();
+        process::exit(1);
+    }
 }
-
@@ -0,0 +1,130 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use types::{Pandoc, Meta, MetaValue, Block};
+use walk::Walkable;
+use query::plain_text;
+
+/// The metadata of a document, as seen by a filter walking its blocks.
+/// Reads give the metadata as it was before the walk; `set` and `remove`
+/// are recorded and applied to the document once the walk is over.
+pub struct MetaView<'a> {
+    meta: &'a Meta,
+    changes: RefCell<BTreeMap<String, Option<MetaValue>>>
+}
+
+impl<'a> MetaView<'a> {
+    pub fn new(meta: &'a Meta) -> MetaView<'a> {
+        MetaView { meta: meta, changes: RefCell::new(BTreeMap::new()) }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&'a MetaValue> {
+        self.meta.un_meta.get(key)
+    }
+
+    /// The value of a `MetaBool`, or of a `MetaString` or `MetaInlines`
+    /// reading `true` or `false`, as YAML front matter often gives them.
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        match self.get_string(key) {
+            Some(ref s) if s == "true" => Some(true),
+            Some(ref s) if s == "false" => Some(false),
+            _ => match self.get(key) {
+                Some(&MetaValue::MetaBool(b)) => Some(b),
+                _ => None
+            }
+        }
+    }
+
+    /// The text of a `MetaString` or `MetaInlines`.
+    pub fn get_string(&self, key: &str) -> Option<String> {
+        match self.get(key) {
+            Some(&MetaValue::MetaString(ref s)) => Some(s.clone()),
+            Some(&MetaValue::MetaInlines(ref inlines)) => Some(plain_text(inlines)),
+            _ => None
+        }
+    }
+
+    /// Records that `key` is to be set to `value`.
+    pub fn set(&self, key: &str, value: MetaValue) {
+        self.changes.borrow_mut().insert(String::from(key), Some(value));
+    }
+
+    /// Records that `key` is to be removed.
+    pub fn remove(&self, key: &str) {
+        self.changes.borrow_mut().insert(String::from(key), None);
+    }
+
+    /// The recorded changes, the last one for each key, with `None` for
+    /// removals.
+    pub fn into_changes(self) -> BTreeMap<String, Option<MetaValue>> {
+        self.changes.into_inner()
+    }
+}
+
+impl Pandoc {
+    /// Like `walk` over the blocks of the document, with `f` also given the
+    /// metadata. The metadata itself isn't walked. Changes recorded through
+    /// the `MetaView` are applied at the end, the last one for each key
+    /// winning.
+    pub fn walk_with_meta<T, F>(self, f: &F) -> Pandoc
+        where F: Fn(T, &MetaView) -> T, Vec<Block>: Walkable<T> {
+        let Pandoc(mut meta, blocks, version) = self;
+        let (blocks, changes) = {
+            let view = MetaView::new(&meta);
+            let blocks = blocks.walk(&|x| f(x, &view));
+            (blocks, view.into_changes())
+        };
+        for (key, change) in changes {
+            match change {
+                Some(value) => meta.un_meta.insert(key, value),
+                None => meta.un_meta.remove(&key)
+            };
+        }
+        Pandoc(meta, blocks, version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use meta::MetaView;
+    use types::*;
+    use test_util::text;
+
+    fn doc(style: MetaValue) -> Pandoc {
+        let mut un_meta = BTreeMap::new();
+        un_meta.insert(String::from("company-style"), style);
+        un_meta.insert(String::from("draft"), MetaValue::MetaBool(true));
+        Pandoc(Meta { un_meta: un_meta }, vec![Block::Para(vec![text("acme"), Inline::Space, text("inc")])], None)
+    }
+
+    fn brand(i: Inline, meta: &MetaView) -> Inline {
+        match i {
+            Inline::Str(s) => if meta.get_bool("company-style") == Some(true) {
+                meta.set("branded", MetaValue::MetaBool(true));
+                meta.remove("draft");
+                Inline::Str(s.to_uppercase())
+            } else {
+                Inline::Str(s)
+            },
+            i => i
+        }
+    }
+
+    #[test]
+    fn read_meta() {
+        let on = doc(MetaValue::MetaInlines(vec![text("true")])).walk_with_meta(&brand);
+        assert_eq!(on.1, vec![Block::Para(vec![text("ACME"), Inline::Space, text("INC")])]);
+        let off = doc(MetaValue::MetaBool(false)).walk_with_meta(&brand);
+        assert_eq!(off.1, vec![Block::Para(vec![text("acme"), Inline::Space, text("inc")])]);
+    }
+
+    #[test]
+    fn record_changes() {
+        let on = doc(MetaValue::MetaBool(true)).walk_with_meta(&brand);
+        assert_eq!(on.0.un_meta.get("branded"), Some(&MetaValue::MetaBool(true)));
+        assert_eq!(on.0.un_meta.get("draft"), None);
+        let off = doc(MetaValue::MetaBool(false)).walk_with_meta(&brand);
+        assert_eq!(off.0.un_meta.get("branded"), None);
+        assert_eq!(off.0.un_meta.get("draft"), Some(&MetaValue::MetaBool(true)));
+    }
+}
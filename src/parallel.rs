@@ -0,0 +1,83 @@
+use rayon::prelude::*;
+use types::{Pandoc, Meta, Block};
+use walk::{Walkable, Traversal, BottomUp, TopDown, Descent};
+
+/// Like `Walkable`, but walks the elements of a list on the threads of
+/// rayon's pool. The results are put back in order, so a filter that gives
+/// the same output for the same node, whichever thread calls it, makes the
+/// same document as `walk` does.
+pub trait ParWalkable<T> : Sized {
+    fn par_walk_with<V>(self, v: &V) -> Self
+        where V : Traversal<T> + Sync;
+
+    /// Applies `f` to every `T`, children first.
+    fn par_walk<F>(self, f: &F) -> Self
+        where F : Fn(T) -> T + Sync {
+        self.par_walk_with(&BottomUp(f))
+    }
+
+    /// Applies `f` to every `T`, parents first.
+    fn par_walk_top_down<F>(self, f: &F) -> Self
+        where F : Fn(T) -> (T, Descent) + Sync {
+        self.par_walk_with(&TopDown(f))
+    }
+}
+
+impl<U, T> ParWalkable<U> for Vec<T>
+    where T : Walkable<U> + Send {
+    fn par_walk_with<V>(self, v: &V) -> Self
+        where V : Traversal<U> + Sync {
+        self.into_par_iter().map(|x| x.walk_with(v)).collect()
+    }
+}
+
+/// The metadata is walked first, on the calling thread, then the top-level
+/// blocks in parallel.
+impl<T> ParWalkable<T> for Pandoc
+    where Meta : Walkable<T>, Block : Walkable<T> {
+    fn par_walk_with<V>(self, v: &V) -> Self
+        where V : Traversal<T> + Sync {
+        Pandoc(self.0.walk_with(v), self.1.par_walk_with(v), self.2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use parallel::ParWalkable;
+    use walk::{Walkable, Descent};
+    use types::*;
+    use test_util::{text, attr};
+
+    fn doc() -> Pandoc {
+        let mut un_meta = BTreeMap::new();
+        un_meta.insert(String::from("title"), MetaValue::MetaInlines(vec![text("title")]));
+        let blocks = (0..200).map(|i| match i % 3 {
+            0 => Block::Para(vec![text(&i.to_string()), Inline::Emph(vec![text("x")])]),
+            1 => Block::BlockQuote(vec![Block::Plain(vec![text(&i.to_string())])]),
+            _ => Block::Header(2, attr(""), vec![text(&i.to_string())])
+        }).collect();
+        Pandoc(Meta { un_meta: un_meta }, blocks, None)
+    }
+
+    fn shout(i: Inline) -> Inline {
+        match i {
+            Inline::Str(s) => Inline::Str(s.to_uppercase() + "!"),
+            i => i
+        }
+    }
+
+    #[test]
+    fn par_walk_matches_walk() {
+        assert_eq!(doc().par_walk(&shout), doc().walk(&shout));
+    }
+
+    #[test]
+    fn par_walk_top_down() {
+        let unquote = |b: Block| match b {
+            Block::BlockQuote(blocks) => (Block::Div(attr(""), blocks), Descent::Stop),
+            b => (b, Descent::Continue)
+        };
+        assert_eq!(doc().par_walk_top_down(&unquote), doc().walk_top_down(&unquote));
+    }
+}
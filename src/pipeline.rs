@@ -0,0 +1,244 @@
+use types::Pandoc;
+use walk::Walkable;
+use filter::Filter;
+use two_pass::TwoPass;
+use env::FilterContext;
+
+type Step = Box<FnMut(Pandoc, &FilterContext) -> Pandoc>;
+
+fn step<F>(f: F) -> Step
+    where F: FnMut(Pandoc, &FilterContext) -> Pandoc + 'static {
+    Box::new(f)
+}
+
+/// A chain of filters run one after the other over the same document, so
+/// that it is only parsed and serialized once.
+///
+/// Filters are added with `walk`, `document`, `filter` and `two_pass`. The
+/// combinators `when`, `only_for_format` and `repeat_until_fixpoint` apply to
+/// everything added to the pipeline before them, and `then` appends another
+/// pipeline.
+pub struct Pipeline {
+    steps: Vec<Step>
+}
+
+impl Default for Pipeline {
+    fn default() -> Pipeline {
+        Pipeline::new()
+    }
+}
+
+impl Pipeline {
+    pub fn new() -> Pipeline {
+        Pipeline { steps: vec![] }
+    }
+
+    /// Adds a filter on every `T` in the document, e.g. an `Fn(Inline) -> Inline`.
+    pub fn walk<T, F>(mut self, f: F) -> Pipeline
+        where F: Fn(T) -> T + 'static, Pandoc: Walkable<T> {
+        self.steps.push(step(move |doc, _| doc.walk(&f)));
+        self
+    }
+
+    /// Adds a filter on the whole document.
+    pub fn document<F>(mut self, mut f: F) -> Pipeline
+        where F: FnMut(Pandoc) -> Pandoc + 'static {
+        self.steps.push(step(move |doc, _| f(doc)));
+        self
+    }
+
+    /// Adds a filter on the whole document that reads the output format and
+    /// the rest of the `FilterContext`.
+    pub fn document_in_context<F>(mut self, f: F) -> Pipeline
+        where F: FnMut(Pandoc, &FilterContext) -> Pandoc + 'static {
+        self.steps.push(step(f));
+        self
+    }
+
+    /// Adds a `Filter`, run with `Filter::apply`.
+    pub fn filter<F>(mut self, mut filter: F) -> Pipeline
+        where F: Filter + 'static {
+        self.steps.push(step(move |doc, _| filter.apply(doc)));
+        self
+    }
+
+    /// Adds a `TwoPass`, which reads the document as it is at this point
+    /// before rewriting it.
+    pub fn two_pass<F>(mut self, mut filter: F) -> Pipeline
+        where F: TwoPass + 'static {
+        self.steps.push(step(move |doc, _| filter.apply(doc)));
+        self
+    }
+
+    /// Runs `next` after this pipeline.
+    pub fn then(mut self, next: Pipeline) -> Pipeline {
+        self.steps.extend(next.steps);
+        self
+    }
+
+    /// Runs this pipeline only on documents for which `predicate` holds.
+    pub fn when<P>(mut self, predicate: P) -> Pipeline
+        where P: Fn(&Pandoc) -> bool + 'static {
+        Pipeline::new().then_step(step(move |doc, context| {
+            if predicate(&doc) {
+                self.run(doc, context)
+            } else {
+                doc
+            }
+        }))
+    }
+
+    /// Runs this pipeline only when pandoc is writing `name`. The format
+    /// matches if, without its extensions, it starts with `name`, so `"html"`
+    /// matches `html5+smart`; see `OutputFormat::is`.
+    pub fn only_for_format(mut self, name: &str) -> Pipeline {
+        let name = String::from(name);
+        Pipeline::new().then_step(step(move |doc, context| {
+            if context.is_format(&name) {
+                self.run(doc, context)
+            } else {
+                doc
+            }
+        }))
+    }
+
+    /// Runs this pipeline again and again until it no longer changes the
+    /// document, at most `max_rounds` times. A pipeline that never settles
+    /// leaves the document as the last round wrote it.
+    pub fn repeat_until_fixpoint(mut self, max_rounds: usize) -> Pipeline {
+        Pipeline::new().then_step(step(move |mut doc: Pandoc, context| {
+            for _ in 0..max_rounds {
+                let before = doc.clone();
+                doc = self.run(doc, context);
+                if doc == before {
+                    break;
+                }
+            }
+            doc
+        }))
+    }
+
+    /// Runs every filter over `doc`, in the conversion described by `context`.
+    pub fn run(&mut self, doc: Pandoc, context: &FilterContext) -> Pandoc {
+        self.steps.iter_mut().fold(doc, |doc, step| step(doc, context))
+    }
+
+    fn then_step(mut self, step: Step) -> Pipeline {
+        self.steps.push(step);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pipeline::Pipeline;
+    use filter::Filter;
+    use two_pass::two_pass;
+    use env::FilterContext;
+    use types::*;
+    use test_util::{text, doc};
+
+    /// A document with a single paragraph.
+    fn para(inlines: Vec<Inline>) -> Pandoc {
+        doc(vec![Block::Para(inlines)])
+    }
+
+    struct Exclaim;
+
+    impl Filter for Exclaim {
+        fn on_str(&mut self, text: String) -> Inline {
+            Inline::Str(text + "!")
+        }
+    }
+
+    #[test]
+    fn chain_of_filters() {
+        let mut pipeline = Pipeline::new()
+            .walk(|i| match i {
+                Inline::Str(s) => Inline::Str(s.to_uppercase()),
+                e => e
+            })
+            .walk(|b| match b {
+                Block::Para(inlines) => Block::Plain(inlines),
+                e => e
+            })
+            .then(Pipeline::new().filter(Exclaim))
+            .document(|mut doc: Pandoc| {
+                doc.0.un_meta.insert(String::from("done"), MetaValue::MetaBool(true));
+                doc
+            });
+        let Pandoc(meta, blocks, _) = pipeline.run(para(vec![text("a")]), &FilterContext::default());
+        assert_eq!(blocks, vec![Block::Plain(vec![text("A!")])]);
+        assert_eq!(meta.un_meta["done"], MetaValue::MetaBool(true));
+    }
+
+    #[test]
+    fn when_and_only_for_format() {
+        let exclaim = || Pipeline::new().filter(Exclaim);
+        let mut html = exclaim().only_for_format("html");
+        let writing = FilterContext::with_format;
+        assert_eq!(html.run(para(vec![text("a")]), &writing("html5+smart")), para(vec![text("a!")]));
+        assert_eq!(html.run(para(vec![text("a")]), &writing("latex")), para(vec![text("a")]));
+        assert_eq!(html.run(para(vec![text("a")]), &FilterContext::default()), para(vec![text("a")]));
+        let mut empty_only = exclaim().when(|doc| doc.1.is_empty());
+        assert_eq!(empty_only.run(para(vec![text("a")]), &writing("html")), para(vec![text("a")]));
+    }
+
+    #[test]
+    fn two_pass_sees_earlier_steps() {
+        let count = two_pass(|doc: &Pandoc| doc.inlines_iter().filter(|i| match **i {
+            Inline::Str(ref s) => s.ends_with('!'),
+            _ => false
+        }).count(), |n, mut doc: Pandoc| {
+            doc.0.un_meta.insert(String::from("exclaimed"), MetaValue::MetaString(n.to_string()));
+            doc
+        });
+        let mut pipeline = Pipeline::new().filter(Exclaim).two_pass(count);
+        let Pandoc(meta, _, _) = pipeline.run(para(vec![text("a"), Inline::Space, text("b")]),
+                                                   &FilterContext::default());
+        assert_eq!(meta.un_meta["exclaimed"], MetaValue::MetaString(String::from("2")));
+    }
+
+    #[test]
+    fn document_in_context() {
+        let mut pipeline = Pipeline::new().document_in_context(|mut doc: Pandoc, context: &FilterContext| {
+            let raw = if context.is_format("latex") {
+                Block::RawBlock(Format(String::from("latex")), String::from("\\newpage"))
+            } else {
+                Block::HorizontalRule
+            };
+            doc.1.push(raw);
+            doc
+        });
+        let Pandoc(_, blocks, _) = pipeline.run(para(vec![]), &FilterContext::with_format("latex+smart"));
+        assert_eq!(blocks[1], Block::RawBlock(Format(String::from("latex")), String::from("\\newpage")));
+        let Pandoc(_, blocks, _) = pipeline.run(para(vec![]), &FilterContext::with_format("html"));
+        assert_eq!(blocks[1], Block::HorizontalRule);
+    }
+
+    #[test]
+    fn repeat_until_fixpoint() {
+        // Unwraps one level of emphasis per run.
+        let mut pipeline = Pipeline::new()
+            .walk(|b| match b {
+                Block::Para(mut inlines) => match inlines.pop() {
+                    Some(Inline::Emph(inner)) => Block::Para(inner),
+                    Some(i) => {
+                        inlines.push(i);
+                        Block::Para(inlines)
+                    },
+                    None => Block::Para(inlines)
+                },
+                e => e
+            })
+            .repeat_until_fixpoint(10);
+        let nested = para(vec![Inline::Emph(vec![Inline::Emph(vec![text("a")])])]);
+        assert_eq!(pipeline.run(nested, &FilterContext::default()), para(vec![text("a")]));
+    }
+
+    #[test]
+    fn repeat_at_most_max_rounds() {
+        let mut pipeline = Pipeline::new().filter(Exclaim).repeat_until_fixpoint(3);
+        assert_eq!(pipeline.run(para(vec![text("a")]), &FilterContext::default()), para(vec![text("a!!!")]));
+    }
+}
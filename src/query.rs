@@ -0,0 +1,381 @@
+use std::collections::{BTreeMap, BTreeSet};
+use types::{Inline, Block, Citation, Pandoc, Meta, MetaValue};
+use types::{Caption, TableHead, TableBody, TableFoot, Row, Cell};
+use iter::Node;
+
+/// A result that can be built up from the results for each node, like
+/// Haskell's `Monoid`.
+pub trait Monoid {
+    fn empty() -> Self;
+    fn append(self, other: Self) -> Self;
+}
+
+impl<T> Monoid for Vec<T> {
+    fn empty() -> Self {
+        Vec::new()
+    }
+
+    fn append(mut self, other: Self) -> Self {
+        self.extend(other);
+        self
+    }
+}
+
+impl Monoid for String {
+    fn empty() -> Self {
+        String::new()
+    }
+
+    fn append(mut self, other: Self) -> Self {
+        self.push_str(&other);
+        self
+    }
+}
+
+impl<T: Ord> Monoid for BTreeSet<T> {
+    fn empty() -> Self {
+        BTreeSet::new()
+    }
+
+    fn append(mut self, other: Self) -> Self {
+        self.extend(other);
+        self
+    }
+}
+
+impl<K: Ord, V> Monoid for BTreeMap<K, V> {
+    fn empty() -> Self {
+        BTreeMap::new()
+    }
+
+    fn append(mut self, other: Self) -> Self {
+        self.extend(other);
+        self
+    }
+}
+
+impl Monoid for () {
+    fn empty() -> Self {}
+
+    fn append(self, _: Self) -> Self {}
+}
+
+/// The read-only counterpart of `Walkable`: applies `f` to every `T`, each
+/// before its children, and combines the results instead of rebuilding the tree.
+pub trait Queryable<T> {
+    fn query<R, F>(&self, f: &F) -> R
+        where F : Fn(&T) -> R, R : Monoid;
+}
+
+impl<U, T> Queryable<U> for Vec<T>
+    where T : Queryable<U> {
+    fn query<R, F>(&self, f: &F) -> R
+        where F : Fn(&U) -> R, R : Monoid {
+        self.iter().fold(R::empty(), |r, i| r.append(i.query(f)))
+    }
+}
+
+impl<U, T> Queryable<U> for Option<T>
+    where T : Queryable<U> {
+    fn query<R, F>(&self, f: &F) -> R
+        where F : Fn(&U) -> R, R : Monoid {
+        match *self {
+            Some(ref i) => i.query(f),
+            None => R::empty()
+        }
+    }
+}
+
+impl<U, V> Queryable<U> for BTreeMap<String, V>
+    where V : Queryable<U> {
+    fn query<R, F>(&self, f: &F) -> R
+        where F : Fn(&U) -> R, R : Monoid {
+        self.values().fold(R::empty(), |r, v| r.append(v.query(f)))
+    }
+}
+
+impl Queryable<Pandoc> for Pandoc {
+    fn query<R, F>(&self, f: &F) -> R
+        where F : Fn(&Pandoc) -> R, R : Monoid {
+        f(self)
+    }
+}
+
+impl Queryable<Block> for Pandoc {
+    fn query<R, F>(&self, f: &F) -> R
+        where F : Fn(&Block) -> R, R : Monoid {
+        self.0.query(f).append(self.1.query(f))
+    }
+}
+
+impl Queryable<Inline> for Pandoc {
+    fn query<R, F>(&self, f: &F) -> R
+        where F : Fn(&Inline) -> R, R : Monoid {
+        self.0.query(f).append(self.1.query(f))
+    }
+}
+
+impl Queryable<Meta> for Meta {
+    fn query<R, F>(&self, f: &F) -> R
+        where F : Fn(&Meta) -> R, R : Monoid {
+        f(self)
+    }
+}
+
+impl Queryable<Inline> for Meta {
+    fn query<R, F>(&self, f: &F) -> R
+        where F : Fn(&Inline) -> R, R : Monoid {
+        self.un_meta.query(f)
+    }
+}
+
+impl Queryable<Block> for Meta {
+    fn query<R, F>(&self, f: &F) -> R
+        where F : Fn(&Block) -> R, R : Monoid {
+        self.un_meta.query(f)
+    }
+}
+
+impl Queryable<MetaValue> for MetaValue {
+    fn query<R, F>(&self, f: &F) -> R
+        where F : Fn(&MetaValue) -> R, R : Monoid {
+        let children = match *self {
+            MetaValue::MetaMap(ref map) => map.query(f),
+            MetaValue::MetaList(ref values) => values.query(f),
+            _ => R::empty()
+        };
+        f(self).append(children)
+    }
+}
+
+impl Queryable<Block> for MetaValue {
+    fn query<R, F>(&self, f: &F) -> R
+        where F : Fn(&Block) -> R, R : Monoid {
+        query_meta_value_children(self, f)
+    }
+}
+
+impl Queryable<Inline> for MetaValue {
+    fn query<R, F>(&self, f: &F) -> R
+        where F : Fn(&Inline) -> R, R : Monoid {
+        query_meta_value_children(self, f)
+    }
+}
+
+fn query_meta_value_children<T, R, F>(value: &MetaValue, f: &F) -> R
+    where F : Fn(&T) -> R, R : Monoid,
+          MetaValue : Queryable<T>, Inline : Queryable<T>, Block : Queryable<T> {
+    match *value {
+        MetaValue::MetaMap(ref map) => map.query(f),
+        MetaValue::MetaList(ref values) => values.query(f),
+        MetaValue::MetaInlines(ref inlines) => inlines.query(f),
+        MetaValue::MetaBlocks(ref blocks) => blocks.query(f),
+        _ => R::empty()
+    }
+}
+
+impl Queryable<Block> for Block {
+    fn query<R, F>(&self, f: &F) -> R
+        where F : Fn(&Block) -> R, R : Monoid {
+        f(self).append(query_block_children(self, f))
+    }
+}
+
+impl Queryable<Inline> for Block {
+    fn query<R, F>(&self, f: &F) -> R
+        where F : Fn(&Inline) -> R, R : Monoid {
+        query_block_children(self, f)
+    }
+}
+
+fn query_block_children<T, R, F>(block: &Block, f: &F) -> R
+    where F : Fn(&T) -> R, R : Monoid, Inline : Queryable<T>, Block : Queryable<T> {
+    match *block {
+        Block::Plain(ref inlines) => inlines.query(f),
+        Block::Para(ref inlines) => inlines.query(f),
+        Block::LineBlock(ref lines) => lines.query(f),
+        Block::BlockQuote(ref blocks) => blocks.query(f),
+        Block::OrderedList(_, ref vecs_blocks) => vecs_blocks.query(f),
+        Block::BulletList(ref vecs_blocks) => vecs_blocks.query(f),
+        Block::DefinitionList(ref inlines_and_blocks) => {
+            inlines_and_blocks.iter()
+                .fold(R::empty(), |r, &(ref is, ref vbs)| r.append(is.query(f)).append(vbs.query(f)))
+        },
+        Block::Header(_, _, ref inlines) => inlines.query(f),
+        Block::Table(_, ref caption, _, ref head, ref bodies, ref foot) => {
+            caption.query(f)
+                .append(head.query(f))
+                .append(bodies.query(f))
+                .append(foot.query(f))
+        },
+        Block::Figure(_, ref caption, ref blocks) => caption.query(f).append(blocks.query(f)),
+        Block::Div(_, ref blocks) => blocks.query(f),
+        _ => R::empty()
+    }
+}
+
+impl Queryable<Inline> for Inline {
+    fn query<R, F>(&self, f: &F) -> R
+        where F : Fn(&Inline) -> R, R : Monoid {
+        f(self).append(query_inline_children(self, f))
+    }
+}
+
+impl Queryable<Block> for Inline {
+    fn query<R, F>(&self, f: &F) -> R
+        where F : Fn(&Block) -> R, R : Monoid {
+        query_inline_children(self, f)
+    }
+}
+
+fn query_inline_children<T, R, F>(inline: &Inline, f: &F) -> R
+    where F : Fn(&T) -> R, R : Monoid, Inline : Queryable<T>, Block : Queryable<T> {
+    match *inline {
+        Inline::Emph(ref inlines) => inlines.query(f),
+        Inline::Underline(ref inlines) => inlines.query(f),
+        Inline::Strong(ref inlines) => inlines.query(f),
+        Inline::Strikeout(ref inlines) => inlines.query(f),
+        Inline::Superscript(ref inlines) => inlines.query(f),
+        Inline::Subscript(ref inlines) => inlines.query(f),
+        Inline::SmallCaps(ref inlines) => inlines.query(f),
+        Inline::Quoted(_, ref inlines) => inlines.query(f),
+        Inline::Cite(ref citations, ref inlines) => citations.query(f).append(inlines.query(f)),
+        Inline::Link(_, ref inlines, _) => inlines.query(f),
+        Inline::Image(_, ref inlines, _) => inlines.query(f),
+        Inline::Span(_, ref inlines) => inlines.query(f),
+        Inline::Note(ref blocks) => blocks.query(f),
+        _ => R::empty()
+    }
+}
+
+impl<T> Queryable<T> for Caption
+    where Inline : Queryable<T>, Block : Queryable<T> {
+    fn query<R, F>(&self, f: &F) -> R
+        where F : Fn(&T) -> R, R : Monoid {
+        self.0.query(f).append(self.1.query(f))
+    }
+}
+
+impl<T> Queryable<T> for TableHead
+    where Row : Queryable<T> {
+    fn query<R, F>(&self, f: &F) -> R
+        where F : Fn(&T) -> R, R : Monoid {
+        self.1.query(f)
+    }
+}
+
+impl<T> Queryable<T> for TableBody
+    where Row : Queryable<T> {
+    fn query<R, F>(&self, f: &F) -> R
+        where F : Fn(&T) -> R, R : Monoid {
+        self.2.query(f).append(self.3.query(f))
+    }
+}
+
+impl<T> Queryable<T> for TableFoot
+    where Row : Queryable<T> {
+    fn query<R, F>(&self, f: &F) -> R
+        where F : Fn(&T) -> R, R : Monoid {
+        self.1.query(f)
+    }
+}
+
+impl<T> Queryable<T> for Row
+    where Cell : Queryable<T> {
+    fn query<R, F>(&self, f: &F) -> R
+        where F : Fn(&T) -> R, R : Monoid {
+        self.1.query(f)
+    }
+}
+
+impl<T> Queryable<T> for Cell
+    where Block : Queryable<T> {
+    fn query<R, F>(&self, f: &F) -> R
+        where F : Fn(&T) -> R, R : Monoid {
+        self.4.query(f)
+    }
+}
+
+impl<T> Queryable<T> for Citation
+    where Inline : Queryable<T> {
+    fn query<R, F>(&self, f: &F) -> R
+        where F : Fn(&T) -> R, R : Monoid {
+        self.citation_prefix.query(f).append(self.citation_suffix.query(f))
+    }
+}
+
+/// The text of `inlines`, with spaces and breaks as single spaces and
+/// formatting and notes dropped.
+pub fn plain_text(inlines: &[Inline]) -> String {
+    inlines.iter().map(|i| node_text(Node::Inline(i))).collect()
+}
+
+fn node_text(node: Node) -> String {
+    match node {
+        Node::Inline(&Inline::Str(ref s)) |
+        Node::Inline(&Inline::Code(_, ref s)) |
+        Node::Inline(&Inline::Math(_, ref s)) => s.clone(),
+        Node::Inline(&Inline::Space) |
+        Node::Inline(&Inline::SoftBreak) |
+        Node::Inline(&Inline::LineBreak) => String::from(" "),
+        Node::Inline(&Inline::Note(_)) => String::new(),
+        node => node.children().into_iter().map(node_text).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+    use query::{Queryable, plain_text};
+    use types::*;
+    use test_util::{text, attr};
+
+    fn doc() -> Pandoc {
+        let mut meta = BTreeMap::new();
+        meta.insert(String::from("title"), MetaValue::MetaInlines(vec![text("Title")]));
+        let link = Inline::Link(attr(""), vec![Inline::Emph(vec![text("here")])],
+                                (String::from("http://a.org"), String::new()));
+        let note = Inline::Note(vec![Block::Para(vec![
+            Inline::Link(attr(""), vec![text("there")], (String::from("http://b.org"), String::new()))
+        ])]);
+        Pandoc(Meta { un_meta: meta },
+               vec![Block::Header(1, attr(""), vec![text("Intro")]),
+                    Block::Para(vec![link, note]),
+                    Block::Div(attr(""), vec![Block::Header(2, attr(""), vec![text("Sub")])])],
+               None)
+    }
+
+    #[test]
+    fn query_link_targets() {
+        let targets: Vec<String> = doc().query(&(|i: &Inline| match *i {
+            Inline::Link(_, _, (ref url, _)) => vec![url.clone()],
+            _ => vec![]
+        }));
+        assert_eq!(targets, vec!["http://a.org", "http://b.org"]);
+    }
+
+    #[test]
+    fn query_header_levels() {
+        let levels: BTreeSet<u64> = doc().query(&(|b: &Block| match *b {
+            Block::Header(level, _, _) => vec![level].into_iter().collect(),
+            _ => BTreeSet::new()
+        }));
+        assert_eq!(levels, vec![1, 2].into_iter().collect());
+    }
+
+    #[test]
+    fn query_text_in_order() {
+        let text: String = doc().query(&(|i: &Inline| match *i {
+            Inline::Str(ref s) => s.clone(),
+            _ => String::new()
+        }));
+        assert_eq!(text, "TitleIntroherethereSub");
+    }
+
+    #[test]
+    fn plain_text_skips_notes() {
+        let inlines = vec![text("a"), Inline::SoftBreak, Inline::Emph(vec![text("b")]),
+                           Inline::Note(vec![Block::Para(vec![text("c")])])];
+        assert_eq!(plain_text(&inlines), "a b");
+    }
+}
@@ -0,0 +1,408 @@
+use std::cell::RefCell;
+use std::error;
+use std::fmt;
+use std::mem;
+use std::str::FromStr;
+use types::{Block, Inline, Attr};
+use context::{ContextWalkable, Context, Ancestor};
+use query::Monoid;
+use walk::Descent;
+
+/// Something a selector can be matched against: a node, or an ancestor of one.
+pub trait Element {
+    /// The constructor, as its `"t"`.
+    fn tag(&self) -> &str;
+    fn attr(&self) -> Option<&Attr>;
+    /// The level of a `Header`.
+    fn level(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl Element for Block {
+    fn tag(&self) -> &str {
+        Block::tag(self)
+    }
+
+    fn attr(&self) -> Option<&Attr> {
+        Block::attr(self)
+    }
+
+    fn level(&self) -> Option<u64> {
+        match *self {
+            Block::Header(level, _, _) => Some(level),
+            _ => None
+        }
+    }
+}
+
+impl Element for Inline {
+    fn tag(&self) -> &str {
+        Inline::tag(self)
+    }
+
+    fn attr(&self) -> Option<&Attr> {
+        Inline::attr(self)
+    }
+}
+
+impl<'a> Element for Ancestor<'a> {
+    fn tag(&self) -> &str {
+        self.tag
+    }
+
+    fn attr(&self) -> Option<&Attr> {
+        self.attr
+    }
+
+    fn level(&self) -> Option<u64> {
+        self.level
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Combinator {
+    Descendant,
+    Child
+}
+
+/// One selector between combinators, e.g. `Span.keyword[lang=en]`.
+#[derive(PartialEq, Debug, Default)]
+struct Compound {
+    tag: Option<String>,
+    id: Option<String>,
+    classes: Vec<String>,
+    attributes: Vec<(String, Option<String>)>
+}
+
+impl Compound {
+    fn matches(&self, node: &Element) -> bool {
+        if self.tag.as_ref().map_or(false, |tag| tag != node.tag()) {
+            return false;
+        }
+        if self.id.is_none() && self.classes.is_empty() && self.attributes.is_empty() {
+            return true;
+        }
+        let level = node.level();
+        let empty = (String::new(), vec![], vec![]);
+        let &(ref id, ref classes, ref kvs) = node.attr().unwrap_or(&empty);
+        if node.attr().is_none() && level.is_none() {
+            return false;
+        }
+        self.id.as_ref().map_or(true, |i| i == id) &&
+            self.classes.iter().all(|c| classes.contains(c)) &&
+            self.attributes.iter().all(|&(ref key, ref value)| {
+                if let (Some(level), "level") = (level, &key[..]) {
+                    return value.as_ref().map_or(true, |v| *v == level.to_string());
+                }
+                kvs.iter().any(|&(ref k, ref v)| k == key && value.as_ref().map_or(true, |value| v == value))
+            })
+    }
+}
+
+/// A CSS-like selector over blocks and inlines.
+///
+/// A selector is a list of compound selectors separated by whitespace, for
+/// a descendant, or `>`, for a child. A compound selector is a constructor
+/// name or `*`, followed by any number of `#id`, `.class`, `[key]` and
+/// `[key=value]` tests on the node's `Attr`. `[level=2]` also tests the level
+/// of a `Header`. Values can be quoted with `"` or `'`.
+///
+/// ```text
+/// Header[level=2] Span.keyword Str
+/// Div#intro > Para
+/// CodeBlock.python
+/// ```
+#[derive(PartialEq, Debug)]
+pub struct Selector {
+    // Each compound with the combinator that joins it to the one before;
+    // the first combinator is unused.
+    steps: Vec<(Combinator, Compound)>
+}
+
+impl Selector {
+    pub fn parse(selector: &str) -> Result<Selector, ParseError> {
+        Parser { chars: selector.chars().collect(), pos: 0 }.selector()
+    }
+
+    /// Whether `node`, whose ancestors are `ancestors` (closest first),
+    /// matches the selector.
+    pub fn matches(&self, node: &Element, ancestors: &[&Element]) -> bool {
+        self.matches_step(self.steps.len() - 1, node, ancestors)
+    }
+
+    fn matches_step(&self, step: usize, node: &Element, ancestors: &[&Element]) -> bool {
+        let (combinator, ref compound) = self.steps[step];
+        if !compound.matches(node) {
+            return false;
+        }
+        if step == 0 {
+            return true;
+        }
+        match combinator {
+            Combinator::Child =>
+                !ancestors.is_empty() && self.matches_step(step - 1, ancestors[0], &ancestors[1..]),
+            Combinator::Descendant =>
+                (0..ancestors.len()).any(|i| self.matches_step(step - 1, ancestors[i], &ancestors[i + 1..]))
+        }
+    }
+
+    /// Walks `doc` with `ContextWalkable`, rewriting with `f` the `T`s that
+    /// match the selector.
+    pub fn walk<T, W, F>(&self, doc: W, f: F) -> W
+        where W: ContextWalkable<T>, T: Element, F: Fn(T) -> T {
+        doc.walk_with_context(&|node: T, cx: &Context| {
+            let matched = {
+                let ancestors: Vec<&Element> = cx.ancestors().map(|a| a as &Element).collect();
+                self.matches(&node, &ancestors)
+            };
+            if matched { f(node) } else { node }
+        })
+    }
+
+    /// Calls `f` on every `T` of `doc` that matches the selector, in
+    /// document order, and combines the results. `doc` is cloned to be
+    /// walked with `ContextWalkable`.
+    pub fn query<T, W, R, F>(&self, doc: &W, f: F) -> R
+        where W: ContextWalkable<T> + Clone, T: Element, F: Fn(&T) -> R, R: Monoid {
+        let result = RefCell::new(R::empty());
+        doc.clone().walk_top_down_with_context(&|node: T, cx: &Context| {
+            let matched = {
+                let ancestors: Vec<&Element> = cx.ancestors().map(|a| a as &Element).collect();
+                self.matches(&node, &ancestors)
+            };
+            if matched {
+                let mut result = result.borrow_mut();
+                let before = mem::replace(&mut *result, R::empty());
+                *result = before.append(f(&node));
+            }
+            (node, Descent::Continue)
+        });
+        result.into_inner()
+    }
+
+    /// Every `T` of `doc` that matches the selector.
+    pub fn select<T, W>(&self, doc: &W) -> Vec<T>
+        where W: ContextWalkable<T> + Clone, T: Element + Clone {
+        self.query(doc, |node: &T| vec![node.clone()])
+    }
+}
+
+impl FromStr for Selector {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Selector, ParseError> {
+        Selector::parse(s)
+    }
+}
+
+/// Why a selector could not be parsed, and the character it went wrong at.
+#[derive(PartialEq, Debug)]
+pub struct ParseError {
+    pub position: usize,
+    pub message: String
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid selector at character {}: {}", self.position, self.message)
+    }
+}
+
+impl error::Error for ParseError {
+    fn description(&self) -> &str {
+        "invalid selector"
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, ParseError> {
+        Err(ParseError { position: self.pos, message: String::from(message) })
+    }
+
+    fn skip_whitespace(&mut self) -> bool {
+        let start = self.pos;
+        while self.peek().map_or(false, char::is_whitespace) {
+            self.pos += 1;
+        }
+        self.pos > start
+    }
+
+    fn selector(mut self) -> Result<Selector, ParseError> {
+        let mut steps = vec![];
+        let mut combinator = Combinator::Descendant;
+        self.skip_whitespace();
+        loop {
+            steps.push((combinator, try!(self.compound())));
+            let spaced = self.skip_whitespace();
+            match self.peek() {
+                None => break,
+                Some('>') => {
+                    self.pos += 1;
+                    self.skip_whitespace();
+                    combinator = Combinator::Child;
+                },
+                Some(_) if spaced => combinator = Combinator::Descendant,
+                Some(_) => return self.error("expected a combinator")
+            }
+        }
+        Ok(Selector { steps: steps })
+    }
+
+    fn compound(&mut self) -> Result<Compound, ParseError> {
+        let mut compound = Compound::default();
+        let start = self.pos;
+        match self.peek() {
+            Some('*') => self.pos += 1,
+            Some(c) if c.is_alphabetic() => {
+                let tag = try!(self.identifier());
+                if !Block::TAGS.contains(&&tag[..]) && !Inline::TAGS.contains(&&tag[..]) {
+                    self.pos = start;
+                    return self.error(&format!("{:?} is not a block or inline", tag));
+                }
+                compound.tag = Some(tag);
+            },
+            _ => ()
+        }
+        loop {
+            match self.peek() {
+                Some('#') => {
+                    self.pos += 1;
+                    compound.id = Some(try!(self.identifier()));
+                },
+                Some('.') => {
+                    self.pos += 1;
+                    compound.classes.push(try!(self.identifier()));
+                },
+                Some('[') => {
+                    self.pos += 1;
+                    compound.attributes.push(try!(self.attribute()));
+                },
+                _ => break
+            }
+        }
+        if self.pos == start {
+            return self.error("expected a selector");
+        }
+        Ok(compound)
+    }
+
+    fn attribute(&mut self) -> Result<(String, Option<String>), ParseError> {
+        self.skip_whitespace();
+        let key = try!(self.identifier());
+        self.skip_whitespace();
+        let value = if self.peek() == Some('=') {
+            self.pos += 1;
+            self.skip_whitespace();
+            let value = match self.peek() {
+                Some(quote) if quote == '"' || quote == '\'' => try!(self.quoted(quote)),
+                _ => try!(self.identifier())
+            };
+            self.skip_whitespace();
+            Some(value)
+        } else {
+            None
+        };
+        if self.peek() != Some(']') {
+            return self.error("expected ']'");
+        }
+        self.pos += 1;
+        Ok((key, value))
+    }
+
+    fn identifier(&mut self) -> Result<String, ParseError> {
+        let start = self.pos;
+        while self.peek().map_or(false, |c| c.is_alphanumeric() || c == '-' || c == '_' || c == ':') {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return self.error("expected a name");
+        }
+        Ok(self.chars[start..self.pos].iter().cloned().collect())
+    }
+
+    fn quoted(&mut self, quote: char) -> Result<String, ParseError> {
+        self.pos += 1;
+        let start = self.pos;
+        while self.peek().map_or(false, |c| c != quote) {
+            self.pos += 1;
+        }
+        if self.peek().is_none() {
+            return self.error("unterminated string");
+        }
+        self.pos += 1;
+        Ok(self.chars[start..self.pos - 1].iter().cloned().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use selector::{Selector, ParseError};
+    use types::*;
+    use test_util::{text, attr, attr_with};
+
+    fn doc() -> Pandoc {
+        let keyword = |s| Inline::Span(attr_with("", &["keyword"], &[]), vec![text(s)]);
+        Pandoc(Meta { un_meta: BTreeMap::new() },
+               vec![Block::Header(1, attr(""), vec![keyword("one")]),
+                    Block::Header(2, attr(""), vec![text("x"), keyword("two")]),
+                    Block::Div(attr("intro"),
+                               vec![Block::Para(vec![keyword("three")]),
+                                    Block::BlockQuote(vec![Block::Para(vec![text("four")])])]),
+                    Block::CodeBlock(attr_with("", &["python"], &[("startFrom", "3")]), String::from("pass"))],
+               None)
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(Selector::parse("Para >"),
+                   Err(ParseError { position: 6, message: String::from("expected a selector") }));
+        assert_eq!(Selector::parse("Paragraph").unwrap_err().position, 0);
+        assert_eq!(Selector::parse("Span[lang=en").unwrap_err().message, "expected ']'");
+        assert!("Header[level=2] Span.keyword Str".parse::<Selector>().is_ok());
+    }
+
+    #[test]
+    fn query_descendants() {
+        let doc = doc();
+        let selector = Selector::parse("Header[level=2] Span.keyword Str").unwrap();
+        let selected: Vec<Inline> = selector.select(&doc);
+        assert_eq!(selected, vec![text("two")]);
+        let selector = Selector::parse("CodeBlock.python[startFrom='3']").unwrap();
+        let code: Vec<String> = selector.query(&doc.1, |block: &Block| match *block {
+            Block::CodeBlock(_, ref code) => vec![code.clone()],
+            _ => vec![]
+        });
+        assert_eq!(code, vec!["pass"]);
+    }
+
+    #[test]
+    fn query_in_document_order() {
+        let selector = Selector::parse("Div *").unwrap();
+        let tags: Vec<&str> = selector.query(&doc().1[2], |block: &Block| vec![block.tag()]);
+        assert_eq!(tags, vec!["Para", "BlockQuote", "Para"]);
+    }
+
+    #[test]
+    fn walk_children_only() {
+        let selector = Selector::parse("Div#intro > Para").unwrap();
+        let Pandoc(_, blocks, _) = selector.walk(doc(), |b| match b {
+            Block::Para(inlines) => Block::Plain(inlines),
+            e => e
+        });
+        assert_eq!(blocks[2], Block::Div(attr("intro"),
+                                         vec![Block::Plain(vec![Inline::Span(attr_with("", &["keyword"], &[]),
+                                                                             vec![text("three")])]),
+                                              Block::BlockQuote(vec![Block::Para(vec![text("four")])])]));
+    }
+}
@@ -0,0 +1,339 @@
+use std::collections::BTreeMap;
+use types::{Inline, Block, Citation, Pandoc, Meta, MetaValue};
+use types::{Caption, TableHead, TableBody, TableFoot, Row, Cell};
+
+/// A traversal whose filter returns the nodes that take the place of the one
+/// it was given, like a pandocfilters action returning a list: `vec![]`
+/// deletes the node, `vec![x]` replaces it and longer vectors are spliced
+/// into the parent list.
+///
+/// The filter is applied to every `T` that sits in a list, after its children
+/// have been spliced. A `Block` or `Inline` that `splice` is called on
+/// directly has no list to go into, so only its children are filtered.
+pub trait Splicable<T> {
+    fn splice<F>(self, f: &F) -> Self
+        where F : Fn(T) -> Vec<T>;
+}
+
+/// How an element of a list is put back into it during a `Splicable` walk:
+/// nodes of the filtered type are replaced by what the filter returns, and
+/// everything else by itself, spliced.
+pub trait SpliceItem<T> : Sized {
+    fn splice_into<F>(self, f: &F, out: &mut Vec<Self>)
+        where F : Fn(T) -> Vec<T>;
+}
+
+impl<U, T> Splicable<U> for Vec<T>
+    where T : SpliceItem<U> {
+    fn splice<F>(self, f: &F) -> Self
+        where F : Fn(U) -> Vec<U> {
+        let mut out = Vec::with_capacity(self.len());
+        for i in self {
+            i.splice_into(f, &mut out);
+        }
+        out
+    }
+}
+
+impl<U, T> Splicable<U> for Option<T>
+    where T : Splicable<U> {
+    fn splice<F>(self, f: &F) -> Self
+        where F : Fn(U) -> Vec<U> {
+        self.map(|i| i.splice(f))
+    }
+}
+
+impl<U, V> Splicable<U> for BTreeMap<String, V>
+    where V : Splicable<U> {
+    fn splice<F>(self, f: &F) -> Self
+        where F : Fn(U) -> Vec<U> {
+        self.into_iter().map(|(k, v)| (k, v.splice(f))).collect()
+    }
+}
+
+impl<U, A, B> Splicable<U> for (A, B)
+    where A : Splicable<U>, B : Splicable<U> {
+    fn splice<F>(self, f: &F) -> Self
+        where F : Fn(U) -> Vec<U> {
+        (self.0.splice(f), self.1.splice(f))
+    }
+}
+
+/// Implements `SpliceItem` for list elements that are never replaced
+/// themselves, only spliced inside.
+macro_rules! splice_in_place {
+    ($($ty:ty),*) => {
+        $(impl<U> SpliceItem<U> for $ty
+              where $ty : Splicable<U> {
+              fn splice_into<F>(self, f: &F, out: &mut Vec<Self>)
+                  where F : Fn(U) -> Vec<U> {
+                  out.push(self.splice(f))
+              }
+          })*
+    }
+}
+
+impl<U, T> SpliceItem<U> for Vec<T>
+    where Vec<T> : Splicable<U> {
+    fn splice_into<F>(self, f: &F, out: &mut Vec<Self>)
+        where F : Fn(U) -> Vec<U> {
+        out.push(self.splice(f))
+    }
+}
+
+impl<U, A, B> SpliceItem<U> for (A, B)
+    where (A, B) : Splicable<U> {
+    fn splice_into<F>(self, f: &F, out: &mut Vec<Self>)
+        where F : Fn(U) -> Vec<U> {
+        out.push(self.splice(f))
+    }
+}
+
+splice_in_place!(MetaValue, TableBody, Row, Cell, Citation);
+
+impl<T> Splicable<T> for Pandoc
+    where Meta : Splicable<T>, Block : SpliceItem<T> {
+    fn splice<F>(self, f: &F) -> Self
+        where F : Fn(T) -> Vec<T> {
+        Pandoc(self.0.splice(f), self.1.splice(f), self.2)
+    }
+}
+
+impl<T> Splicable<T> for Meta
+    where MetaValue : Splicable<T> {
+    fn splice<F>(self, f: &F) -> Self
+        where F : Fn(T) -> Vec<T> {
+        Meta { un_meta: self.un_meta.splice(f) }
+    }
+}
+
+impl Splicable<Block> for MetaValue {
+    fn splice<F>(self, f: &F) -> Self
+        where F : Fn(Block) -> Vec<Block> {
+        splice_meta_value(self, f)
+    }
+}
+
+impl Splicable<Inline> for MetaValue {
+    fn splice<F>(self, f: &F) -> Self
+        where F : Fn(Inline) -> Vec<Inline> {
+        splice_meta_value(self, f)
+    }
+}
+
+fn splice_meta_value<T, F>(value: MetaValue, f: &F) -> MetaValue
+    where F : Fn(T) -> Vec<T>,
+          MetaValue : Splicable<T>, Inline : SpliceItem<T>, Block : SpliceItem<T> {
+    match value {
+        MetaValue::MetaMap(map) => MetaValue::MetaMap(map.splice(f)),
+        MetaValue::MetaList(values) => MetaValue::MetaList(values.splice(f)),
+        MetaValue::MetaInlines(inlines) => MetaValue::MetaInlines(inlines.splice(f)),
+        MetaValue::MetaBlocks(blocks) => MetaValue::MetaBlocks(blocks.splice(f)),
+        e => e
+    }
+}
+
+impl Splicable<Block> for Block {
+    fn splice<F>(self, f: &F) -> Self
+        where F : Fn(Block) -> Vec<Block> {
+        splice_block_children(self, f)
+    }
+}
+
+impl Splicable<Inline> for Block {
+    fn splice<F>(self, f: &F) -> Self
+        where F : Fn(Inline) -> Vec<Inline> {
+        splice_block_children(self, f)
+    }
+}
+
+impl SpliceItem<Block> for Block {
+    fn splice_into<F>(self, f: &F, out: &mut Vec<Self>)
+        where F : Fn(Block) -> Vec<Block> {
+        out.extend(f(self.splice(f)))
+    }
+}
+
+impl SpliceItem<Inline> for Block {
+    fn splice_into<F>(self, f: &F, out: &mut Vec<Self>)
+        where F : Fn(Inline) -> Vec<Inline> {
+        out.push(self.splice(f))
+    }
+}
+
+fn splice_block_children<T, F>(block: Block, f: &F) -> Block
+    where F : Fn(T) -> Vec<T>, Inline : SpliceItem<T>, Block : SpliceItem<T> {
+    match block {
+        Block::Plain(inlines) => Block::Plain(inlines.splice(f)),
+        Block::Para(inlines) => Block::Para(inlines.splice(f)),
+        Block::LineBlock(lines) => Block::LineBlock(lines.splice(f)),
+        Block::BlockQuote(blocks) => Block::BlockQuote(blocks.splice(f)),
+        Block::OrderedList(list, vecs_blocks) =>
+            Block::OrderedList(list, vecs_blocks.splice(f)),
+        Block::BulletList(vecs_blocks) => Block::BulletList(vecs_blocks.splice(f)),
+        Block::DefinitionList(inlines_and_blocks) =>
+            Block::DefinitionList(inlines_and_blocks.splice(f)),
+        Block::Header(i, attr, inlines) => Block::Header(i, attr, inlines.splice(f)),
+        Block::Table(attr, caption, col_specs, head, bodies, foot) => {
+            Block::Table(attr, caption.splice(f), col_specs,
+                         head.splice(f), bodies.splice(f), foot.splice(f))
+        },
+        Block::Figure(attr, caption, blocks) =>
+            Block::Figure(attr, caption.splice(f), blocks.splice(f)),
+        Block::Div(attr, blocks) => Block::Div(attr, blocks.splice(f)),
+        e => e
+    }
+}
+
+impl Splicable<Inline> for Inline {
+    fn splice<F>(self, f: &F) -> Self
+        where F : Fn(Inline) -> Vec<Inline> {
+        splice_inline_children(self, f)
+    }
+}
+
+impl Splicable<Block> for Inline {
+    fn splice<F>(self, f: &F) -> Self
+        where F : Fn(Block) -> Vec<Block> {
+        splice_inline_children(self, f)
+    }
+}
+
+impl SpliceItem<Inline> for Inline {
+    fn splice_into<F>(self, f: &F, out: &mut Vec<Self>)
+        where F : Fn(Inline) -> Vec<Inline> {
+        out.extend(f(self.splice(f)))
+    }
+}
+
+impl SpliceItem<Block> for Inline {
+    fn splice_into<F>(self, f: &F, out: &mut Vec<Self>)
+        where F : Fn(Block) -> Vec<Block> {
+        out.push(self.splice(f))
+    }
+}
+
+fn splice_inline_children<T, F>(inline: Inline, f: &F) -> Inline
+    where F : Fn(T) -> Vec<T>, Inline : SpliceItem<T>, Block : SpliceItem<T> {
+    match inline {
+        Inline::Emph(inlines) => Inline::Emph(inlines.splice(f)),
+        Inline::Underline(inlines) => Inline::Underline(inlines.splice(f)),
+        Inline::Strong(inlines) => Inline::Strong(inlines.splice(f)),
+        Inline::Strikeout(inlines) => Inline::Strikeout(inlines.splice(f)),
+        Inline::Superscript(inlines) => Inline::Superscript(inlines.splice(f)),
+        Inline::Subscript(inlines) => Inline::Subscript(inlines.splice(f)),
+        Inline::SmallCaps(inlines) => Inline::SmallCaps(inlines.splice(f)),
+        Inline::Quoted(quote_type, inlines) => Inline::Quoted(quote_type, inlines.splice(f)),
+        Inline::Cite(citations, inlines) => Inline::Cite(citations.splice(f), inlines.splice(f)),
+        Inline::Link(attr, inlines, target) => Inline::Link(attr, inlines.splice(f), target),
+        Inline::Image(attr, inlines, target) => Inline::Image(attr, inlines.splice(f), target),
+        Inline::Span(attr, inlines) => Inline::Span(attr, inlines.splice(f)),
+        Inline::Note(blocks) => Inline::Note(blocks.splice(f)),
+        e => e
+    }
+}
+
+impl<T> Splicable<T> for Caption
+    where Inline : SpliceItem<T>, Block : SpliceItem<T> {
+    fn splice<F>(self, f: &F) -> Self
+        where F : Fn(T) -> Vec<T> {
+        Caption(self.0.splice(f), self.1.splice(f))
+    }
+}
+
+impl<T> Splicable<T> for TableHead
+    where Inline : SpliceItem<T>, Block : SpliceItem<T> {
+    fn splice<F>(self, f: &F) -> Self
+        where F : Fn(T) -> Vec<T> {
+        TableHead(self.0, self.1.splice(f))
+    }
+}
+
+impl<T> Splicable<T> for TableBody
+    where Inline : SpliceItem<T>, Block : SpliceItem<T> {
+    fn splice<F>(self, f: &F) -> Self
+        where F : Fn(T) -> Vec<T> {
+        TableBody(self.0, self.1, self.2.splice(f), self.3.splice(f))
+    }
+}
+
+impl<T> Splicable<T> for TableFoot
+    where Inline : SpliceItem<T>, Block : SpliceItem<T> {
+    fn splice<F>(self, f: &F) -> Self
+        where F : Fn(T) -> Vec<T> {
+        TableFoot(self.0, self.1.splice(f))
+    }
+}
+
+impl<T> Splicable<T> for Row
+    where Inline : SpliceItem<T>, Block : SpliceItem<T> {
+    fn splice<F>(self, f: &F) -> Self
+        where F : Fn(T) -> Vec<T> {
+        Row(self.0, self.1.splice(f))
+    }
+}
+
+impl<T> Splicable<T> for Cell
+    where Inline : SpliceItem<T>, Block : SpliceItem<T> {
+    fn splice<F>(self, f: &F) -> Self
+        where F : Fn(T) -> Vec<T> {
+        Cell(self.0, self.1, self.2, self.3, self.4.splice(f))
+    }
+}
+
+impl<T> Splicable<T> for Citation
+    where Inline : SpliceItem<T> {
+    fn splice<F>(self, f: &F) -> Self
+        where F : Fn(T) -> Vec<T> {
+        Citation { citation_id: self.citation_id,
+                   citation_prefix: self.citation_prefix.splice(f),
+                   citation_suffix: self.citation_suffix.splice(f),
+                   citation_mode: self.citation_mode,
+                   citation_note_num: self.citation_note_num,
+                   citation_hash: self.citation_hash
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use splice::Splicable;
+    use types::*;
+    use test_util::{text, attr};
+
+    #[test]
+    fn splice_delete_and_insert_blocks() {
+        let blocks = vec![Block::Header(1, attr(""), vec![text("Title")]),
+                          Block::Para(vec![text("drop me")]),
+                          Block::Div(attr(""), vec![Block::Para(vec![text("and me")]),
+                                                  Block::HorizontalRule])];
+        let spliced = blocks.splice(&(|b| match b {
+            Block::Para(_) => vec![],
+            h @ Block::Header(..) => vec![h, Block::RawBlock(Format(String::from("html")),
+                                                             String::from("<hr>"))],
+            e => vec![e]
+        }));
+        let expected = vec![Block::Header(1, attr(""), vec![text("Title")]),
+                            Block::RawBlock(Format(String::from("html")), String::from("<hr>")),
+                            Block::Div(attr(""), vec![Block::HorizontalRule])];
+        assert_eq!(spliced, expected);
+    }
+
+    #[test]
+    fn splice_inlines_in_note_and_meta() {
+        let split = |i| match i {
+            Inline::Str(s) => s.split('-').map(|s| Inline::Str(String::from(s))).collect(),
+            Inline::Space => vec![],
+            e => vec![e]
+        };
+        let note = Inline::Note(vec![Block::Plain(vec![text("a-b"), Inline::Space, text("c")])]);
+        let mut meta = Meta { un_meta: Default::default() };
+        meta.un_meta.insert(String::from("title"), MetaValue::MetaInlines(vec![text("x-y")]));
+        let doc = Pandoc(meta, vec![Block::Para(vec![Inline::Emph(vec![note])])], None);
+        let Pandoc(meta, blocks, _) = doc.splice(&split);
+        assert_eq!(meta.un_meta["title"], MetaValue::MetaInlines(vec![text("x"), text("y")]));
+        assert_eq!(blocks, vec![Block::Para(vec![Inline::Emph(vec![
+            Inline::Note(vec![Block::Plain(vec![text("a"), text("b"), text("c")])])
+        ])])]);
+    }
+}
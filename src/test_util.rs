@@ -0,0 +1,24 @@
+//! Fixtures shared by the tests of every module.
+
+use std::collections::BTreeMap;
+use types::{Inline, Block, Pandoc, Meta, Attr};
+
+pub fn text(s: &str) -> Inline {
+    Inline::Str(String::from(s))
+}
+
+/// An `Attr` with the identifier `id` and no classes or attributes.
+pub fn attr(id: &str) -> Attr {
+    attr_with(id, &[], &[])
+}
+
+pub fn attr_with(id: &str, classes: &[&str], kvs: &[(&str, &str)]) -> Attr {
+    (String::from(id),
+     classes.iter().map(|c| String::from(*c)).collect(),
+     kvs.iter().map(|&(k, v)| (String::from(k), String::from(v))).collect())
+}
+
+/// A legacy document with no metadata.
+pub fn doc(blocks: Vec<Block>) -> Pandoc {
+    Pandoc(Meta { un_meta: BTreeMap::new() }, blocks, None)
+}
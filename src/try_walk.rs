@@ -0,0 +1,585 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use types::{Inline, Block, Citation, Pandoc, Meta, MetaValue};
+use types::{Caption, TableHead, TableBody, TableFoot, Row, Cell};
+use error::{FilterError, key_path, index_path};
+use walk::Descent;
+
+/// Where a node is in the document, as the JSON path it is written at, e.g.
+/// `$.blocks[0].c[1]`. Paths are built on the stack while walking and only
+/// turned into strings when a filter fails.
+pub struct Path<'a> {
+    parent: Option<&'a Path<'a>>,
+    step: Step<'a>
+}
+
+enum Step<'a> {
+    Root,
+    Key(&'a str),
+    Index(usize)
+}
+
+impl<'a> Path<'a> {
+    /// The path of the value `try_walk` was called on, `$`.
+    pub fn root() -> Path<'static> {
+        Path { parent: None, step: Step::Root }
+    }
+
+    /// The path of the value under `key` in the object at this path.
+    pub fn key<'b>(&'b self, key: &'b str) -> Path<'b> {
+        Path { parent: Some(self), step: Step::Key(key) }
+    }
+
+    /// The path of the `index`th element of the array at this path.
+    pub fn index<'b>(&'b self, index: usize) -> Path<'b> {
+        Path { parent: Some(self), step: Step::Index(index) }
+    }
+}
+
+impl<'a> fmt::Display for Path<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path = match (self.parent, &self.step) {
+            (Some(parent), &Step::Key(key)) => key_path(&parent.to_string(), key),
+            (Some(parent), &Step::Index(index)) => index_path(&parent.to_string(), index),
+            _ => String::from("$")
+        };
+        f.write_str(&path)
+    }
+}
+
+/// An error returned by the filter, and the path of the node it failed on,
+/// in the latest layout (see `TryWalkable`).
+#[derive(Debug)]
+pub struct NodeError<E> {
+    pub path: String,
+    pub error: E
+}
+
+impl<E: fmt::Display> From<NodeError<E>> for FilterError {
+    fn from(e: NodeError<E>) -> FilterError {
+        FilterError::Filter { path: e.path, message: e.error.to_string() }
+    }
+}
+
+/// The errors met so far in a walk, and whether to keep going after the first.
+pub struct Errors<E> {
+    errors: Vec<NodeError<E>>,
+    all: bool
+}
+
+impl<E> Errors<E> {
+    fn stopped(&self) -> bool {
+        !self.all && !self.errors.is_empty()
+    }
+
+    fn apply<T, R, F>(&mut self, f: &F, node: T, path: &Path) -> Option<R>
+        where F : Fn(T) -> Result<R, E> {
+        if self.stopped() {
+            return None;
+        }
+        match f(node) {
+            Ok(node) => Some(node),
+            Err(e) => {
+                self.errors.push(NodeError { path: path.to_string(), error: e });
+                None
+            }
+        }
+    }
+}
+
+/// The order in which `TryWalkable` applies its filter to a node and walks
+/// the node's children.
+pub trait TryTraversal<T, E> {
+    /// Filters `node`, found at `path`, using `children` to walk its children.
+    fn visit<W>(&self, node: T, path: &Path, errors: &mut Errors<E>, children: W) -> Option<T>
+        where W : FnOnce(T, &mut Errors<E>) -> Option<T>;
+}
+
+/// `BottomUp` for `TryWalkable`.
+pub struct TryBottomUp<'a, F: 'a>(pub &'a F);
+
+impl<'a, T, E, F> TryTraversal<T, E> for TryBottomUp<'a, F>
+    where F : Fn(T) -> Result<T, E> {
+    fn visit<W>(&self, node: T, path: &Path, errors: &mut Errors<E>, children: W) -> Option<T>
+        where W : FnOnce(T, &mut Errors<E>) -> Option<T> {
+        children(node, errors).and_then(|node| errors.apply(self.0, node, path))
+    }
+}
+
+/// `TopDown` for `TryWalkable`.
+pub struct TryTopDown<'a, F: 'a>(pub &'a F);
+
+impl<'a, T, E, F> TryTraversal<T, E> for TryTopDown<'a, F>
+    where F : Fn(T) -> Result<(T, Descent), E> {
+    fn visit<W>(&self, node: T, path: &Path, errors: &mut Errors<E>, children: W) -> Option<T>
+        where W : FnOnce(T, &mut Errors<E>) -> Option<T> {
+        match errors.apply(self.0, node, path) {
+            Some((node, Descent::Continue)) => children(node, errors),
+            Some((node, Descent::Stop)) => Some(node),
+            None => None
+        }
+    }
+}
+
+/// The fallible counterpart of `Walkable`: the same nodes are visited in the
+/// same order, but the filter returns a `Result`, and a failure is reported
+/// with the path of the node that caused it.
+///
+/// Paths follow the layout the value is serialized with, so for a `Pandoc`
+/// they depend on whether it has an API version. Nodes are always in the
+/// layout of `codec::LATEST`, though, so in a document from before 1.21 a path
+/// into a table points into the table as `codec` upgraded it, not into the
+/// JSON that was read. Tables are the only nodes read from an older layout.
+pub trait TryWalkable<T> : Sized {
+    /// Walks the value found at `path`, recording failures in `errors`.
+    /// Returns `None` if the filter failed anywhere below.
+    fn try_walk_in<V, E>(self, v: &V, path: &Path, errors: &mut Errors<E>) -> Option<Self>
+        where V : TryTraversal<T, E>;
+
+    /// Walks the value, stopping at the first node the filter fails on.
+    fn try_walk<F, E>(self, f: &F) -> Result<Self, NodeError<E>>
+        where F : Fn(T) -> Result<T, E> {
+        let mut errors = Errors { errors: vec![], all: false };
+        match self.try_walk_in(&TryBottomUp(f), &Path::root(), &mut errors) {
+            Some(walked) => Ok(walked),
+            None => Err(errors.errors.remove(0))
+        }
+    }
+
+    /// Walks the value to the end, and returns every failure if there were any.
+    fn try_walk_all<F, E>(self, f: &F) -> Result<Self, Vec<NodeError<E>>>
+        where F : Fn(T) -> Result<T, E> {
+        let mut errors = Errors { errors: vec![], all: true };
+        match self.try_walk_in(&TryBottomUp(f), &Path::root(), &mut errors) {
+            Some(walked) => Ok(walked),
+            None => Err(errors.errors)
+        }
+    }
+
+    /// Like `try_walk`, parents first. Returning `Descent::Stop` leaves the
+    /// children of the returned node as they are.
+    fn try_walk_top_down<F, E>(self, f: &F) -> Result<Self, NodeError<E>>
+        where F : Fn(T) -> Result<(T, Descent), E> {
+        let mut errors = Errors { errors: vec![], all: false };
+        match self.try_walk_in(&TryTopDown(f), &Path::root(), &mut errors) {
+            Some(walked) => Ok(walked),
+            None => Err(errors.errors.remove(0))
+        }
+    }
+}
+
+impl<U, T> TryWalkable<U> for Vec<T>
+    where T : TryWalkable<U> {
+    fn try_walk_in<V, E>(self, v: &V, path: &Path, errors: &mut Errors<E>) -> Option<Self>
+        where V : TryTraversal<U, E> {
+        let mut walked = Vec::with_capacity(self.len());
+        let mut ok = true;
+        for (index, i) in self.into_iter().enumerate() {
+            if errors.stopped() {
+                return None;
+            }
+            match i.try_walk_in(v, &path.index(index), errors) {
+                Some(i) => walked.push(i),
+                None => ok = false
+            }
+        }
+        if ok { Some(walked) } else { None }
+    }
+}
+
+impl<U, T> TryWalkable<U> for Option<T>
+    where T : TryWalkable<U> {
+    fn try_walk_in<V, E>(self, v: &V, path: &Path, errors: &mut Errors<E>) -> Option<Self>
+        where V : TryTraversal<U, E> {
+        match self {
+            Some(i) => i.try_walk_in(v, path, errors).map(Some),
+            None => Some(None)
+        }
+    }
+}
+
+impl<U, K> TryWalkable<U> for BTreeMap<String, K>
+    where K : TryWalkable<U> {
+    fn try_walk_in<V, E>(self, v: &V, path: &Path, errors: &mut Errors<E>) -> Option<Self>
+        where V : TryTraversal<U, E> {
+        let mut walked = BTreeMap::new();
+        let mut ok = true;
+        for (k, x) in self {
+            if errors.stopped() {
+                return None;
+            }
+            match x.try_walk_in(v, &path.key(&k), errors) {
+                Some(x) => { walked.insert(k, x); },
+                None => ok = false
+            }
+        }
+        if ok { Some(walked) } else { None }
+    }
+}
+
+impl TryWalkable<Pandoc> for Pandoc {
+    fn try_walk_in<V, E>(self, v: &V, path: &Path, errors: &mut Errors<E>) -> Option<Self>
+        where V : TryTraversal<Pandoc, E> {
+        v.visit(self, path, errors, |node, _| Some(node))
+    }
+}
+
+impl TryWalkable<Block> for Pandoc {
+    fn try_walk_in<V, E>(self, v: &V, path: &Path, errors: &mut Errors<E>) -> Option<Self>
+        where V : TryTraversal<Block, E> {
+        try_walk_pandoc(self, v, path, errors)
+    }
+}
+
+impl TryWalkable<Inline> for Pandoc {
+    fn try_walk_in<V, E>(self, v: &V, path: &Path, errors: &mut Errors<E>) -> Option<Self>
+        where V : TryTraversal<Inline, E> {
+        try_walk_pandoc(self, v, path, errors)
+    }
+}
+
+fn try_walk_pandoc<T, V, E>(pandoc: Pandoc, v: &V, path: &Path, errors: &mut Errors<E>) -> Option<Pandoc>
+    where V : TryTraversal<T, E>, Meta : TryWalkable<T>, Block : TryWalkable<T> {
+    let Pandoc(meta, blocks, version) = pandoc;
+    let (meta, blocks) = if version.is_some() {
+        (meta.try_walk_in(v, &path.key("meta"), errors),
+         blocks.try_walk_in(v, &path.key("blocks"), errors))
+    } else {
+        (meta.try_walk_in(v, &path.index(0).key("unMeta"), errors),
+         blocks.try_walk_in(v, &path.index(1), errors))
+    };
+    match (meta, blocks) {
+        (Some(meta), Some(blocks)) => Some(Pandoc(meta, blocks, version)),
+        _ => None
+    }
+}
+
+impl TryWalkable<Meta> for Meta {
+    fn try_walk_in<V, E>(self, v: &V, path: &Path, errors: &mut Errors<E>) -> Option<Self>
+        where V : TryTraversal<Meta, E> {
+        v.visit(self, path, errors, |node, _| Some(node))
+    }
+}
+
+impl TryWalkable<Inline> for Meta {
+    fn try_walk_in<V, E>(self, v: &V, path: &Path, errors: &mut Errors<E>) -> Option<Self>
+        where V : TryTraversal<Inline, E> {
+        self.un_meta.try_walk_in(v, path, errors).map(|un_meta| Meta { un_meta: un_meta })
+    }
+}
+
+impl TryWalkable<Block> for Meta {
+    fn try_walk_in<V, E>(self, v: &V, path: &Path, errors: &mut Errors<E>) -> Option<Self>
+        where V : TryTraversal<Block, E> {
+        self.un_meta.try_walk_in(v, path, errors).map(|un_meta| Meta { un_meta: un_meta })
+    }
+}
+
+impl TryWalkable<MetaValue> for MetaValue {
+    fn try_walk_in<V, E>(self, v: &V, path: &Path, errors: &mut Errors<E>) -> Option<Self>
+        where V : TryTraversal<MetaValue, E> {
+        v.visit(self, path, errors, |value, errors| {
+            let c = path.key("c");
+            match value {
+                MetaValue::MetaMap(map) => map.try_walk_in(v, &c, errors).map(MetaValue::MetaMap),
+                MetaValue::MetaList(values) =>
+                    values.try_walk_in(v, &c, errors).map(MetaValue::MetaList),
+                e => Some(e)
+            }
+        })
+    }
+}
+
+impl TryWalkable<Block> for MetaValue {
+    fn try_walk_in<V, E>(self, v: &V, path: &Path, errors: &mut Errors<E>) -> Option<Self>
+        where V : TryTraversal<Block, E> {
+        try_walk_meta_value_children(self, v, path, errors)
+    }
+}
+
+impl TryWalkable<Inline> for MetaValue {
+    fn try_walk_in<V, E>(self, v: &V, path: &Path, errors: &mut Errors<E>) -> Option<Self>
+        where V : TryTraversal<Inline, E> {
+        try_walk_meta_value_children(self, v, path, errors)
+    }
+}
+
+fn try_walk_meta_value_children<T, V, E>(value: MetaValue, v: &V, path: &Path,
+                                         errors: &mut Errors<E>) -> Option<MetaValue>
+    where V : TryTraversal<T, E>,
+          MetaValue : TryWalkable<T>, Inline : TryWalkable<T>, Block : TryWalkable<T> {
+    let c = path.key("c");
+    match value {
+        MetaValue::MetaMap(map) => map.try_walk_in(v, &c, errors).map(MetaValue::MetaMap),
+        MetaValue::MetaList(values) => values.try_walk_in(v, &c, errors).map(MetaValue::MetaList),
+        MetaValue::MetaInlines(inlines) =>
+            inlines.try_walk_in(v, &c, errors).map(MetaValue::MetaInlines),
+        MetaValue::MetaBlocks(blocks) =>
+            blocks.try_walk_in(v, &c, errors).map(MetaValue::MetaBlocks),
+        e => Some(e)
+    }
+}
+
+impl TryWalkable<Block> for Block {
+    fn try_walk_in<V, E>(self, v: &V, path: &Path, errors: &mut Errors<E>) -> Option<Self>
+        where V : TryTraversal<Block, E> {
+        v.visit(self, path, errors, |block, errors| try_walk_block_children(block, v, path, errors))
+    }
+}
+
+impl TryWalkable<Inline> for Block {
+    fn try_walk_in<V, E>(self, v: &V, path: &Path, errors: &mut Errors<E>) -> Option<Self>
+        where V : TryTraversal<Inline, E> {
+        try_walk_block_children(self, v, path, errors)
+    }
+}
+
+fn try_walk_block_children<T, V, E>(block: Block, v: &V, path: &Path,
+                                    errors: &mut Errors<E>) -> Option<Block>
+    where V : TryTraversal<T, E>, Inline : TryWalkable<T>, Block : TryWalkable<T> {
+    let c = path.key("c");
+    match block {
+        Block::Plain(inlines) => inlines.try_walk_in(v, &c, errors).map(Block::Plain),
+        Block::Para(inlines) => inlines.try_walk_in(v, &c, errors).map(Block::Para),
+        Block::LineBlock(lines) => lines.try_walk_in(v, &c, errors).map(Block::LineBlock),
+        Block::BlockQuote(blocks) => blocks.try_walk_in(v, &c, errors).map(Block::BlockQuote),
+        Block::OrderedList(list, vecs_blocks) =>
+            vecs_blocks.try_walk_in(v, &c.index(1), errors)
+                .map(|vecs_blocks| Block::OrderedList(list, vecs_blocks)),
+        Block::BulletList(vecs_blocks) =>
+            vecs_blocks.try_walk_in(v, &c, errors).map(Block::BulletList),
+        Block::DefinitionList(inlines_and_blocks) => {
+            let mut walked = Vec::with_capacity(inlines_and_blocks.len());
+            let mut ok = true;
+            for (index, (is, vbs)) in inlines_and_blocks.into_iter().enumerate() {
+                let item = c.index(index);
+                match (is.try_walk_in(v, &item.index(0), errors),
+                       vbs.try_walk_in(v, &item.index(1), errors)) {
+                    (Some(is), Some(vbs)) => walked.push((is, vbs)),
+                    _ => ok = false
+                }
+            }
+            if ok { Some(Block::DefinitionList(walked)) } else { None }
+        },
+        Block::Header(i, attr, inlines) =>
+            inlines.try_walk_in(v, &c.index(2), errors).map(|inlines| Block::Header(i, attr, inlines)),
+        Block::Table(attr, caption, col_specs, head, bodies, foot) => {
+            match (caption.try_walk_in(v, &c.index(1), errors),
+                   head.try_walk_in(v, &c.index(3), errors),
+                   bodies.try_walk_in(v, &c.index(4), errors),
+                   foot.try_walk_in(v, &c.index(5), errors)) {
+                (Some(caption), Some(head), Some(bodies), Some(foot)) =>
+                    Some(Block::Table(attr, caption, col_specs, head, bodies, foot)),
+                _ => None
+            }
+        },
+        Block::Figure(attr, caption, blocks) => {
+            match (caption.try_walk_in(v, &c.index(1), errors),
+                   blocks.try_walk_in(v, &c.index(2), errors)) {
+                (Some(caption), Some(blocks)) => Some(Block::Figure(attr, caption, blocks)),
+                _ => None
+            }
+        },
+        Block::Div(attr, blocks) =>
+            blocks.try_walk_in(v, &c.index(1), errors).map(|blocks| Block::Div(attr, blocks)),
+        b => Some(b)
+    }
+}
+
+impl TryWalkable<Inline> for Inline {
+    fn try_walk_in<V, E>(self, v: &V, path: &Path, errors: &mut Errors<E>) -> Option<Self>
+        where V : TryTraversal<Inline, E> {
+        v.visit(self, path, errors, |inline, errors| try_walk_inline_children(inline, v, path, errors))
+    }
+}
+
+impl TryWalkable<Block> for Inline {
+    fn try_walk_in<V, E>(self, v: &V, path: &Path, errors: &mut Errors<E>) -> Option<Self>
+        where V : TryTraversal<Block, E> {
+        try_walk_inline_children(self, v, path, errors)
+    }
+}
+
+fn try_walk_inline_children<T, V, E>(inline: Inline, v: &V, path: &Path,
+                                     errors: &mut Errors<E>) -> Option<Inline>
+    where V : TryTraversal<T, E>, Inline : TryWalkable<T>, Block : TryWalkable<T> {
+    let c = path.key("c");
+    match inline {
+        Inline::Emph(inlines) => inlines.try_walk_in(v, &c, errors).map(Inline::Emph),
+        Inline::Underline(inlines) => inlines.try_walk_in(v, &c, errors).map(Inline::Underline),
+        Inline::Strong(inlines) => inlines.try_walk_in(v, &c, errors).map(Inline::Strong),
+        Inline::Strikeout(inlines) => inlines.try_walk_in(v, &c, errors).map(Inline::Strikeout),
+        Inline::Superscript(inlines) =>
+            inlines.try_walk_in(v, &c, errors).map(Inline::Superscript),
+        Inline::Subscript(inlines) => inlines.try_walk_in(v, &c, errors).map(Inline::Subscript),
+        Inline::SmallCaps(inlines) => inlines.try_walk_in(v, &c, errors).map(Inline::SmallCaps),
+        Inline::Quoted(quote_type, inlines) =>
+            inlines.try_walk_in(v, &c.index(1), errors)
+                .map(|inlines| Inline::Quoted(quote_type, inlines)),
+        Inline::Cite(citations, inlines) => {
+            match (citations.try_walk_in(v, &c.index(0), errors),
+                   inlines.try_walk_in(v, &c.index(1), errors)) {
+                (Some(citations), Some(inlines)) => Some(Inline::Cite(citations, inlines)),
+                _ => None
+            }
+        },
+        Inline::Link(attr, inlines, target) =>
+            inlines.try_walk_in(v, &c.index(1), errors)
+                .map(|inlines| Inline::Link(attr, inlines, target)),
+        Inline::Image(attr, inlines, target) =>
+            inlines.try_walk_in(v, &c.index(1), errors)
+                .map(|inlines| Inline::Image(attr, inlines, target)),
+        Inline::Span(attr, inlines) =>
+            inlines.try_walk_in(v, &c.index(1), errors).map(|inlines| Inline::Span(attr, inlines)),
+        Inline::Note(blocks) => blocks.try_walk_in(v, &c, errors).map(Inline::Note),
+        i => Some(i)
+    }
+}
+
+impl<T> TryWalkable<T> for Caption
+    where Inline : TryWalkable<T>, Block : TryWalkable<T> {
+    fn try_walk_in<V, E>(self, v: &V, path: &Path, errors: &mut Errors<E>) -> Option<Self>
+        where V : TryTraversal<T, E> {
+        match (self.0.try_walk_in(v, &path.index(0), errors),
+               self.1.try_walk_in(v, &path.index(1), errors)) {
+            (Some(short), Some(blocks)) => Some(Caption(short, blocks)),
+            _ => None
+        }
+    }
+}
+
+impl<T> TryWalkable<T> for TableHead
+    where Row : TryWalkable<T> {
+    fn try_walk_in<V, E>(self, v: &V, path: &Path, errors: &mut Errors<E>) -> Option<Self>
+        where V : TryTraversal<T, E> {
+        let TableHead(attr, rows) = self;
+        rows.try_walk_in(v, &path.index(1), errors).map(|rows| TableHead(attr, rows))
+    }
+}
+
+impl<T> TryWalkable<T> for TableBody
+    where Row : TryWalkable<T> {
+    fn try_walk_in<V, E>(self, v: &V, path: &Path, errors: &mut Errors<E>) -> Option<Self>
+        where V : TryTraversal<T, E> {
+        let TableBody(attr, row_head_columns, head, body) = self;
+        match (head.try_walk_in(v, &path.index(2), errors),
+               body.try_walk_in(v, &path.index(3), errors)) {
+            (Some(head), Some(body)) => Some(TableBody(attr, row_head_columns, head, body)),
+            _ => None
+        }
+    }
+}
+
+impl<T> TryWalkable<T> for TableFoot
+    where Row : TryWalkable<T> {
+    fn try_walk_in<V, E>(self, v: &V, path: &Path, errors: &mut Errors<E>) -> Option<Self>
+        where V : TryTraversal<T, E> {
+        let TableFoot(attr, rows) = self;
+        rows.try_walk_in(v, &path.index(1), errors).map(|rows| TableFoot(attr, rows))
+    }
+}
+
+impl<T> TryWalkable<T> for Row
+    where Cell : TryWalkable<T> {
+    fn try_walk_in<V, E>(self, v: &V, path: &Path, errors: &mut Errors<E>) -> Option<Self>
+        where V : TryTraversal<T, E> {
+        let Row(attr, cells) = self;
+        cells.try_walk_in(v, &path.index(1), errors).map(|cells| Row(attr, cells))
+    }
+}
+
+impl<T> TryWalkable<T> for Cell
+    where Block : TryWalkable<T> {
+    fn try_walk_in<V, E>(self, v: &V, path: &Path, errors: &mut Errors<E>) -> Option<Self>
+        where V : TryTraversal<T, E> {
+        let Cell(attr, alignment, row_span, col_span, blocks) = self;
+        blocks.try_walk_in(v, &path.index(4), errors)
+            .map(|blocks| Cell(attr, alignment, row_span, col_span, blocks))
+    }
+}
+
+impl<T> TryWalkable<T> for Citation
+    where Inline : TryWalkable<T> {
+    fn try_walk_in<V, E>(self, v: &V, path: &Path, errors: &mut Errors<E>) -> Option<Self>
+        where V : TryTraversal<T, E> {
+        let Citation { citation_id, citation_prefix, citation_suffix,
+                       citation_mode, citation_note_num, citation_hash } = self;
+        match (citation_prefix.try_walk_in(v, &path.key("citationPrefix"), errors),
+               citation_suffix.try_walk_in(v, &path.key("citationSuffix"), errors)) {
+            (Some(prefix), Some(suffix)) => Some(Citation {
+                citation_id: citation_id,
+                citation_prefix: prefix,
+                citation_suffix: suffix,
+                citation_mode: citation_mode,
+                citation_note_num: citation_note_num,
+                citation_hash: citation_hash
+            }),
+            _ => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use try_walk::TryWalkable;
+    use walk::Descent;
+    use types::*;
+    use test_util::{text, attr};
+
+    fn doc() -> Pandoc {
+        Pandoc(Meta { un_meta: BTreeMap::new() },
+               vec![Block::Para(vec![text("1"), Inline::Space, text("2")]),
+                    Block::Div(attr(""),
+                               vec![Block::Plain(vec![Inline::Emph(vec![text("x"), text("y")])])])],
+               Some(vec![1, 23]))
+    }
+
+    fn double(i: Inline) -> Result<Inline, String> {
+        match i {
+            Inline::Str(s) => s.parse::<u64>()
+                .map(|n| Inline::Str((n * 2).to_string()))
+                .map_err(|_| format!("not a number: {}", s)),
+            e => Ok(e)
+        }
+    }
+
+    #[test]
+    fn try_walk_ok() {
+        let walked = Block::Para(vec![text("1"), Inline::Space, text("2")]).try_walk(&double).unwrap();
+        assert_eq!(walked, Block::Para(vec![text("2"), Inline::Space, text("4")]));
+    }
+
+    #[test]
+    fn try_walk_stops_at_first_error() {
+        let e = doc().try_walk(&double).unwrap_err();
+        assert_eq!(e.path, "$.blocks[1].c[1][0].c[0].c[0]");
+        assert_eq!(e.error, "not a number: x");
+    }
+
+    #[test]
+    fn try_walk_all_collects_errors() {
+        let mut legacy = doc();
+        legacy.2 = None;
+        let errors = legacy.try_walk_all(&double).unwrap_err();
+        let paths: Vec<&str> = errors.iter().map(|e| &e.path[..]).collect();
+        assert_eq!(paths, vec!["$[1][1].c[1][0].c[0].c[0]", "$[1][1].c[1][0].c[0].c[1]"]);
+    }
+
+    #[test]
+    fn try_walk_top_down_skips_stopped() {
+        let walked = doc().try_walk_top_down(&(|i| match i {
+            e @ Inline::Emph(_) => Ok((e, Descent::Stop)),
+            e => double(e).map(|e| (e, Descent::Continue))
+        })).unwrap();
+        assert_eq!(walked.1[0], Block::Para(vec![text("2"), Inline::Space, text("4")]));
+        assert_eq!(walked.1[1], doc().1[1]);
+    }
+
+    #[test]
+    fn path_in_upgraded_table() {
+        let json = r#"{"pandoc-api-version":[1,20],"meta":{},"blocks":[{"t":"Table","c":[[],[{"t":"AlignLeft","c":[]}],[0],[[]],[[[{"t":"Plain","c":[{"t":"Str","c":"a"}]}]]]]}]}"#;
+        let e = Pandoc::from_json_str(json).unwrap().try_walk(&double).unwrap_err();
+        assert_eq!(e.path, "$.blocks[0].c[4][0][3][0][1][0][4][0].c[0]");
+    }
+}
@@ -0,0 +1,188 @@
+use std::cmp;
+use std::collections::BTreeSet;
+use types::{Pandoc, Block};
+use iter::{Node, Nodes};
+use query::plain_text;
+use env::FilterContext;
+
+/// A filter that reads the whole document before changing it, for tables of
+/// contents, cross-references and the like: `collect` gathers what it needs
+/// from the document, and `transform` rewrites it with that.
+pub trait TwoPass {
+    type State;
+
+    fn collect(&mut self, pandoc: &Pandoc) -> Self::State;
+
+    fn transform(&mut self, state: Self::State, pandoc: Pandoc) -> Pandoc;
+
+    /// Called by `apply_with_context` before `collect`, for filters that
+    /// depend on the output format or pandoc's options.
+    fn on_context(&mut self, _context: &FilterContext) {}
+
+    /// Runs both passes over `pandoc`.
+    fn apply(&mut self, pandoc: Pandoc) -> Pandoc {
+        let state = self.collect(&pandoc);
+        self.transform(state, pandoc)
+    }
+
+    /// Like `apply`, first passing `context` to `on_context`.
+    fn apply_with_context(&mut self, pandoc: Pandoc, context: &FilterContext) -> Pandoc {
+        self.on_context(context);
+        self.apply(pandoc)
+    }
+}
+
+/// A `TwoPass` made of two functions, as returned by `two_pass`.
+pub struct TwoPassFn<C, T> {
+    collect: C,
+    transform: T
+}
+
+/// Makes a `TwoPass` out of its two passes.
+pub fn two_pass<S, C, T>(collect: C, transform: T) -> TwoPassFn<C, T>
+    where C: FnMut(&Pandoc) -> S, T: FnMut(S, Pandoc) -> Pandoc {
+    TwoPassFn { collect: collect, transform: transform }
+}
+
+impl<S, C, T> TwoPass for TwoPassFn<C, T>
+    where C: FnMut(&Pandoc) -> S, T: FnMut(S, Pandoc) -> Pandoc {
+    type State = S;
+
+    fn collect(&mut self, pandoc: &Pandoc) -> S {
+        (self.collect)(pandoc)
+    }
+
+    fn transform(&mut self, state: S, pandoc: Pandoc) -> Pandoc {
+        (self.transform)(state, pandoc)
+    }
+}
+
+/// A header of the document, as collected by `headers`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Heading {
+    pub level: u64,
+    pub id: String,
+    /// The header's inlines as plain text.
+    pub text: String,
+    /// The section number, e.g. `[2, 1]` for the first subsection of the
+    /// second section. Levels skipped above a header count as 0, and levels
+    /// past `MAX_LEVEL` count as `MAX_LEVEL`.
+    pub number: Vec<u64>
+}
+
+/// The deepest header level `headers` numbers, the deepest HTML has.
+pub const MAX_LEVEL: u64 = 6;
+
+/// Every header in the blocks of the document, in document order, including
+/// those inside divs, lists, tables and notes.
+pub fn headers(pandoc: &Pandoc) -> Vec<Heading> {
+    let mut counters: Vec<u64> = vec![];
+    Nodes::new(pandoc.1.iter().map(Node::Block).collect()).filter_map(|node| match node {
+        Node::Block(&Block::Header(level, ref attr, ref inlines)) => {
+            counters.resize(cmp::min(level, MAX_LEVEL) as usize, 0);
+            if let Some(last) = counters.last_mut() {
+                *last += 1;
+            }
+            Some(Heading { level: level, id: attr.0.clone(), text: plain_text(inlines),
+                           number: counters.clone() })
+        },
+        _ => None
+    }).collect()
+}
+
+/// Every non-empty identifier on a block or inline, metadata included.
+pub fn identifiers(pandoc: &Pandoc) -> BTreeSet<String> {
+    pandoc.nodes().filter_map(|node| match node {
+        Node::Block(b) => b.attr(),
+        Node::Inline(i) => i.attr(),
+        _ => None
+    }).filter(|attr| !attr.0.is_empty()).map(|attr| attr.0.clone()).collect()
+}
+
+/// The id of every citation, metadata included, in the order they first
+/// appear.
+pub fn citations(pandoc: &Pandoc) -> Vec<String> {
+    let mut seen = BTreeSet::new();
+    pandoc.citations_iter()
+        .filter(|c| seen.insert(&c.citation_id[..]))
+        .map(|c| c.citation_id.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use two_pass::{TwoPass, Heading, two_pass, headers, identifiers, citations};
+    use walk::Walkable;
+    use types::*;
+    use test_util::{text, attr};
+
+    fn cite(id: &str) -> Inline {
+        Inline::Cite(vec![Citation { citation_id: String::from(id), citation_prefix: vec![],
+                                     citation_suffix: vec![], citation_mode: CitationMode::NormalCitation,
+                                     citation_note_num: 0, citation_hash: 0 }], vec![])
+    }
+
+    fn doc() -> Pandoc {
+        let mut un_meta = BTreeMap::new();
+        un_meta.insert(String::from("abstract"),
+                       MetaValue::MetaBlocks(vec![Block::Header(1, attr("abstract"), vec![text("Abstract")])]));
+        Pandoc(Meta { un_meta: un_meta },
+               vec![Block::Header(1, attr("intro"), vec![text("Intro"), Inline::Space, Inline::Emph(vec![text("here")])]),
+                    Block::Para(vec![text("See"), Inline::Space,
+                                     Inline::Link(attr(""), vec![], (String::from("#usage"), String::new())),
+                                     cite("doe"), cite("roe"), cite("doe")]),
+                    Block::Header(1, attr("body"), vec![text("Body")]),
+                    Block::Div(attr("box"), vec![Block::Header(2, attr("usage"), vec![text("Usage")])])],
+               None)
+    }
+
+    #[test]
+    fn collect_helpers() {
+        let d = doc();
+        assert_eq!(headers(&d), vec![
+            Heading { level: 1, id: String::from("intro"), text: String::from("Intro here"), number: vec![1] },
+            Heading { level: 1, id: String::from("body"), text: String::from("Body"), number: vec![2] },
+            Heading { level: 2, id: String::from("usage"), text: String::from("Usage"), number: vec![2, 1] }
+        ]);
+        let ids: Vec<String> = identifiers(&d).into_iter().collect();
+        assert_eq!(ids, vec!["abstract", "body", "box", "intro", "usage"]);
+        assert_eq!(citations(&d), vec!["doe", "roe"]);
+    }
+
+    #[test]
+    fn headers_deep_levels_and_notes() {
+        let note = Inline::Note(vec![Block::Para(vec![text("note")])]);
+        let d = Pandoc(Meta { un_meta: BTreeMap::new() },
+                       vec![Block::Header(u64::max_value(), attr("deep"), vec![text("Deep"), note])],
+                       None);
+        assert_eq!(headers(&d), vec![
+            Heading { level: u64::max_value(), id: String::from("deep"), text: String::from("Deep"),
+                      number: vec![0, 0, 0, 0, 0, 1] }
+        ]);
+    }
+
+    #[test]
+    fn section_references() {
+        let mut refs = two_pass(headers, |headings: Vec<Heading>, doc: Pandoc| doc.walk(&|i| match i {
+            Inline::Link(attr, content, target) => if content.is_empty() {
+                let number = headings.iter().find(|h| "#".to_string() + &h.id == target.0).map(|h| {
+                    h.number.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(".")
+                });
+                let content = vec![text(&format!("section {}", number.unwrap_or(String::from("?"))))];
+                Inline::Link(attr, content, target)
+            } else {
+                Inline::Link(attr, content, target)
+            },
+            i => i
+        }));
+        let d = refs.apply(doc());
+        match d.1[1] {
+            Block::Para(ref inlines) => match inlines[2] {
+                Inline::Link(_, ref content, _) => assert_eq!(*content, vec![text("section 2.1")]),
+                _ => panic!()
+            },
+            _ => panic!()
+        }
+    }
+}
@@ -1,51 +1,225 @@
 use std::collections::BTreeMap;
+use std::marker::PhantomData;
 use serde::ser::{Serialize, Serializer};
+use serde::de::{self, Deserialize, Deserializer, Visitor, SeqVisitor, MapVisitor};
+use serde::de::impls::IgnoredAny;
+use serde_json::{self, Value};
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Pandoc(pub Meta, pub Vec<Block>);
+use codec;
 
-#[derive(Debug, Serialize, Deserialize)]
+/// The `pandoc-api-version` a document was written with, e.g. `[1, 23, 1]`.
+pub type ApiVersion = Vec<u64>;
+
+/// A pandoc document. The third field is the API version the document came
+/// with; `None` stands for the legacy `[meta, blocks]` layout used by pandoc
+/// before 1.18.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Pandoc(pub Meta, pub Vec<Block>, pub Option<ApiVersion>);
+
+impl Serialize for Pandoc {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer {
+        AtVersion(self, &self.2).serialize(serializer)
+    }
+}
+
+/// Serializes a document in the layout of another API version than the one
+/// it was read with. Only the envelope changes; see `codec` for the nodes.
+pub struct AtVersion<'a>(pub &'a Pandoc, pub &'a Option<ApiVersion>);
+
+impl<'a> Serialize for AtVersion<'a> {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer {
+        let AtVersion(pandoc, version) = *self;
+        match *version {
+            Some(ref version) => {
+                let mut state = try!(serializer.serialize_map(Some(3)));
+                try!(serializer.serialize_map_key(&mut state, "pandoc-api-version"));
+                try!(serializer.serialize_map_value(&mut state, version));
+                try!(serializer.serialize_map_key(&mut state, "meta"));
+                try!(serializer.serialize_map_value(&mut state, &pandoc.0.un_meta));
+                try!(serializer.serialize_map_key(&mut state, "blocks"));
+                try!(serializer.serialize_map_value(&mut state, &pandoc.1));
+                serializer.serialize_map_end(state)
+            },
+            None => {
+                let mut state = try!(serializer.serialize_tuple_struct("Pandoc", 2));
+                try!(serializer.serialize_tuple_struct_elt(&mut state, &pandoc.0));
+                try!(serializer.serialize_tuple_struct_elt(&mut state, &pandoc.1));
+                serializer.serialize_tuple_struct_end(state)
+            }
+        }
+    }
+}
+
+impl Deserialize for Pandoc {
+    fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+        where D: Deserializer {
+        deserializer.deserialize(PandocVisitor)
+    }
+}
+
+struct PandocVisitor;
+
+impl Visitor for PandocVisitor {
+    type Value = Pandoc;
+
+    fn visit_seq<V>(&mut self, mut visitor: V) -> Result<Pandoc, V::Error>
+        where V: SeqVisitor {
+        let meta = try!(next_element(&mut visitor, 0));
+        let blocks = try!(next_element(&mut visitor, 1));
+        try!(visitor.end());
+        Ok(Pandoc(meta, blocks, None))
+    }
+
+    fn visit_map<V>(&mut self, mut visitor: V) -> Result<Pandoc, V::Error>
+        where V: MapVisitor {
+        let mut version = None;
+        let mut meta = None;
+        let mut blocks = None;
+        while let Some(key) = try!(visitor.visit_key::<String>()) {
+            match &key[..] {
+                "pandoc-api-version" => version = Some(try!(visitor.visit_value())),
+                "meta" => meta = Some(try!(visitor.visit_value())),
+                "blocks" => blocks = Some(try!(visitor.visit_value())),
+                _ => { try!(visitor.visit_value::<IgnoredAny>()); }
+            }
+        }
+        try!(visitor.end());
+        let version = match version {
+            Some(version) => version,
+            None => try!(visitor.missing_field("pandoc-api-version"))
+        };
+        let meta = match meta {
+            Some(meta) => meta,
+            None => try!(visitor.missing_field("meta"))
+        };
+        let blocks = match blocks {
+            Some(blocks) => blocks,
+            None => try!(visitor.missing_field("blocks"))
+        };
+        Ok(Pandoc(Meta { un_meta: meta }, blocks, Some(version)))
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Meta {
     #[serde(rename = "unMeta")]
     pub un_meta: BTreeMap<String, MetaValue>
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub enum MetaValue {
-    MetaMap(BTreeMap<String, MetaValue>),
-    MetaList(Vec<MetaValue>),
-    MetaBool(bool),
-    MetaString(String),
-    MetaInlines(Vec<Inline>),
-    MetaBlocks(Vec<Block>)
+// pandoc writes every constructor as an object with its name under "t" and
+// its fields, if any, under "c": a single field as is, several as an array.
+// `Tagged::decode` builds a value from the name and the "c" field, which is
+// read straight from the input when "t" comes first and buffered otherwise.
+
+trait Tagged: Sized {
+    fn decode<C: Content>(tag: &str, content: C) -> Result<Self, C::Error>;
+}
+
+trait Content {
+    type Error: de::Error;
+    fn take<T: Deserialize>(self) -> Result<T, Self::Error>;
+    fn skip(self) -> Result<(), Self::Error>;
 }
 
-// http://hackage.haskell.org/package/pandoc-types-1.16.1.1/docs/Text-Pandoc-Definition.html#t:Block
-#[derive(PartialEq, Debug, Serialize, Deserialize)]
-pub enum Block {
-    Plain(Vec<Inline>),
-    Para(Vec<Inline>),
-    CodeBlock(Attr, String),
-    RawBlock(Format, String),
-    BlockQuote(Vec<Block>),
-    OrderedList(ListAttributes, Vec<Vec<Block>>),
-    BulletList(Vec<Vec<Block>>),
-    DefinitionList(Vec<(Vec<Inline>, Vec<Vec<Block>>)>),
-    Header(u64, Attr, Vec<Inline>),
-    HorizontalRule,
-    Table(Vec<Inline>, Vec<Alignment>, Vec<f64>, Vec<TableCell>, Vec<Vec<TableCell>>),
-    Div(Attr, Vec<Block>),
-    Null
+struct MapContent<'a, V: 'a>(&'a mut V);
+
+impl<'a, V: MapVisitor> Content for MapContent<'a, V> {
+    type Error = V::Error;
+
+    fn take<T: Deserialize>(self) -> Result<T, V::Error> {
+        self.0.visit_value()
+    }
+
+    fn skip(self) -> Result<(), V::Error> {
+        self.0.visit_value::<IgnoredAny>().map(|_| ())
+    }
 }
 
-pub type ListAttributes = (u64, ListNumberStyle, ListNumberDelim);
+struct BufferedContent<E>(Option<Value>, PhantomData<E>);
+
+impl<E: de::Error> Content for BufferedContent<E> {
+    type Error = E;
+
+    fn take<T: Deserialize>(self) -> Result<T, E> {
+        match self.0 {
+            Some(value) => from_value(value),
+            None => Err(E::missing_field("c"))
+        }
+    }
+
+    fn skip(self) -> Result<(), E> {
+        Ok(())
+    }
+}
+
+struct TaggedVisitor<T>(PhantomData<T>);
+
+impl<T: Tagged + Deserialize> Visitor for TaggedVisitor<T> {
+    type Value = T;
+
+    fn visit_map<V>(&mut self, mut visitor: V) -> Result<T, V::Error>
+        where V: MapVisitor {
+        let mut tag: Option<String> = None;
+        let mut value = None;
+        let mut buffered = None;
+        while let Some(key) = try!(visitor.visit_key::<String>()) {
+            match &key[..] {
+                "t" => tag = Some(try!(visitor.visit_value())),
+                "c" => match tag {
+                    Some(ref tag) => value = Some(try!(T::decode(tag, MapContent(&mut visitor)))),
+                    None => buffered = Some(try!(visitor.visit_value::<Value>()))
+                },
+                _ => { try!(visitor.visit_value::<IgnoredAny>()); }
+            }
+        }
+        try!(visitor.end());
+        match (value, tag) {
+            (Some(value), _) => Ok(value),
+            (None, Some(tag)) => T::decode(&tag, BufferedContent(buffered, PhantomData)),
+            (None, None) => visitor.missing_field("t")
+        }
+    }
+}
+
+fn serialize_tagged<S, C>(serializer: &mut S, tag: &'static str, content: &C) -> Result<(), S::Error>
+    where S: Serializer, C: Serialize {
+    let mut state = try!(serializer.serialize_map(Some(2)));
+    try!(serializer.serialize_map_key(&mut state, "t"));
+    try!(serializer.serialize_map_value(&mut state, tag));
+    try!(serializer.serialize_map_key(&mut state, "c"));
+    try!(serializer.serialize_map_value(&mut state, content));
+    serializer.serialize_map_end(state)
+}
+
+fn serialize_unit_tagged<S>(serializer: &mut S, tag: &'static str) -> Result<(), S::Error>
+    where S: Serializer {
+    let mut state = try!(serializer.serialize_map(Some(1)));
+    try!(serializer.serialize_map_key(&mut state, "t"));
+    try!(serializer.serialize_map_value(&mut state, tag));
+    serializer.serialize_map_end(state)
+}
+
+fn next_element<V, T>(visitor: &mut V, index: usize) -> Result<T, V::Error>
+    where V: SeqVisitor, T: Deserialize {
+    match try!(visitor.visit()) {
+        Some(element) => Ok(element),
+        None => Err(de::Error::invalid_length(index))
+    }
+}
+
+fn from_value<T, E>(value: Value) -> Result<T, E>
+    where T: Deserialize, E: de::Error {
+    serde_json::from_value(value).map_err(|e| E::custom(e.to_string()))
+}
 
 macro_rules! serialize_enum {
     ($name:ident,
      units = { $( $unit:ident ),* },
      newtypes = { $( $newtype:ident[$val_ident:ident, $newtype_val:ty] ),* },
      tuples = { $( $tuple:ident[$( $el_ident:ident=$tuple_el:ty ),*] ),* }) => {
-        #[derive(PartialEq, Debug, Deserialize)]
+        #[derive(Clone, PartialEq, Debug)]
         pub enum $name {
             $( $unit, )*
             $( $newtype($newtype_val), )*
@@ -57,35 +231,277 @@ macro_rules! serialize_enum {
                 where S: Serializer {
                 match *self {
                     $(
-                        $name::$unit => {
-                            let mut state = try!(serializer.serialize_map(Some(1)));
-                            try!(serializer.serialize_map_key(&mut state, stringify!($unit)));
-                            let v: Vec<String> = Vec::new();
-                            try!(serializer.serialize_map_value(&mut state, v));
-                            serializer.serialize_map_end(state)
-                        },
+                        $name::$unit => serialize_unit_tagged(serializer, stringify!($unit)),
                     )*
                     $(
                         $name::$newtype(ref $val_ident) =>
-                            serializer.serialize_newtype_variant(stringify!($name), 0,
-                                                                 stringify!($newtype), $val_ident),
+                            serialize_tagged(serializer, stringify!($newtype), $val_ident),
                     )*
                     $(
-                        $name::$tuple( $( ref $el_ident ),* ) => {
-                            let mut state = try!(serializer.serialize_tuple_variant(stringify!($name), 0,
-                                                                                    stringify!($tuple), 2));
-                            $(
-                                try!(serializer.serialize_tuple_variant_elt(&mut state, $el_ident));
-                            )*
-                                serializer.serialize_tuple_variant_end(state)
+                        $name::$tuple( $( ref $el_ident ),* ) =>
+                            serialize_tagged(serializer, stringify!($tuple), &( $( $el_ident, )* )),
+                    )*
+                }
+            }
+        }
+
+        impl $name {
+            /// The `"t"` of every constructor.
+            pub const TAGS: &'static [&'static str] = &[
+                $( stringify!($unit), )*
+                $( stringify!($newtype), )*
+                $( stringify!($tuple), )*
+            ];
+
+            /// The `"t"` this value is written with.
+            pub fn tag(&self) -> &'static str {
+                match *self {
+                    $( $name::$unit => stringify!($unit), )*
+                    $( $name::$newtype(_) => stringify!($newtype), )*
+                    $( $name::$tuple(..) => stringify!($tuple), )*
+                }
+            }
+        }
+
+        impl Tagged for $name {
+            fn decode<C: Content>(tag: &str, content: C) -> Result<Self, C::Error> {
+                match tag {
+                    $(
+                        stringify!($unit) => {
+                            try!(content.skip());
+                            Ok($name::$unit)
+                        },
+                    )*
+                    $(
+                        stringify!($newtype) => Ok($name::$newtype(try!(content.take()))),
+                    )*
+                    $(
+                        stringify!($tuple) => {
+                            let ( $( $el_ident, )* ): ( $( $tuple_el, )* ) = try!(content.take());
+                            Ok($name::$tuple( $( $el_ident ),* ))
                         },
                     )*
+                    _ => Err(de::Error::unknown_variant(tag))
                 }
             }
         }
+
+        impl Deserialize for $name {
+            fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+                where D: Deserializer {
+                deserializer.deserialize_map(TaggedVisitor(PhantomData))
+            }
+        }
     }
 }
 
+serialize_enum!(
+    MetaValue,
+    units = {},
+    newtypes = {
+        MetaMap[m, BTreeMap<String, MetaValue>],
+        MetaList[v, Vec<MetaValue>],
+        MetaBool[b, bool],
+        MetaString[s, String],
+        MetaInlines[v, Vec<Inline>],
+        MetaBlocks[v, Vec<Block>]
+    },
+    tuples = {}
+);
+
+// http://hackage.haskell.org/package/pandoc-types-1.23.1/docs/Text-Pandoc-Definition.html#t:Block
+#[derive(Clone, PartialEq, Debug)]
+pub enum Block {
+    Plain(Vec<Inline>),
+    Para(Vec<Inline>),
+    LineBlock(Vec<Vec<Inline>>),
+    CodeBlock(Attr, String),
+    RawBlock(Format, String),
+    BlockQuote(Vec<Block>),
+    OrderedList(ListAttributes, Vec<Vec<Block>>),
+    BulletList(Vec<Vec<Block>>),
+    DefinitionList(Vec<(Vec<Inline>, Vec<Vec<Block>>)>),
+    Header(u64, Attr, Vec<Inline>),
+    HorizontalRule,
+    Table(Attr, Caption, Vec<ColSpec>, TableHead, Vec<TableBody>, TableFoot),
+    Figure(Attr, Caption, Vec<Block>),
+    Div(Attr, Vec<Block>),
+    // Removed from pandoc-types in 1.22, kept for documents written by older
+    // versions. It is written as `{"t":"Null"}`, and `codec::downgrade` drops
+    // it when writing for 1.22 and later (see `codec::drops_null` and
+    // `Pandoc::needs_downgrade`).
+    Null
+}
+
+impl Block {
+    /// The `"t"` of every constructor.
+    pub const TAGS: &'static [&'static str] = &[
+        "Plain", "Para", "LineBlock", "CodeBlock", "RawBlock", "BlockQuote", "OrderedList",
+        "BulletList", "DefinitionList", "Header", "HorizontalRule", "Table", "Figure", "Div",
+        "Null"
+    ];
+
+    /// The `"t"` this value is written with.
+    pub fn tag(&self) -> &'static str {
+        match *self {
+            Block::Plain(_) => "Plain",
+            Block::Para(_) => "Para",
+            Block::LineBlock(_) => "LineBlock",
+            Block::CodeBlock(..) => "CodeBlock",
+            Block::RawBlock(..) => "RawBlock",
+            Block::BlockQuote(_) => "BlockQuote",
+            Block::OrderedList(..) => "OrderedList",
+            Block::BulletList(_) => "BulletList",
+            Block::DefinitionList(_) => "DefinitionList",
+            Block::Header(..) => "Header",
+            Block::HorizontalRule => "HorizontalRule",
+            Block::Table(..) => "Table",
+            Block::Figure(..) => "Figure",
+            Block::Div(..) => "Div",
+            Block::Null => "Null"
+        }
+    }
+
+    /// The attributes of the blocks that have them.
+    pub fn attr(&self) -> Option<&Attr> {
+        match *self {
+            Block::CodeBlock(ref a, _) | Block::Header(_, ref a, _) | Block::Table(ref a, ..) |
+            Block::Figure(ref a, ..) | Block::Div(ref a, _) => Some(a),
+            _ => None
+        }
+    }
+}
+
+// `Block` is not generated by `serialize_enum!` because of `Null` and of
+// `Table`, which is also read in the layout used before pandoc-types 1.21.
+impl Serialize for Block {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer {
+        match *self {
+            Block::Plain(ref v) => serialize_tagged(serializer, "Plain", v),
+            Block::Para(ref v) => serialize_tagged(serializer, "Para", v),
+            Block::LineBlock(ref v) => serialize_tagged(serializer, "LineBlock", v),
+            Block::CodeBlock(ref a, ref s) => serialize_tagged(serializer, "CodeBlock", &(a, s)),
+            Block::RawBlock(ref f, ref s) => serialize_tagged(serializer, "RawBlock", &(f, s)),
+            Block::BlockQuote(ref v) => serialize_tagged(serializer, "BlockQuote", v),
+            Block::OrderedList(ref l, ref v) => serialize_tagged(serializer, "OrderedList", &(l, v)),
+            Block::BulletList(ref v) => serialize_tagged(serializer, "BulletList", v),
+            Block::DefinitionList(ref v) => serialize_tagged(serializer, "DefinitionList", v),
+            Block::Header(ref n, ref a, ref v) => serialize_tagged(serializer, "Header", &(n, a, v)),
+            Block::HorizontalRule => serialize_unit_tagged(serializer, "HorizontalRule"),
+            Block::Table(ref a, ref c, ref s, ref h, ref b, ref f) =>
+                serialize_tagged(serializer, "Table", &(a, c, s, h, b, f)),
+            Block::Figure(ref a, ref c, ref v) => serialize_tagged(serializer, "Figure", &(a, c, v)),
+            Block::Div(ref a, ref v) => serialize_tagged(serializer, "Div", &(a, v)),
+            Block::Null => serialize_unit_tagged(serializer, "Null"),
+        }
+    }
+}
+
+impl Tagged for Block {
+    fn decode<C: Content>(tag: &str, content: C) -> Result<Self, C::Error> {
+        match tag {
+            "Plain" => Ok(Block::Plain(try!(content.take()))),
+            "Para" => Ok(Block::Para(try!(content.take()))),
+            "LineBlock" => Ok(Block::LineBlock(try!(content.take()))),
+            "CodeBlock" => {
+                let (a, s) = try!(content.take());
+                Ok(Block::CodeBlock(a, s))
+            },
+            "RawBlock" => {
+                let (f, s) = try!(content.take());
+                Ok(Block::RawBlock(f, s))
+            },
+            "BlockQuote" => Ok(Block::BlockQuote(try!(content.take()))),
+            "OrderedList" => {
+                let (l, v) = try!(content.take());
+                Ok(Block::OrderedList(l, v))
+            },
+            "BulletList" => Ok(Block::BulletList(try!(content.take()))),
+            "DefinitionList" => Ok(Block::DefinitionList(try!(content.take()))),
+            "Header" => {
+                let (n, a, v) = try!(content.take());
+                Ok(Block::Header(n, a, v))
+            },
+            "HorizontalRule" => {
+                try!(content.skip());
+                Ok(Block::HorizontalRule)
+            },
+            "Table" => {
+                let table: TableContent = try!(content.take());
+                Ok(table.0)
+            },
+            "Figure" => {
+                let (a, c, v) = try!(content.take());
+                Ok(Block::Figure(a, c, v))
+            },
+            "Div" => {
+                let (a, v) = try!(content.take());
+                Ok(Block::Div(a, v))
+            },
+            "Null" => {
+                try!(content.skip());
+                Ok(Block::Null)
+            },
+            _ => Err(de::Error::unknown_variant(tag))
+        }
+    }
+}
+
+impl Deserialize for Block {
+    fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+        where D: Deserializer {
+        deserializer.deserialize_map(TaggedVisitor(PhantomData))
+    }
+}
+
+/// The content of a `Table` node, in either the current layout or the
+/// `[caption, aligns, widths, headers, rows]` one used before pandoc-types 1.21.
+struct TableContent(Block);
+
+impl Deserialize for TableContent {
+    fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+        where D: Deserializer {
+        deserializer.deserialize_seq(TableContentVisitor)
+    }
+}
+
+struct TableContentVisitor;
+
+impl Visitor for TableContentVisitor {
+    type Value = TableContent;
+
+    fn visit_seq<V>(&mut self, mut visitor: V) -> Result<TableContent, V::Error>
+        where V: SeqVisitor {
+        // The current layout starts with an `Attr`, the old one with the
+        // caption inlines, so the first element tells them apart.
+        let first: Value = try!(next_element(&mut visitor, 0));
+        let current = first.as_array()
+            .and_then(|arr| arr.first())
+            .map_or(false, |id| id.is_string());
+        let table = if current {
+            let attr = try!(from_value(first));
+            let caption = try!(next_element(&mut visitor, 1));
+            let col_specs = try!(next_element(&mut visitor, 2));
+            let head = try!(next_element(&mut visitor, 3));
+            let bodies = try!(next_element(&mut visitor, 4));
+            let foot = try!(next_element(&mut visitor, 5));
+            Block::Table(attr, caption, col_specs, head, bodies, foot)
+        } else {
+            let caption = try!(from_value(first));
+            let aligns = try!(next_element(&mut visitor, 1));
+            let widths = try!(next_element(&mut visitor, 2));
+            let headers = try!(next_element(&mut visitor, 3));
+            let rows = try!(next_element(&mut visitor, 4));
+            codec::table_from_legacy(caption, aligns, widths, headers, rows)
+        };
+        try!(visitor.end());
+        Ok(TableContent(table))
+    }
+}
+
+pub type ListAttributes = (u64, ListNumberStyle, ListNumberDelim);
+
 serialize_enum!(
     ListNumberStyle,
     units = {
@@ -122,9 +538,51 @@ serialize_enum!(
     newtypes = {}, tuples = {}
 );
 
-type TableCell = Vec<Block>;
+serialize_enum!(
+    ColWidth,
+    units = {
+        ColWidthDefault
+    },
+    newtypes = {
+        ColWidth[w, f64]
+    },
+    tuples = {}
+);
+
+// http://hackage.haskell.org/package/pandoc-types-1.21/docs/Text-Pandoc-Definition.html#t:Table
+pub type ShortCaption = Vec<Inline>;
+pub type ColSpec = (Alignment, ColWidth);
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Caption(pub Option<ShortCaption>, pub Vec<Block>);
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct RowHeadColumns(pub u64);
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct RowSpan(pub u64);
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ColSpan(pub u64);
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct TableHead(pub Attr, pub Vec<Row>);
+
+/// A table body: the number of row head columns, the intermediate head rows
+/// and the body rows.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct TableBody(pub Attr, pub RowHeadColumns, pub Vec<Row>, pub Vec<Row>);
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct TableFoot(pub Attr, pub Vec<Row>);
 
-// http://hackage.haskell.org/package/pandoc-types-1.16.1.1/docs/Text-Pandoc-Definition.html#t:Inline
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Row(pub Attr, pub Vec<Cell>);
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Cell(pub Attr, pub Alignment, pub RowSpan, pub ColSpan, pub Vec<Block>);
+
+// http://hackage.haskell.org/package/pandoc-types-1.23.1/docs/Text-Pandoc-Definition.html#t:Inline
 serialize_enum!(
     Inline,
     units = {
@@ -135,11 +593,13 @@ serialize_enum!(
     newtypes = {
         Str[s, String],
         Emph[v, Vec<Inline>],
+        Underline[v, Vec<Inline>],
         Strong[v, Vec<Inline>],
         Strikeout[v, Vec<Inline>],
         Superscript[v, Vec<Inline>],
         Subscript[v, Vec<Inline>],
-        SmallCaps[v, Vec<Inline>]
+        SmallCaps[v, Vec<Inline>],
+        Note[v, Vec<Block>]
     },
     tuples = {
         Quoted[q=QuoteType, v=Vec<Inline>],
@@ -153,6 +613,17 @@ serialize_enum!(
     }
 );
 
+impl Inline {
+    /// The attributes of the inlines that have them.
+    pub fn attr(&self) -> Option<&Attr> {
+        match *self {
+            Inline::Code(ref a, _) | Inline::Link(ref a, ..) | Inline::Image(ref a, ..) |
+            Inline::Span(ref a, _) => Some(a),
+            _ => None
+        }
+    }
+}
+
 serialize_enum!(
     QuoteType,
     units = {
@@ -171,11 +642,13 @@ serialize_enum!(
     newtypes = {}, tuples = {}
 );
 
-pub type Format = String;
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Format(pub String);
+
 pub type Attr = (String, Vec<String>, Vec<(String, String)>);
 pub type Target = (String, String);
 
-#[derive(PartialEq, Debug, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Citation {
     #[serde(rename = "citationId")]
     pub citation_id: String,
@@ -191,16 +664,22 @@ pub struct Citation {
     pub citation_hash: u64
 }
 
-#[derive(PartialEq, Debug, Serialize, Deserialize)]
-pub enum CitationMode {
-    AuthorInText,
-    SuppressAuthor,
-    NormalCitation
-}
+serialize_enum!(
+    CitationMode,
+    units = {
+        AuthorInText,
+        SuppressAuthor,
+        NormalCitation
+    },
+    newtypes = {}, tuples = {}
+);
 
 #[cfg(test)]
 mod tests {
     use serde_json::ser::to_string;
+    use serde_json::de::from_str;
+    use serde::{Serialize, Deserialize};
+    use std::fmt::Debug;
     use types::*;
     use std::collections::BTreeMap;
 
@@ -239,6 +718,12 @@ mod tests {
         (0, ListNumberStyle::DefaultStyle, ListNumberDelim::DefaultDelim)
     }
 
+    fn row_base_val() -> Row {
+        Row(attr_base_val(),
+            vec![Cell(attr_base_val(), Alignment::AlignDefault, RowSpan(1), ColSpan(1),
+                      vec![block_base_val()])])
+    }
+
     fn citation_base_val() -> Citation {
         Citation {
             citation_id: String::from("test"),
@@ -250,18 +735,48 @@ mod tests {
         }
     }
 
+    fn roundtrip<T>(value: T)
+        where T: Serialize + Deserialize + PartialEq + Debug {
+        let json = to_string(&value).unwrap();
+        assert_eq!(from_str::<T>(&json).unwrap(), value);
+    }
+
+    #[test]
+    fn deserialize_roundtrip() {
+        roundtrip(Pandoc(meta_base_val(), vec![block_base_val()], None));
+        roundtrip(Pandoc(meta_base_val(), vec![block_base_val()], Some(vec![1, 23, 1])));
+        roundtrip(Block::OrderedList(list_attributes_base_val(), vec![vec![block_base_val()]]));
+        roundtrip(Block::Figure(attr_base_val(), Caption(Some(vec![]), vec![]),
+                                vec![Block::HorizontalRule]));
+        roundtrip(Inline::Cite(vec![citation_base_val()], vec![Inline::Space]));
+        roundtrip(Inline::Link(attr_base_val(), vec![inline_base_val()],
+                               (String::from("url"), String::from("title"))));
+        roundtrip(MetaValue::MetaList(vec![MetaValue::MetaBool(false)]));
+    }
+
+    #[test]
+    fn deserialize_tagged() {
+        assert_eq!(from_str::<Inline>(r#"{"c":"test","t":"Str"}"#).unwrap(), inline_base_val());
+        assert_eq!(from_str::<Inline>(r#"{"t":"Space","c":[]}"#).unwrap(), Inline::Space);
+        assert_eq!(from_str::<Block>(r#"{"t":"Null"}"#).unwrap(), Block::Null);
+        assert!(from_str::<Inline>(r#"{"t":"Str"}"#).is_err());
+        assert!(from_str::<Inline>(r#"{"t":"Unknown","c":[]}"#).is_err());
+    }
+
     #[test]
     fn serialize_pandoc() {
         test_serialize!(
-            Pandoc(meta_base_val(), vec![block_base_val()]) =>
-                r#"[{"unMeta":{"test":{"MetaString":"test"}}},[{"Plain":[{"Str":"test"}]}]]"#
+            Pandoc(meta_base_val(), vec![block_base_val()], None) =>
+                r#"[{"unMeta":{"test":{"t":"MetaString","c":"test"}}},[{"t":"Plain","c":[{"t":"Str","c":"test"}]}]]"#,
+            Pandoc(meta_base_val(), vec![block_base_val()], Some(vec![1, 23, 1])) =>
+                r#"{"pandoc-api-version":[1,23,1],"meta":{"test":{"t":"MetaString","c":"test"}},"blocks":[{"t":"Plain","c":[{"t":"Str","c":"test"}]}]}"#
         );
     }
 
     #[test]
     fn serialize_meta() {
         test_serialize!(
-            meta_base_val() => r#"{"unMeta":{"test":{"MetaString":"test"}}}"#
+            meta_base_val() => r#"{"unMeta":{"test":{"t":"MetaString","c":"test"}}}"#
         );
     }
 
@@ -270,76 +785,85 @@ mod tests {
         let mut map = BTreeMap::new();
         map.insert(String::from("test"), meta_value_base_val());
         test_serialize!(
-            MetaValue::MetaMap(map) => r#"{"MetaMap":{"test":{"MetaString":"test"}}}"#,
-            MetaValue::MetaList(vec![meta_value_base_val()]) => r#"{"MetaList":[{"MetaString":"test"}]}"#,
-            MetaValue::MetaBool(true) => r#"{"MetaBool":true}"#,
-            meta_value_base_val() => r#"{"MetaString":"test"}"#,
-            MetaValue::MetaInlines(vec![inline_base_val()]) => r#"{"MetaInlines":[{"Str":"test"}]}"#,
-            MetaValue::MetaBlocks(vec![block_base_val()]) => r#"{"MetaBlocks":[{"Plain":[{"Str":"test"}]}]}"#
+            MetaValue::MetaMap(map) => r#"{"t":"MetaMap","c":{"test":{"t":"MetaString","c":"test"}}}"#,
+            MetaValue::MetaList(vec![meta_value_base_val()]) => r#"{"t":"MetaList","c":[{"t":"MetaString","c":"test"}]}"#,
+            MetaValue::MetaBool(true) => r#"{"t":"MetaBool","c":true}"#,
+            meta_value_base_val() => r#"{"t":"MetaString","c":"test"}"#,
+            MetaValue::MetaInlines(vec![inline_base_val()]) => r#"{"t":"MetaInlines","c":[{"t":"Str","c":"test"}]}"#,
+            MetaValue::MetaBlocks(vec![block_base_val()]) => r#"{"t":"MetaBlocks","c":[{"t":"Plain","c":[{"t":"Str","c":"test"}]}]}"#
         );
     }
 
     #[test]
     fn serialize_block() {
         test_serialize!(
-            block_base_val() => r#"{"Plain":[{"Str":"test"}]}"#,
-            Block::Para(vec![inline_base_val()]) => r#"{"Para":[{"Str":"test"}]}"#,
+            block_base_val() => r#"{"t":"Plain","c":[{"t":"Str","c":"test"}]}"#,
+            Block::Para(vec![inline_base_val()]) => r#"{"t":"Para","c":[{"t":"Str","c":"test"}]}"#,
             Block::CodeBlock(attr_base_val(), String::from("test")) => 
-                   r#"{"CodeBlock":[["test",["test"],[["test","test"]]],"test"]}"#,
-            Block::RawBlock(String::from("test"), String::from("test")) =>
-                r#"{"RawBlock":["test","test"]}"#,
+                   r#"{"t":"CodeBlock","c":[["test",["test"],[["test","test"]]],"test"]}"#,
+            Block::LineBlock(vec![vec![inline_base_val()], vec![]]) =>
+                r#"{"t":"LineBlock","c":[[{"t":"Str","c":"test"}],[]]}"#,
+            Block::RawBlock(Format(String::from("test")), String::from("test")) =>
+                r#"{"t":"RawBlock","c":["test","test"]}"#,
             Block::BlockQuote(vec![block_base_val()]) =>
-                r#"{"BlockQuote":[{"Plain":[{"Str":"test"}]}]}"#,
+                r#"{"t":"BlockQuote","c":[{"t":"Plain","c":[{"t":"Str","c":"test"}]}]}"#,
             Block::OrderedList(list_attributes_base_val(), vec![vec![block_base_val()]]) =>
-                r#"{"OrderedList":[[0,{"DefaultStyle":[]},{"DefaultDelim":[]}],[[{"Plain":[{"Str":"test"}]}]]]}"#,
+                r#"{"t":"OrderedList","c":[[0,{"t":"DefaultStyle"},{"t":"DefaultDelim"}],[[{"t":"Plain","c":[{"t":"Str","c":"test"}]}]]]}"#,
             Block::BulletList(vec![vec![block_base_val()]]) =>
-                r#"{"BulletList":[[{"Plain":[{"Str":"test"}]}]]}"#,
+                r#"{"t":"BulletList","c":[[{"t":"Plain","c":[{"t":"Str","c":"test"}]}]]}"#,
             Block::DefinitionList(vec![(vec![inline_base_val()], vec![vec![block_base_val()]])]) =>
-                r#"{"DefinitionList":[[[{"Str":"test"}],[[{"Plain":[{"Str":"test"}]}]]]]}"#,
+                r#"{"t":"DefinitionList","c":[[[{"t":"Str","c":"test"}],[[{"t":"Plain","c":[{"t":"Str","c":"test"}]}]]]]}"#,
             Block::Header(0, attr_base_val(), vec![inline_base_val()]) =>
-                r#"{"Header":[0,["test",["test"],[["test","test"]]],[{"Str":"test"}]]}"#,
-            Block::HorizontalRule => "\"HorizontalRule\"",
-            Block::Table(vec![inline_base_val()], vec![Alignment::AlignLeft],
-                         vec![0.0], vec![vec![block_base_val()]],
-                         vec![vec![vec![block_base_val()]]]) =>
-                r#"{"Table":[[{"Str":"test"}],[{"AlignLeft":[]}],[0.0],[[{"Plain":[{"Str":"test"}]}]],[[[{"Plain":[{"Str":"test"}]}]]]]}"#,
+                r#"{"t":"Header","c":[0,["test",["test"],[["test","test"]]],[{"t":"Str","c":"test"}]]}"#,
+            Block::HorizontalRule => r#"{"t":"HorizontalRule"}"#,
+            Block::Table(attr_base_val(),
+                         Caption(None, vec![block_base_val()]),
+                         vec![(Alignment::AlignLeft, ColWidth::ColWidthDefault)],
+                         TableHead(attr_base_val(), vec![row_base_val()]),
+                         vec![TableBody(attr_base_val(), RowHeadColumns(0), vec![], vec![row_base_val()])],
+                         TableFoot(attr_base_val(), vec![])) =>
+                r#"{"t":"Table","c":[["test",["test"],[["test","test"]]],[null,[{"t":"Plain","c":[{"t":"Str","c":"test"}]}]],[[{"t":"AlignLeft"},{"t":"ColWidthDefault"}]],[["test",["test"],[["test","test"]]],[[["test",["test"],[["test","test"]]],[[["test",["test"],[["test","test"]]],{"t":"AlignDefault"},1,1,[{"t":"Plain","c":[{"t":"Str","c":"test"}]}]]]]]],[[["test",["test"],[["test","test"]]],0,[],[[["test",["test"],[["test","test"]]],[[["test",["test"],[["test","test"]]],{"t":"AlignDefault"},1,1,[{"t":"Plain","c":[{"t":"Str","c":"test"}]}]]]]]]],[["test",["test"],[["test","test"]]],[]]]}"#,
+            Block::Figure(attr_base_val(), Caption(None, vec![]), vec![block_base_val()]) =>
+                r#"{"t":"Figure","c":[["test",["test"],[["test","test"]]],[null,[]],[{"t":"Plain","c":[{"t":"Str","c":"test"}]}]]}"#,
             Block::Div(attr_base_val(), vec![block_base_val()]) =>
-                r#"{"Div":[["test",["test"],[["test","test"]]],[{"Plain":[{"Str":"test"}]}]]}"#,
-            Block::Null => "\"Null\""
+                r#"{"t":"Div","c":[["test",["test"],[["test","test"]]],[{"t":"Plain","c":[{"t":"Str","c":"test"}]}]]}"#,
+            Block::Null => r#"{"t":"Null"}"#
         );
     }
 
     #[test]
     fn serialize_inline() {
         test_serialize!(
-            Inline::Space => r#"{"Space":[]}"#,
-            Inline::SoftBreak => r#"{"SoftBreak":[]}"#,
-            Inline::LineBreak => r#"{"LineBreak":[]}"#,
-            inline_base_val() => r#"{"Str":"test"}"#,
-            Inline::Emph(vec![inline_base_val()]) => r#"{"Emph":[{"Str":"test"}]}"#,
-            Inline::Strong(vec![inline_base_val()]) => r#"{"Strong":[{"Str":"test"}]}"#,
-            Inline::Strikeout(vec![inline_base_val()]) => r#"{"Strikeout":[{"Str":"test"}]}"#,
-            Inline::Superscript(vec![inline_base_val()]) => r#"{"Superscript":[{"Str":"test"}]}"#,
-            Inline::Subscript(vec![inline_base_val()]) => r#"{"Subscript":[{"Str":"test"}]}"#,
-            Inline::SmallCaps(vec![inline_base_val()]) => r#"{"SmallCaps":[{"Str":"test"}]}"#,
+            Inline::Space => r#"{"t":"Space"}"#,
+            Inline::SoftBreak => r#"{"t":"SoftBreak"}"#,
+            Inline::LineBreak => r#"{"t":"LineBreak"}"#,
+            inline_base_val() => r#"{"t":"Str","c":"test"}"#,
+            Inline::Emph(vec![inline_base_val()]) => r#"{"t":"Emph","c":[{"t":"Str","c":"test"}]}"#,
+            Inline::Underline(vec![inline_base_val()]) => r#"{"t":"Underline","c":[{"t":"Str","c":"test"}]}"#,
+            Inline::Strong(vec![inline_base_val()]) => r#"{"t":"Strong","c":[{"t":"Str","c":"test"}]}"#,
+            Inline::Strikeout(vec![inline_base_val()]) => r#"{"t":"Strikeout","c":[{"t":"Str","c":"test"}]}"#,
+            Inline::Superscript(vec![inline_base_val()]) => r#"{"t":"Superscript","c":[{"t":"Str","c":"test"}]}"#,
+            Inline::Subscript(vec![inline_base_val()]) => r#"{"t":"Subscript","c":[{"t":"Str","c":"test"}]}"#,
+            Inline::SmallCaps(vec![inline_base_val()]) => r#"{"t":"SmallCaps","c":[{"t":"Str","c":"test"}]}"#,
             Inline::Quoted(QuoteType::SingleQuote, vec![inline_base_val()]) =>
-                r#"{"Quoted":[{"SingleQuote":[]},[{"Str":"test"}]]}"#,
+                r#"{"t":"Quoted","c":[{"t":"SingleQuote"},[{"t":"Str","c":"test"}]]}"#,
             Inline::Cite(vec![citation_base_val()], vec![inline_base_val()]) =>
-                r#"{"Cite":[[{"citationId":"test","citationPrefix":[{"Str":"test"}],"citationSuffix":[{"Str":"test"}],"citationMode":"AuthorInText","citationNoteNum":0,"citationHash":0}],[{"Str":"test"}]]}"#,
+                r#"{"t":"Cite","c":[[{"citationId":"test","citationPrefix":[{"t":"Str","c":"test"}],"citationSuffix":[{"t":"Str","c":"test"}],"citationMode":{"t":"AuthorInText"},"citationNoteNum":0,"citationHash":0}],[{"t":"Str","c":"test"}]]}"#,
             Inline::Code(attr_base_val(), String::from("test")) =>
-                r#"{"Code":[["test",["test"],[["test","test"]]],"test"]}"#,
+                r#"{"t":"Code","c":[["test",["test"],[["test","test"]]],"test"]}"#,
             Inline::Math(MathType::DisplayMath, String::from("test")) =>
-                r#"{"Math":[{"DisplayMath":[]},"test"]}"#,
-            Inline::RawInline(String::from("test"), String::from("test")) =>
-                r#"{"RawInline":["test","test"]}"#,
+                r#"{"t":"Math","c":[{"t":"DisplayMath"},"test"]}"#,
+            Inline::RawInline(Format(String::from("test")), String::from("test")) =>
+                r#"{"t":"RawInline","c":["test","test"]}"#,
             Inline::Link(attr_base_val(), vec![inline_base_val()],
                          (String::from("test"), String::from("test"))) =>
-                r#"{"Link":[["test",["test"],[["test","test"]]],[{"Str":"test"}],["test","test"]]}"#,
+                r#"{"t":"Link","c":[["test",["test"],[["test","test"]]],[{"t":"Str","c":"test"}],["test","test"]]}"#,
             Inline::Image(attr_base_val(), vec![inline_base_val()],
                          (String::from("test"), String::from("test"))) =>
-                r#"{"Image":[["test",["test"],[["test","test"]]],[{"Str":"test"}],["test","test"]]}"#,
+                r#"{"t":"Image","c":[["test",["test"],[["test","test"]]],[{"t":"Str","c":"test"}],["test","test"]]}"#,
             Inline::Span(attr_base_val(), vec![inline_base_val()]) =>
-                r#"{"Span":[["test",["test"],[["test","test"]]],[{"Str":"test"}]]}"#
+                r#"{"t":"Span","c":[["test",["test"],[["test","test"]]],[{"t":"Str","c":"test"}]]}"#,
+            Inline::Note(vec![block_base_val()]) => r#"{"t":"Note","c":[{"t":"Plain","c":[{"t":"Str","c":"test"}]}]}"#
         );
     }
 
@@ -347,65 +871,81 @@ mod tests {
     fn serialize_citation() {
         test_serialize!(
             citation_base_val() =>
-                r#"{"citationId":"test","citationPrefix":[{"Str":"test"}],"citationSuffix":[{"Str":"test"}],"citationMode":"AuthorInText","citationNoteNum":0,"citationHash":0}"#
+                r#"{"citationId":"test","citationPrefix":[{"t":"Str","c":"test"}],"citationSuffix":[{"t":"Str","c":"test"}],"citationMode":{"t":"AuthorInText"},"citationNoteNum":0,"citationHash":0}"#
         );
     }
 
     #[test]
     fn serialize_quotetype() {
         test_serialize!(
-            QuoteType::SingleQuote => r#"{"SingleQuote":[]}"#,
-            QuoteType::DoubleQuote => r#"{"DoubleQuote":[]}"#
+            QuoteType::SingleQuote => r#"{"t":"SingleQuote"}"#,
+            QuoteType::DoubleQuote => r#"{"t":"DoubleQuote"}"#
         );
     }
 
     #[test]
     fn serialize_mathtype() {
         test_serialize!(
-            MathType::DisplayMath => r#"{"DisplayMath":[]}"#,
-            MathType::InlineMath => r#"{"InlineMath":[]}"#
+            MathType::DisplayMath => r#"{"t":"DisplayMath"}"#,
+            MathType::InlineMath => r#"{"t":"InlineMath"}"#
         );
     }
 
     #[test]
     fn serialize_citation_mode() {
         test_serialize!(
-            CitationMode::AuthorInText => "\"AuthorInText\"",
-            CitationMode::SuppressAuthor => "\"SuppressAuthor\"",
-            CitationMode::NormalCitation => "\"NormalCitation\""
+            CitationMode::AuthorInText => r#"{"t":"AuthorInText"}"#,
+            CitationMode::SuppressAuthor => r#"{"t":"SuppressAuthor"}"#,
+            CitationMode::NormalCitation => r#"{"t":"NormalCitation"}"#
         );
     }
 
     #[test]
     fn serialize_alignment() {
         test_serialize!(
-            Alignment::AlignLeft => r#"{"AlignLeft":[]}"#,
-            Alignment::AlignRight => r#"{"AlignRight":[]}"#,
-            Alignment::AlignCenter => r#"{"AlignCenter":[]}"#,
-            Alignment::AlignDefault => r#"{"AlignDefault":[]}"#
+            Alignment::AlignLeft => r#"{"t":"AlignLeft"}"#,
+            Alignment::AlignRight => r#"{"t":"AlignRight"}"#,
+            Alignment::AlignCenter => r#"{"t":"AlignCenter"}"#,
+            Alignment::AlignDefault => r#"{"t":"AlignDefault"}"#
+        );
+    }
+
+    #[test]
+    fn serialize_col_width() {
+        test_serialize!(
+            ColWidth::ColWidth(0.5) => r#"{"t":"ColWidth","c":0.5}"#,
+            ColWidth::ColWidthDefault => r#"{"t":"ColWidthDefault"}"#
+        );
+    }
+
+    #[test]
+    fn serialize_caption() {
+        test_serialize!(
+            Caption(Some(vec![inline_base_val()]), vec![block_base_val()]) =>
+                r#"[[{"t":"Str","c":"test"}],[{"t":"Plain","c":[{"t":"Str","c":"test"}]}]]"#
         );
     }
 
     #[test]
     fn serialize_list_number_delim() {
         test_serialize!(
-            ListNumberDelim::DefaultDelim => r#"{"DefaultDelim":[]}"#,
-            ListNumberDelim::Period => r#"{"Period":[]}"#,
-            ListNumberDelim::OneParen => r#"{"OneParen":[]}"#,
-            ListNumberDelim::TwoParens => r#"{"TwoParens":[]}"#
+            ListNumberDelim::DefaultDelim => r#"{"t":"DefaultDelim"}"#,
+            ListNumberDelim::Period => r#"{"t":"Period"}"#,
+            ListNumberDelim::OneParen => r#"{"t":"OneParen"}"#,
+            ListNumberDelim::TwoParens => r#"{"t":"TwoParens"}"#
         );
     }
 
     #[test]
     fn serialize_list_number_style() {
         test_serialize!(
-            ListNumberStyle::DefaultStyle => r#"{"DefaultStyle":[]}"#,
-            ListNumberStyle::Example => r#"{"Example":[]}"#,
-            ListNumberStyle::Decimal => r#"{"Decimal":[]}"#,
-            ListNumberStyle::LowerRoman => r#"{"LowerRoman":[]}"#,
-            ListNumberStyle::UpperRoman => r#"{"UpperRoman":[]}"#,
-            ListNumberStyle::LowerAlpha => r#"{"LowerAlpha":[]}"#,
-            ListNumberStyle::UpperAlpha => r#"{"UpperAlpha":[]}"#
+            ListNumberStyle::DefaultStyle => r#"{"t":"DefaultStyle"}"#,
+            ListNumberStyle::Example => r#"{"t":"Example"}"#,
+            ListNumberStyle::Decimal => r#"{"t":"Decimal"}"#,
+            ListNumberStyle::LowerRoman => r#"{"t":"LowerRoman"}"#,
+            ListNumberStyle::UpperRoman => r#"{"t":"UpperRoman"}"#,
+            ListNumberStyle::LowerAlpha => r#"{"t":"LowerAlpha"}"#,
+            ListNumberStyle::UpperAlpha => r#"{"t":"UpperAlpha"}"#
         );
     }
 }
@@ -0,0 +1,173 @@
+//! Deserializing from a borrowed `Value`, which `serde_json::from_value`
+//! can't do, so that a document can be decoded and, if that fails, searched
+//! for the culprit without being cloned.
+
+use std::collections::btree_map;
+use std::slice;
+use serde::de;
+use serde::de::value::ValueDeserializer;
+use serde_json::{Value, Error};
+
+/// Deserializes a `T` from `value`, like `serde_json::from_value`.
+pub fn from_value_ref<T: de::Deserialize>(value: &Value) -> Result<T, Error> {
+    T::deserialize(&mut ValueRef(value))
+}
+
+struct ValueRef<'a>(&'a Value);
+
+impl<'a> de::Deserializer for ValueRef<'a> {
+    type Error = Error;
+
+    fn deserialize<V>(&mut self, mut visitor: V) -> Result<V::Value, Error>
+        where V: de::Visitor {
+        match *self.0 {
+            Value::Null => visitor.visit_unit(),
+            Value::Bool(v) => visitor.visit_bool(v),
+            Value::I64(v) => visitor.visit_i64(v),
+            Value::U64(v) => visitor.visit_u64(v),
+            Value::F64(v) => visitor.visit_f64(v),
+            Value::String(ref v) => visitor.visit_str(v),
+            Value::Array(ref v) => visitor.visit_seq(SeqRef { iter: v.iter(), len: v.len() }),
+            Value::Object(ref v) => visitor.visit_map(MapRef { iter: v.iter(), value: None, len: v.len() })
+        }
+    }
+
+    fn deserialize_option<V>(&mut self, mut visitor: V) -> Result<V::Value, Error>
+        where V: de::Visitor {
+        match *self.0 {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(&mut self, _name: &'static str, mut visitor: V)
+                                     -> Result<V::Value, Error>
+        where V: de::Visitor {
+        visitor.visit_newtype_struct(self)
+    }
+
+    forward_to_deserialize! {
+        bool usize u8 u16 u32 u64 isize i8 i16 i32 i64 f32 f64 char str string
+        unit seq seq_fixed_size bytes map unit_struct tuple_struct struct
+        struct_field tuple enum ignored_any
+    }
+}
+
+struct SeqRef<'a> {
+    iter: slice::Iter<'a, Value>,
+    len: usize
+}
+
+impl<'a> de::SeqVisitor for SeqRef<'a> {
+    type Error = Error;
+
+    fn visit<T>(&mut self) -> Result<Option<T>, Error>
+        where T: de::Deserialize {
+        match self.iter.next() {
+            Some(value) => {
+                self.len -= 1;
+                from_value_ref(value).map(Some)
+            },
+            None => Ok(None)
+        }
+    }
+
+    fn end(&mut self) -> Result<(), Error> {
+        if self.len == 0 {
+            Ok(())
+        } else {
+            Err(de::Error::invalid_length(self.len))
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+struct MapRef<'a> {
+    iter: btree_map::Iter<'a, String, Value>,
+    value: Option<&'a Value>,
+    len: usize
+}
+
+impl<'a> de::MapVisitor for MapRef<'a> {
+    type Error = Error;
+
+    fn visit_key<T>(&mut self) -> Result<Option<T>, Error>
+        where T: de::Deserialize {
+        match self.iter.next() {
+            Some((key, value)) => {
+                self.len -= 1;
+                self.value = Some(value);
+                let mut key = <&str as ValueDeserializer<Error>>::into_deserializer(key);
+                T::deserialize(&mut key).map(Some)
+            },
+            None => Ok(None)
+        }
+    }
+
+    fn visit_value<T>(&mut self) -> Result<T, Error>
+        where T: de::Deserialize {
+        let value = self.value.take().expect("value is missing");
+        from_value_ref(value)
+    }
+
+    fn end(&mut self) -> Result<(), Error> {
+        if self.len == 0 {
+            Ok(())
+        } else {
+            Err(de::Error::invalid_length(self.len))
+        }
+    }
+
+    fn missing_field<V>(&mut self, field: &'static str) -> Result<V, Error>
+        where V: de::Deserialize {
+        V::deserialize(&mut MissingField(field))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+/// Like `serde_json::from_value`, a missing field is an error unless it is
+/// an `Option`, which is then `None`.
+struct MissingField(&'static str);
+
+impl de::Deserializer for MissingField {
+    type Error = Error;
+
+    fn deserialize<V>(&mut self, _visitor: V) -> Result<V::Value, Error>
+        where V: de::Visitor {
+        Err(de::Error::missing_field(self.0))
+    }
+
+    fn deserialize_option<V>(&mut self, mut visitor: V) -> Result<V::Value, Error>
+        where V: de::Visitor {
+        visitor.visit_none()
+    }
+
+    forward_to_deserialize! {
+        bool usize u8 u16 u32 u64 isize i8 i16 i32 i64 f32 f64 char str string
+        unit seq seq_fixed_size bytes map unit_struct newtype_struct tuple_struct
+        struct struct_field tuple enum ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+    use types::Pandoc;
+    use value_ref::from_value_ref;
+
+    #[test]
+    fn same_as_from_value() {
+        let json = r#"{"pandoc-api-version":[1,23,1],"meta":{"title":{"t":"MetaInlines","c":[{"t":"Str","c":"T"}]}},"blocks":[{"t":"Para","c":[{"t":"Cite","c":[[{"citationId":"doe","citationPrefix":[],"citationSuffix":[],"citationMode":{"t":"NormalCitation"},"citationNoteNum":1,"citationHash":0}],[{"t":"Str","c":"[@doe]"}]]}]},{"t":"Table","c":[["",[],[]],[null,[]],[[{"t":"AlignDefault"},{"t":"ColWidthDefault"}]],[["",[],[]],[]],[[["",[],[]],0,[],[[["",[],[]],[[["",[],[]],{"t":"AlignDefault"},2,1,[]]]]]]],[["",[],[]],[]]]}]}"#;
+        let value: serde_json::Value = serde_json::from_str(json).unwrap();
+        let borrowed: Pandoc = from_value_ref(&value).unwrap();
+        assert_eq!(borrowed, serde_json::from_value::<Pandoc>(value).unwrap());
+        let value: serde_json::Value = serde_json::from_str(r#"{"pandoc-api-version":[1,23],"meta":{},"blocks":[{"t":"Para"}]}"#).unwrap();
+        assert!(from_value_ref::<Pandoc>(&value).is_err());
+    }
+}
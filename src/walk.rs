@@ -1,202 +1,608 @@
 use std::collections::BTreeMap;
-use types::{Inline, Block, Citation, Pandoc, Meta, MetaValue};
+use types::{Inline, Block, Citation, Pandoc, Meta, MetaValue, Attr, Target, Format};
+use types::{Caption, TableHead, TableBody, TableFoot, Row, Cell};
 
-pub trait Walkable<T> {
+/// Whether a top-down walk goes on into the children of a node.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Descent {
+    Continue,
+    Stop
+}
+
+/// The order in which a walk applies its filter to a node and walks the
+/// node's children.
+pub trait Traversal<T> {
+    /// Filters `node`, using `children` to walk its children.
+    fn visit<W>(&self, node: T, children: W) -> T
+        where W : FnOnce(T) -> T;
+}
+
+/// Children first, then the node, like pandoc's `walk`.
+pub struct BottomUp<'a, F: 'a>(pub &'a F);
+
+impl<'a, T, F> Traversal<T> for BottomUp<'a, F>
+    where F : Fn(T) -> T {
+    fn visit<W>(&self, node: T, children: W) -> T
+        where W : FnOnce(T) -> T {
+        (self.0)(children(node))
+    }
+}
+
+/// The node first, then the children of what the filter returned, unless it
+/// asked to stop there.
+pub struct TopDown<'a, F: 'a>(pub &'a F);
+
+impl<'a, T, F> Traversal<T> for TopDown<'a, F>
+    where F : Fn(T) -> (T, Descent) {
+    fn visit<W>(&self, node: T, children: W) -> T
+        where W : FnOnce(T) -> T {
+        match (self.0)(node) {
+            (node, Descent::Continue) => children(node),
+            (node, Descent::Stop) => node
+        }
+    }
+}
+
+/// The order in which `WalkableMut` applies its filter to a node and walks
+/// the node's children.
+pub trait TraversalMut<T> {
+    /// Filters `node` in place, using `children` to walk its children.
+    fn visit<W>(&mut self, node: &mut T, children: W)
+        where W : FnOnce(&mut T, &mut Self);
+}
+
+/// `BottomUp` for `WalkableMut`.
+pub struct BottomUpMut<'a, F: 'a>(pub &'a mut F);
+
+impl<'a, T, F> TraversalMut<T> for BottomUpMut<'a, F>
+    where F : FnMut(&mut T) {
+    fn visit<W>(&mut self, node: &mut T, children: W)
+        where W : FnOnce(&mut T, &mut Self) {
+        children(node, self);
+        (self.0)(node)
+    }
+}
+
+/// `TopDown` for `WalkableMut`.
+pub struct TopDownMut<'a, F: 'a>(pub &'a mut F);
+
+impl<'a, T, F> TraversalMut<T> for TopDownMut<'a, F>
+    where F : FnMut(&mut T) -> Descent {
+    fn visit<W>(&mut self, node: &mut T, children: W)
+        where W : FnOnce(&mut T, &mut Self) {
+        if (self.0)(node) == Descent::Continue {
+            children(node, self)
+        }
+    }
+}
+
+/// A value in which every `T` can be replaced by a filter. Every `T` is
+/// passed to the filter, including those that contain other `T`s.
+pub trait Walkable<T> : Sized {
+    fn walk_with<V>(self, v: &V) -> Self
+        where V : Traversal<T>;
+
+    /// Applies `f` to every `T`, children first.
     fn walk<F>(self, f: &F) -> Self
-        where F : Fn(T) -> T;
+        where F : Fn(T) -> T {
+        self.walk_with(&BottomUp(f))
+    }
+
+    /// Applies `f` to every `T`, parents first. Returning `Descent::Stop`
+    /// leaves the children of the returned node as they are.
+    fn walk_top_down<F>(self, f: &F) -> Self
+        where F : Fn(T) -> (T, Descent) {
+        self.walk_with(&TopDown(f))
+    }
 }
 
 impl<U, T> Walkable<U> for Vec<T>
     where T : Walkable<U> {
-    fn walk<F>(self, f: &F) -> Self
-        where F : Fn(U) -> U {
-        self.into_iter().map(|i| i.walk(f)).collect()
+    fn walk_with<V>(self, v: &V) -> Self
+        where V : Traversal<U> {
+        self.into_iter().map(|i| i.walk_with(v)).collect()
     }
 }
 
-impl<U, V> Walkable<U> for BTreeMap<String, V>
-    where V : Walkable<U> {
-    fn walk<F>(self, f: &F) -> Self
-        where F : Fn(U) -> U {
-        self.into_iter().map(|(k,v)| (k,v.walk(f))).collect()
+impl<U, T> Walkable<U> for Option<T>
+    where T : Walkable<U> {
+    fn walk_with<V>(self, v: &V) -> Self
+        where V : Traversal<U> {
+        self.map(|i| i.walk_with(v))
     }
 }
 
-impl Walkable<Pandoc> for Pandoc {
-    fn walk<F>(self, f: &F) -> Self
-        where F : Fn(Pandoc) -> Pandoc {
-        f(self)
+impl<U, K> Walkable<U> for BTreeMap<String, K>
+    where K : Walkable<U> {
+    fn walk_with<V>(self, v: &V) -> Self
+        where V : Traversal<U> {
+        self.into_iter().map(|(k, x)| (k, x.walk_with(v))).collect()
     }
 }
 
-impl Walkable<Block> for Pandoc {
-    fn walk<F>(self, f: &F) -> Self
-        where F : Fn(Block) -> Block {
-        Pandoc(self.0.walk(f), self.1.walk(f))
+/// Implements `Walkable<T>` for a type that is not itself a `T`, for each of
+/// the given `T`s, by walking its children with `$children`.
+macro_rules! walk_children {
+    ($node:ty, $children:expr, [ $( $t:ty ),* ]) => {
+        $(
+            impl Walkable<$t> for $node {
+                fn walk_with<V>(self, v: &V) -> Self
+                    where V : Traversal<$t> {
+                    $children(self, v)
+                }
+            }
+        )*
     }
 }
 
-impl Walkable<Inline> for Pandoc {
-    fn walk<F>(self, f: &F) -> Self
-        where F : Fn(Inline) -> Inline {
-        Pandoc(self.0.walk(f), self.1.walk(f))
+/// Implements `Walkable<$node>` for `$node`, passing it to the filter and
+/// walking its children with `$children`.
+macro_rules! walk_node {
+    ($node:ty, $children:expr) => {
+        impl Walkable<$node> for $node {
+            fn walk_with<V>(self, v: &V) -> Self
+                where V : Traversal<$node> {
+                v.visit(self, |node| $children(node, v))
+            }
+        }
     }
 }
 
-impl Walkable<Meta> for Meta {
-    fn walk<F>(self, f: &F) -> Self
-        where F : Fn(Meta) -> Meta {
-        f(self)
+walk_node!(Pandoc, |p, _| p);
+walk_node!(Meta, |m, _| m);
+walk_children!(Pandoc, walk_pandoc_children,
+               [Block, Inline, MetaValue, Citation, Attr, Target, Format]);
+walk_children!(Meta, walk_meta_children,
+               [Block, Inline, MetaValue, Citation, Attr, Target, Format]);
+
+fn walk_pandoc_children<T, V>(pandoc: Pandoc, v: &V) -> Pandoc
+    where V : Traversal<T>, Meta : Walkable<T>, Block : Walkable<T> {
+    Pandoc(pandoc.0.walk_with(v), pandoc.1.walk_with(v), pandoc.2)
+}
+
+fn walk_meta_children<T, V>(meta: Meta, v: &V) -> Meta
+    where V : Traversal<T>, MetaValue : Walkable<T> {
+    Meta { un_meta: meta.un_meta.walk_with(v) }
+}
+
+walk_node!(MetaValue, walk_meta_value_children);
+walk_children!(MetaValue, walk_meta_value_children,
+               [Block, Inline, Citation, Attr, Target, Format]);
+
+fn walk_meta_value_children<T, V>(value: MetaValue, v: &V) -> MetaValue
+    where V : Traversal<T>,
+          MetaValue : Walkable<T>, Inline : Walkable<T>, Block : Walkable<T> {
+    match value {
+        MetaValue::MetaMap(map) => MetaValue::MetaMap(map.walk_with(v)),
+        MetaValue::MetaList(values) => MetaValue::MetaList(values.walk_with(v)),
+        MetaValue::MetaInlines(inlines) => MetaValue::MetaInlines(inlines.walk_with(v)),
+        MetaValue::MetaBlocks(blocks) => MetaValue::MetaBlocks(blocks.walk_with(v)),
+        e => e
     }
 }
 
-impl Walkable<Inline> for Meta {
-    fn walk<F>(self, f: &F) -> Self
-        where F : Fn(Inline) -> Inline {
-        Meta { un_meta: self.un_meta.walk(f) }
+walk_node!(Block, walk_block_children);
+walk_children!(Block, walk_block_children,
+               [Inline, MetaValue, Citation, Attr, Target, Format]);
+
+fn walk_block_children<T, V>(block: Block, v: &V) -> Block
+    where V : Traversal<T>, Inline : Walkable<T>, Block : Walkable<T>,
+          Attr : Walkable<T>, Format : Walkable<T> {
+    match block {
+        Block::Plain(inlines) => Block::Plain(inlines.walk_with(v)),
+        Block::Para(inlines) => Block::Para(inlines.walk_with(v)),
+        Block::LineBlock(lines) => Block::LineBlock(lines.walk_with(v)),
+        Block::CodeBlock(attr, code) => Block::CodeBlock(attr.walk_with(v), code),
+        Block::RawBlock(format, raw) => Block::RawBlock(format.walk_with(v), raw),
+        Block::BlockQuote(blocks) =>
+            Block::BlockQuote(blocks.walk_with(v)),
+        Block::OrderedList(list, vecs_blocks) =>
+            Block::OrderedList(list, vecs_blocks.walk_with(v)),
+        Block::BulletList(vecs_blocks) =>
+            Block::BulletList(vecs_blocks.walk_with(v)),
+        Block::DefinitionList(inlines_and_blocks) => {
+            Block::DefinitionList(inlines_and_blocks
+                                  .into_iter()
+                                  .map(|(is, vbs)| (is.walk_with(v), vbs.walk_with(v)))
+                                  .collect())
+        },
+        Block::Header(i, attr, inlines) =>
+            Block::Header(i, attr.walk_with(v), inlines.walk_with(v)),
+        Block::Table(attr, caption, col_specs, head, bodies, foot) => {
+            Block::Table(attr.walk_with(v), caption.walk_with(v), col_specs,
+                         head.walk_with(v), bodies.walk_with(v), foot.walk_with(v))
+        },
+        Block::Figure(attr, caption, blocks) =>
+            Block::Figure(attr.walk_with(v), caption.walk_with(v), blocks.walk_with(v)),
+        Block::Div(attr, blocks) =>
+            Block::Div(attr.walk_with(v), blocks.walk_with(v)),
+        b => b
     }
 }
 
-impl Walkable<Block> for Meta {
-    fn walk<F>(self, f: &F) -> Self
-        where F : Fn(Block) -> Block {
-        Meta { un_meta: self.un_meta.walk(f) }
+walk_node!(Inline, walk_inline_children);
+walk_children!(Inline, walk_inline_children,
+               [Block, MetaValue, Citation, Attr, Target, Format]);
+
+fn walk_inline_children<T, V>(inline: Inline, v: &V) -> Inline
+    where V : Traversal<T>, Inline : Walkable<T>, Block : Walkable<T>, Citation : Walkable<T>,
+          Attr : Walkable<T>, Target : Walkable<T>, Format : Walkable<T> {
+    match inline {
+        Inline::Emph(inlines) => Inline::Emph(inlines.walk_with(v)),
+        Inline::Underline(inlines) => Inline::Underline(inlines.walk_with(v)),
+        Inline::Strong(inlines) => Inline::Strong(inlines.walk_with(v)),
+        Inline::Strikeout(inlines) => Inline::Strikeout(inlines.walk_with(v)),
+        Inline::Superscript(inlines) => Inline::Superscript(inlines.walk_with(v)),
+        Inline::Subscript(inlines) => Inline::Subscript(inlines.walk_with(v)),
+        Inline::SmallCaps(inlines) => Inline::SmallCaps(inlines.walk_with(v)),
+        Inline::Quoted(quote_type, inlines) => Inline::Quoted(quote_type, inlines.walk_with(v)),
+        Inline::Cite(citations, inlines) =>
+            Inline::Cite(citations.walk_with(v), inlines.walk_with(v)),
+        Inline::Code(attr, code) => Inline::Code(attr.walk_with(v), code),
+        Inline::RawInline(format, raw) => Inline::RawInline(format.walk_with(v), raw),
+        Inline::Link(attr, inlines, target) =>
+            Inline::Link(attr.walk_with(v), inlines.walk_with(v), target.walk_with(v)),
+        Inline::Image(attr, inlines, target) =>
+            Inline::Image(attr.walk_with(v), inlines.walk_with(v), target.walk_with(v)),
+        Inline::Span(attr, inlines) => Inline::Span(attr.walk_with(v), inlines.walk_with(v)),
+        Inline::Note(blocks) => Inline::Note(blocks.walk_with(v)),
+        e => e
     }
 }
 
-impl Walkable<MetaValue> for MetaValue {
-    fn walk<F>(self, f: &F) -> Self
-        where F : Fn(MetaValue) -> MetaValue {
-        match self {
-            MetaValue::MetaMap(map) => MetaValue::MetaMap(map.walk(f)),
-            MetaValue::MetaList(values) => MetaValue::MetaList(values.walk(f)),
-            e => f(e)
+walk_node!(Citation, walk_citation_children);
+walk_children!(Citation, walk_citation_children,
+               [Block, Inline, MetaValue, Attr, Target, Format]);
+
+fn walk_citation_children<T, V>(citation: Citation, v: &V) -> Citation
+    where V : Traversal<T>, Inline : Walkable<T> {
+    Citation { citation_id: citation.citation_id,
+               citation_prefix: citation.citation_prefix.walk_with(v),
+               citation_suffix: citation.citation_suffix.walk_with(v),
+               citation_mode: citation.citation_mode,
+               citation_note_num: citation.citation_note_num,
+               citation_hash: citation.citation_hash
+    }
+}
+
+fn walk_no_children<N, V>(node: N, _: &V) -> N {
+    node
+}
+
+walk_node!(Attr, walk_no_children);
+walk_children!(Attr, walk_no_children, [Block, Inline, MetaValue, Citation, Target, Format]);
+walk_node!(Target, walk_no_children);
+walk_children!(Target, walk_no_children, [Block, Inline, MetaValue, Citation, Attr, Format]);
+walk_node!(Format, walk_no_children);
+walk_children!(Format, walk_no_children, [Block, Inline, MetaValue, Citation, Attr, Target]);
+
+impl<T> Walkable<T> for Caption
+    where Inline : Walkable<T>, Block : Walkable<T> {
+    fn walk_with<V>(self, v: &V) -> Self
+        where V : Traversal<T> {
+        Caption(self.0.walk_with(v), self.1.walk_with(v))
+    }
+}
+
+impl<T> Walkable<T> for TableHead
+    where Attr : Walkable<T>, Row : Walkable<T> {
+    fn walk_with<V>(self, v: &V) -> Self
+        where V : Traversal<T> {
+        TableHead(self.0.walk_with(v), self.1.walk_with(v))
+    }
+}
+
+impl<T> Walkable<T> for TableBody
+    where Attr : Walkable<T>, Row : Walkable<T> {
+    fn walk_with<V>(self, v: &V) -> Self
+        where V : Traversal<T> {
+        TableBody(self.0.walk_with(v), self.1, self.2.walk_with(v), self.3.walk_with(v))
+    }
+}
+
+impl<T> Walkable<T> for TableFoot
+    where Attr : Walkable<T>, Row : Walkable<T> {
+    fn walk_with<V>(self, v: &V) -> Self
+        where V : Traversal<T> {
+        TableFoot(self.0.walk_with(v), self.1.walk_with(v))
+    }
+}
+
+impl<T> Walkable<T> for Row
+    where Attr : Walkable<T>, Cell : Walkable<T> {
+    fn walk_with<V>(self, v: &V) -> Self
+        where V : Traversal<T> {
+        Row(self.0.walk_with(v), self.1.walk_with(v))
+    }
+}
+
+impl<T> Walkable<T> for Cell
+    where Attr : Walkable<T>, Block : Walkable<T> {
+    fn walk_with<V>(self, v: &V) -> Self
+        where V : Traversal<T> {
+        Cell(self.0.walk_with(v), self.1, self.2, self.3, self.4.walk_with(v))
+    }
+}
+
+/// Like `Walkable`, but edits the tree in place instead of rebuilding it.
+/// Nodes are visited in the same order, and the same ones, as by `walk`, so
+/// `x.walk_mut(&mut f)` has the same effect as `x = x.walk(&g)` when `f`
+/// does in place what `g` does by value.
+pub trait WalkableMut<T> {
+    fn walk_mut_with<V>(&mut self, v: &mut V)
+        where V : TraversalMut<T>;
+
+    /// Applies `f` to every `T`, children first.
+    fn walk_mut<F>(&mut self, f: &mut F)
+        where F : FnMut(&mut T) {
+        self.walk_mut_with(&mut BottomUpMut(f))
+    }
+
+    /// Applies `f` to every `T`, parents first. Returning `Descent::Stop`
+    /// leaves the children of the node as they are.
+    fn walk_mut_top_down<F>(&mut self, f: &mut F)
+        where F : FnMut(&mut T) -> Descent {
+        self.walk_mut_with(&mut TopDownMut(f))
+    }
+}
+
+impl<U, T> WalkableMut<U> for Vec<T>
+    where T : WalkableMut<U> {
+    fn walk_mut_with<V>(&mut self, v: &mut V)
+        where V : TraversalMut<U> {
+        for i in self.iter_mut() {
+            i.walk_mut_with(v);
         }
     }
 }
 
-impl Walkable<Block> for MetaValue {
-    fn walk<F>(self, f: &F) -> Self
-        where F : Fn(Block) -> Block {
-        match self {
-            MetaValue::MetaMap(map) => MetaValue::MetaMap(map.walk(f)),
-            MetaValue::MetaList(values) => MetaValue::MetaList(values.walk(f)),
-            MetaValue::MetaBlocks(blocks) => MetaValue::MetaBlocks(blocks.walk(f)),
-            e => e
+impl<U, T> WalkableMut<U> for Option<T>
+    where T : WalkableMut<U> {
+    fn walk_mut_with<V>(&mut self, v: &mut V)
+        where V : TraversalMut<U> {
+        if let Some(ref mut i) = *self {
+            i.walk_mut_with(v);
         }
     }
 }
 
-impl Walkable<Inline> for MetaValue {
-    fn walk<F>(self, f: &F) -> Self
-        where F : Fn(Inline) -> Inline {
-        match self {
-            MetaValue::MetaMap(map) => MetaValue::MetaMap(map.walk(f)),
-            MetaValue::MetaList(values) => MetaValue::MetaList(values.walk(f)),
-            MetaValue::MetaInlines(inlines) => MetaValue::MetaInlines(inlines.walk(f)),
-            MetaValue::MetaBlocks(blocks) => MetaValue::MetaBlocks(blocks.walk(f)),
-            e => e
+impl<U, K> WalkableMut<U> for BTreeMap<String, K>
+    where K : WalkableMut<U> {
+    fn walk_mut_with<V>(&mut self, v: &mut V)
+        where V : TraversalMut<U> {
+        for x in self.values_mut() {
+            x.walk_mut_with(v);
         }
     }
 }
 
+impl WalkableMut<Pandoc> for Pandoc {
+    fn walk_mut_with<V>(&mut self, v: &mut V)
+        where V : TraversalMut<Pandoc> {
+        v.visit(self, |_, _| ())
+    }
+}
 
-impl Walkable<Block> for Block {
-    fn walk<F>(self, f: &F) -> Self
-        where F : Fn(Block) -> Block {
-        match self {
-            Block::BlockQuote(blocks) =>
-                Block::BlockQuote(blocks.walk(f)),
-            Block::OrderedList(list, vecs_blocks) =>
-                Block::OrderedList(list, vecs_blocks.walk(f)),
-            Block::BulletList(vecs_blocks) =>
-                Block::BulletList(vecs_blocks.walk(f)),
-            Block::DefinitionList(inlines_and_blocks) => {
-                Block::DefinitionList(inlines_and_blocks
-                                      .into_iter()
-                                      .map(|(is, vbs)| (is, vbs.walk(f)))
-                                      .collect())
-            },
-            Block::Table(inlines, alignment, width, headers, rows) => {
-                Block::Table(inlines, alignment, width,
-                             headers.walk(f),
-                             rows.walk(f))
-            },
-            Block::Div(attr, blocks) => Block::Div(attr, blocks.walk(f)),
-            e => f(e)
-        }
+impl WalkableMut<Block> for Pandoc {
+    fn walk_mut_with<V>(&mut self, v: &mut V)
+        where V : TraversalMut<Block> {
+        self.0.walk_mut_with(v);
+        self.1.walk_mut_with(v);
     }
 }
 
-impl Walkable<Inline> for Block {
-    fn walk<F>(self, f: &F) -> Self
-        where F : Fn(Inline) -> Inline {
-        match self {
-            Block::Plain(inlines) => Block::Plain(inlines.walk(f)),
-            Block::Para(inlines) => Block::Para(inlines.walk(f)),
-            Block::BlockQuote(blocks) =>
-                Block::BlockQuote(blocks.walk(f)),
-            Block::OrderedList(list, vecs_blocks) =>
-                Block::OrderedList(list, vecs_blocks.walk(f)),
-            Block::BulletList(vecs_blocks) =>
-                Block::BulletList(vecs_blocks.walk(f)),
-            Block::DefinitionList(inlines_and_blocks) => {
-                Block::DefinitionList(inlines_and_blocks
-                                      .into_iter()
-                                      .map(|(is, vbs)| (is.walk(f), vbs.walk(f)))
-                                      .collect())
-            },
-            Block::Header(i, attr, inlines) =>
-                Block::Header(i, attr, inlines.walk(f)),
-            Block::Table(inlines, alignment, width, headers, rows) => {
-                Block::Table(inlines.walk(f), alignment, width,
-                             headers.walk(f),
-                             rows.walk(f))
-            },
-            Block::Div(attr, blocks) =>
-                Block::Div(attr, blocks.walk(f)),
-            b => b
-        }
+impl WalkableMut<Inline> for Pandoc {
+    fn walk_mut_with<V>(&mut self, v: &mut V)
+        where V : TraversalMut<Inline> {
+        self.0.walk_mut_with(v);
+        self.1.walk_mut_with(v);
     }
 }
 
-impl Walkable<Inline> for Inline {
-    fn walk<F>(self, f: &F) -> Self
-        where F : Fn(Inline) -> Inline {
-        match self {
-            Inline::Emph(inlines) => Inline::Emph(inlines.walk(f)),
-            Inline::Strong(inlines) => Inline::Strong(inlines.walk(f)),
-            Inline::Strikeout(inlines) => Inline::Strikeout(inlines.walk(f)),
-            Inline::Superscript(inlines) => Inline::Superscript(inlines.walk(f)),
-            Inline::Subscript(inlines) => Inline::Subscript(inlines.walk(f)),
-            Inline::SmallCaps(inlines) => Inline::SmallCaps(inlines.walk(f)),
-            Inline::Quoted(quote_type, inlines) => Inline::Quoted(quote_type, inlines.walk(f)),
-            Inline::Cite(citations, inlines) => Inline::Cite(citations.walk(f), inlines.walk(f)),
-            Inline::Link(attr, inlines, target) => Inline::Link(attr, inlines.walk(f), target),
-            Inline::Image(attr, inlines, target) => Inline::Image(attr, inlines.walk(f), target),
-            Inline::Span(attr, inlines) => Inline::Span(attr, inlines.walk(f)),
-            e => f(e)
+impl WalkableMut<Meta> for Meta {
+    fn walk_mut_with<V>(&mut self, v: &mut V)
+        where V : TraversalMut<Meta> {
+        v.visit(self, |_, _| ())
+    }
+}
+
+impl WalkableMut<Inline> for Meta {
+    fn walk_mut_with<V>(&mut self, v: &mut V)
+        where V : TraversalMut<Inline> {
+        self.un_meta.walk_mut_with(v)
+    }
+}
+
+impl WalkableMut<Block> for Meta {
+    fn walk_mut_with<V>(&mut self, v: &mut V)
+        where V : TraversalMut<Block> {
+        self.un_meta.walk_mut_with(v)
+    }
+}
+
+impl WalkableMut<MetaValue> for MetaValue {
+    fn walk_mut_with<V>(&mut self, v: &mut V)
+        where V : TraversalMut<MetaValue> {
+        v.visit(self, |value, v| match *value {
+            MetaValue::MetaMap(ref mut map) => map.walk_mut_with(v),
+            MetaValue::MetaList(ref mut values) => values.walk_mut_with(v),
+            _ => ()
+        })
+    }
+}
+
+impl WalkableMut<Block> for MetaValue {
+    fn walk_mut_with<V>(&mut self, v: &mut V)
+        where V : TraversalMut<Block> {
+        match *self {
+            MetaValue::MetaMap(ref mut map) => map.walk_mut_with(v),
+            MetaValue::MetaList(ref mut values) => values.walk_mut_with(v),
+            MetaValue::MetaInlines(ref mut inlines) => inlines.walk_mut_with(v),
+            MetaValue::MetaBlocks(ref mut blocks) => blocks.walk_mut_with(v),
+            _ => ()
         }
     }
 }
 
-impl Walkable<Inline> for Citation {
-    fn walk<F>(self, f: &F) -> Self
-        where F : Fn(Inline) -> Inline {
-        Citation { citation_id: self.citation_id,
-                   citation_prefix: self.citation_prefix.walk(f),
-                   citation_suffix: self.citation_suffix.walk(f),
-                   citation_mode: self.citation_mode,
-                   citation_note_num: self.citation_note_num,
-                   citation_hash: self.citation_hash
+impl WalkableMut<Inline> for MetaValue {
+    fn walk_mut_with<V>(&mut self, v: &mut V)
+        where V : TraversalMut<Inline> {
+        match *self {
+            MetaValue::MetaMap(ref mut map) => map.walk_mut_with(v),
+            MetaValue::MetaList(ref mut values) => values.walk_mut_with(v),
+            MetaValue::MetaInlines(ref mut inlines) => inlines.walk_mut_with(v),
+            MetaValue::MetaBlocks(ref mut blocks) => blocks.walk_mut_with(v),
+            _ => ()
         }
     }
 }
 
+impl WalkableMut<Block> for Block {
+    fn walk_mut_with<V>(&mut self, v: &mut V)
+        where V : TraversalMut<Block> {
+        v.visit(self, |block, v| walk_block_children_mut(block, v))
+    }
+}
+
+impl WalkableMut<Inline> for Block {
+    fn walk_mut_with<V>(&mut self, v: &mut V)
+        where V : TraversalMut<Inline> {
+        walk_block_children_mut(self, v)
+    }
+}
+
+fn walk_block_children_mut<T, V>(block: &mut Block, v: &mut V)
+    where V : TraversalMut<T>, Inline : WalkableMut<T>, Block : WalkableMut<T> {
+    match *block {
+        Block::Plain(ref mut inlines) => inlines.walk_mut_with(v),
+        Block::Para(ref mut inlines) => inlines.walk_mut_with(v),
+        Block::LineBlock(ref mut lines) => lines.walk_mut_with(v),
+        Block::Header(_, _, ref mut inlines) => inlines.walk_mut_with(v),
+        Block::BlockQuote(ref mut blocks) => blocks.walk_mut_with(v),
+        Block::OrderedList(_, ref mut vecs_blocks) => vecs_blocks.walk_mut_with(v),
+        Block::BulletList(ref mut vecs_blocks) => vecs_blocks.walk_mut_with(v),
+        Block::DefinitionList(ref mut inlines_and_blocks) => {
+            for &mut (ref mut is, ref mut vbs) in inlines_and_blocks.iter_mut() {
+                is.walk_mut_with(v);
+                vbs.walk_mut_with(v);
+            }
+        },
+        Block::Table(_, ref mut caption, _, ref mut head, ref mut bodies, ref mut foot) => {
+            caption.walk_mut_with(v);
+            head.walk_mut_with(v);
+            bodies.walk_mut_with(v);
+            foot.walk_mut_with(v);
+        },
+        Block::Figure(_, ref mut caption, ref mut blocks) => {
+            caption.walk_mut_with(v);
+            blocks.walk_mut_with(v);
+        },
+        Block::Div(_, ref mut blocks) => blocks.walk_mut_with(v),
+        _ => ()
+    }
+}
+
+impl WalkableMut<Inline> for Inline {
+    fn walk_mut_with<V>(&mut self, v: &mut V)
+        where V : TraversalMut<Inline> {
+        v.visit(self, |inline, v| walk_inline_children_mut(inline, v))
+    }
+}
+
+impl WalkableMut<Block> for Inline {
+    fn walk_mut_with<V>(&mut self, v: &mut V)
+        where V : TraversalMut<Block> {
+        walk_inline_children_mut(self, v)
+    }
+}
+
+fn walk_inline_children_mut<T, V>(inline: &mut Inline, v: &mut V)
+    where V : TraversalMut<T>, Inline : WalkableMut<T>, Block : WalkableMut<T> {
+    match *inline {
+        Inline::Emph(ref mut inlines) => inlines.walk_mut_with(v),
+        Inline::Underline(ref mut inlines) => inlines.walk_mut_with(v),
+        Inline::Strong(ref mut inlines) => inlines.walk_mut_with(v),
+        Inline::Strikeout(ref mut inlines) => inlines.walk_mut_with(v),
+        Inline::Superscript(ref mut inlines) => inlines.walk_mut_with(v),
+        Inline::Subscript(ref mut inlines) => inlines.walk_mut_with(v),
+        Inline::SmallCaps(ref mut inlines) => inlines.walk_mut_with(v),
+        Inline::Quoted(_, ref mut inlines) => inlines.walk_mut_with(v),
+        Inline::Cite(ref mut citations, ref mut inlines) => {
+            citations.walk_mut_with(v);
+            inlines.walk_mut_with(v);
+        },
+        Inline::Link(_, ref mut inlines, _) => inlines.walk_mut_with(v),
+        Inline::Image(_, ref mut inlines, _) => inlines.walk_mut_with(v),
+        Inline::Span(_, ref mut inlines) => inlines.walk_mut_with(v),
+        Inline::Note(ref mut blocks) => blocks.walk_mut_with(v),
+        _ => ()
+    }
+}
+
+impl<T> WalkableMut<T> for Caption
+    where Inline : WalkableMut<T>, Block : WalkableMut<T> {
+    fn walk_mut_with<V>(&mut self, v: &mut V)
+        where V : TraversalMut<T> {
+        self.0.walk_mut_with(v);
+        self.1.walk_mut_with(v);
+    }
+}
+
+impl<T> WalkableMut<T> for TableHead
+    where Row : WalkableMut<T> {
+    fn walk_mut_with<V>(&mut self, v: &mut V)
+        where V : TraversalMut<T> {
+        self.1.walk_mut_with(v)
+    }
+}
+
+impl<T> WalkableMut<T> for TableBody
+    where Row : WalkableMut<T> {
+    fn walk_mut_with<V>(&mut self, v: &mut V)
+        where V : TraversalMut<T> {
+        self.2.walk_mut_with(v);
+        self.3.walk_mut_with(v);
+    }
+}
+
+impl<T> WalkableMut<T> for TableFoot
+    where Row : WalkableMut<T> {
+    fn walk_mut_with<V>(&mut self, v: &mut V)
+        where V : TraversalMut<T> {
+        self.1.walk_mut_with(v)
+    }
+}
+
+impl<T> WalkableMut<T> for Row
+    where Cell : WalkableMut<T> {
+    fn walk_mut_with<V>(&mut self, v: &mut V)
+        where V : TraversalMut<T> {
+        self.1.walk_mut_with(v)
+    }
+}
+
+impl<T> WalkableMut<T> for Cell
+    where Block : WalkableMut<T> {
+    fn walk_mut_with<V>(&mut self, v: &mut V)
+        where V : TraversalMut<T> {
+        self.4.walk_mut_with(v)
+    }
+}
+
+impl<T> WalkableMut<T> for Citation
+    where Inline : WalkableMut<T> {
+    fn walk_mut_with<V>(&mut self, v: &mut V)
+        where V : TraversalMut<T> {
+        self.citation_prefix.walk_mut_with(v);
+        self.citation_suffix.walk_mut_with(v);
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use walk::Walkable;
-    use types::Inline;
+    use std::mem;
+    use walk::{Walkable, WalkableMut, Descent};
+    use types::*;
 
     #[test]
     fn test_walk_inline() {
@@ -205,4 +611,200 @@ mod tests {
         let expected = Inline::Emph(vec![Inline::Str(String::from("a"))]);
         assert_eq!(emph, expected);
     }
+
+    fn note() -> Inline {
+        Inline::Note(vec![Block::Para(vec![Inline::Str(String::from("a"))])])
+    }
+
+    #[test]
+    fn test_walk_inline_in_note() {
+        let walked = Block::Para(vec![note()]).walk(&(|i| match i {
+            Inline::Str(s) => Inline::Str(s.to_uppercase()),
+            e => e
+        }));
+        let expected = Block::Para(vec![
+            Inline::Note(vec![Block::Para(vec![Inline::Str(String::from("A"))])])
+        ]);
+        assert_eq!(walked, expected);
+    }
+
+    #[test]
+    fn test_walk_block_in_note() {
+        let walked = Block::Para(vec![note()]).walk(&(|b| match b {
+            Block::Para(inlines) => Block::Plain(inlines),
+            e => e
+        }));
+        let expected = Block::Plain(vec![
+            Inline::Note(vec![Block::Plain(vec![Inline::Str(String::from("a"))])])
+        ]);
+        assert_eq!(walked, expected);
+    }
+
+    #[test]
+    fn test_walk_figure_and_line_block() {
+        let figure = |s: &str| Block::Figure((String::new(), vec![], vec![]),
+                                             Caption(None, vec![Block::Plain(vec![Inline::Str(String::from(s))])]),
+                                             vec![Block::LineBlock(vec![vec![Inline::Underline(vec![Inline::Str(String::from(s))])]])]);
+        let walked = figure("a").walk(&(|i| match i {
+            Inline::Str(s) => Inline::Str(s.to_uppercase()),
+            e => e
+        }));
+        assert_eq!(walked, figure("A"));
+    }
+
+    #[test]
+    fn test_walk_table_cells() {
+        let cell = |s: &str| Cell((String::new(), vec![], vec![]), Alignment::AlignDefault,
+                                  RowSpan(1), ColSpan(2),
+                                  vec![Block::Plain(vec![Inline::Str(String::from(s))])]);
+        let table = |s: &str| Block::Table((String::new(), vec![], vec![]),
+                                           Caption(None, vec![]), vec![],
+                                           TableHead((String::new(), vec![], vec![]), vec![]),
+                                           vec![TableBody((String::new(), vec![], vec![]),
+                                                          RowHeadColumns(0), vec![],
+                                                          vec![Row((String::new(), vec![], vec![]),
+                                                                   vec![cell(s)])])],
+                                           TableFoot((String::new(), vec![], vec![]), vec![]));
+        let walked = table("a").walk(&(|i| match i {
+            Inline::Str(s) => Inline::Str(s.to_uppercase()),
+            e => e
+        }));
+        assert_eq!(walked, table("A"));
+    }
+
+    #[test]
+    fn test_walk_mut_matches_walk() {
+        let doc = || Block::Div((String::new(), vec![], vec![]),
+                                vec![Block::Para(vec![note(), Inline::Str(String::from("b"))]),
+                                     Block::Header(1, (String::new(), vec![], vec![]),
+                                                   vec![Inline::Emph(vec![Inline::Str(String::from("c"))])])]);
+        let walked = doc().walk(&(|i| match i {
+            Inline::Str(s) => Inline::Str(s.to_uppercase()),
+            e => e
+        }));
+        let mut edited = doc();
+        edited.walk_mut(&mut |i: &mut Inline| if let Inline::Str(ref mut s) = *i {
+            *s = s.to_uppercase();
+        });
+        assert_eq!(edited, walked);
+    }
+
+    #[test]
+    fn test_walk_mut_stateful() {
+        let mut blocks = vec![Block::Para(vec![note()]), Block::HorizontalRule];
+        let mut visited = 0;
+        blocks.walk_mut(&mut |b: &mut Block| {
+            visited += 1;
+            let inlines = match *b {
+                Block::Para(ref mut inlines) => mem::replace(inlines, vec![]),
+                _ => return
+            };
+            *b = Block::Plain(inlines);
+        });
+        assert_eq!(visited, 3);
+        assert_eq!(blocks, vec![Block::Plain(vec![
+            Inline::Note(vec![Block::Plain(vec![Inline::Str(String::from("a"))])])
+        ]), Block::HorizontalRule]);
+    }
+
+    #[test]
+    fn test_walk_visits_containers() {
+        let div = Block::Div((String::new(), vec![String::from("unwrap")], vec![]),
+                             vec![Block::Para(vec![Inline::Span((String::new(), vec![], vec![]),
+                                                                vec![Inline::Str(String::from("a"))])])]);
+        let unwrapped = div.walk(&(|b| match b {
+            Block::Div(attr, mut blocks) =>
+                if attr.1.contains(&String::from("unwrap")) && blocks.len() == 1 {
+                    blocks.remove(0)
+                } else {
+                    Block::Div(attr, blocks)
+                },
+            e => e
+        })).walk(&(|i| match i {
+            Inline::Span(_, inlines) => Inline::Emph(inlines),
+            e => e
+        }));
+        assert_eq!(unwrapped, Block::Para(vec![Inline::Emph(vec![Inline::Str(String::from("a"))])]));
+    }
+
+    #[test]
+    fn test_walk_top_down() {
+        let code = || Inline::Code((String::new(), vec![], vec![]), String::from("x"));
+        let para = Block::Para(vec![Inline::Emph(vec![Inline::Str(String::from("a"))]),
+                                    Inline::Strong(vec![Inline::Str(String::from("b"))]),
+                                    code()]);
+        let walked = para.walk_top_down(&(|i| match i {
+            Inline::Emph(inlines) => (Inline::Strong(inlines), Descent::Continue),
+            s @ Inline::Strong(_) => (s, Descent::Stop),
+            Inline::Str(s) => (Inline::Str(s.to_uppercase()), Descent::Continue),
+            e => (e, Descent::Continue)
+        }));
+        let expected = Block::Para(vec![Inline::Strong(vec![Inline::Str(String::from("A"))]),
+                                        Inline::Strong(vec![Inline::Str(String::from("b"))]),
+                                        code()]);
+        assert_eq!(walked, expected);
+    }
+
+    #[test]
+    fn test_walk_mut_top_down() {
+        let mut para = Block::Para(vec![Inline::Emph(vec![Inline::Str(String::from("a"))]),
+                                        Inline::Strong(vec![Inline::Str(String::from("b"))])]);
+        para.walk_mut_top_down(&mut |i: &mut Inline| match *i {
+            Inline::Strong(_) => Descent::Stop,
+            Inline::Str(ref mut s) => {
+                *s = s.to_uppercase();
+                Descent::Continue
+            },
+            _ => Descent::Continue
+        });
+        assert_eq!(para, Block::Para(vec![Inline::Emph(vec![Inline::Str(String::from("A"))]),
+                                          Inline::Strong(vec![Inline::Str(String::from("b"))])]));
+    }
+
+    #[test]
+    fn test_walk_attr_target_citation() {
+        let attr = |id: &str| (String::from(id), vec![], vec![]);
+        let cite = |id: &str| Citation { citation_id: String::from(id), citation_prefix: vec![],
+                                         citation_suffix: vec![], citation_mode: CitationMode::NormalCitation,
+                                         citation_note_num: 0, citation_hash: 0 };
+        let doc = || Pandoc(Meta { un_meta: Default::default() },
+                            vec![Block::Div(attr("d"), vec![
+                                Block::Para(vec![Inline::Link(attr(""), vec![],
+                                                              (String::from("a.md"), String::new())),
+                                                 Inline::Cite(vec![cite("doe")], vec![])])
+                            ])],
+                            None);
+        let walked = doc().walk(&(|(id, mut classes, kvs): Attr| {
+            if !id.is_empty() {
+                classes.push(String::from("anchor"));
+            }
+            (id, classes, kvs)
+        })).walk(&(|(url, title): Target| (url.replace(".md", ".html"), title)))
+           .walk(&(|mut c: Citation| {
+               c.citation_id = c.citation_id.to_uppercase();
+               c
+           }));
+        let expected = Pandoc(Meta { un_meta: Default::default() },
+                              vec![Block::Div((String::from("d"), vec![String::from("anchor")], vec![]), vec![
+                                  Block::Para(vec![Inline::Link(attr(""), vec![],
+                                                                (String::from("a.html"), String::new())),
+                                                   Inline::Cite(vec![cite("DOE")], vec![])])
+                              ])],
+                              None);
+        assert_eq!(walked, expected);
+    }
+
+    #[test]
+    fn test_walk_meta_value_and_format() {
+        let mut un_meta = ::std::collections::BTreeMap::new();
+        un_meta.insert(String::from("draft"), MetaValue::MetaList(vec![MetaValue::MetaBool(true)]));
+        let meta = Meta { un_meta: un_meta }.walk(&(|v| match v {
+            MetaValue::MetaBool(b) => MetaValue::MetaBool(!b),
+            e => e
+        }));
+        assert_eq!(meta.un_meta["draft"], MetaValue::MetaList(vec![MetaValue::MetaBool(false)]));
+        let raw = Block::RawBlock(Format(String::from("tex")), String::new())
+            .walk(&(|Format(f)| Format(if f == "tex" { String::from("latex") } else { f })));
+        assert_eq!(raw, Block::RawBlock(Format(String::from("latex")), String::new()));
+    }
 }